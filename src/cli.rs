@@ -0,0 +1,445 @@
+use std::{error::Error, fmt, path::PathBuf, time::Duration};
+
+use crate::{
+    cursor::CursorKind,
+    format::{cur, Format},
+    image::Hotspot,
+    pool::Pool,
+    rotation::{self, Interval, Playlist},
+    scheme::CursorScheme,
+    transform::{Pipeline, STANDARD_SIZES},
+};
+
+pub const USAGE: &str = "\
+Usage: justaprankbro [restore] [options]
+       justaprankbro convert <png>... --output <path> [--hotspot <x>,<y>] [--format <format>]
+                             [--sizes <sizes>] [--bit-depth <bits>]
+       justaprankbro import <gif|png> --output <path> [--hotspot <x>,<y>] [--format <format>]
+                            [--sizes <sizes>]
+       justaprankbro generate <design> --output <path> [--format <format>] [--sizes <sizes>]
+       justaprankbro hotspot <cursor|png>... [--kind <kind>] [--output <path>]
+       justaprankbro preview <cursor>... [--output <path>]
+       justaprankbro validate <cursor>... [--json]
+
+Replaces the system cursor until the unlock sequence is typed or the --max-duration is
+up. With a --playlist, or a --pool and an --interval, the cursors change every --interval
+until then.
+
+Commands:
+    restore               Put back cursors left replaced by a run that was killed
+    convert               Turn PNGs, one per size, into a cursor file. The hotspot is in pixels
+                          of the first PNG. The format is cur, ani or xcursor, guessed from the
+                          output file name if not given. With --sizes, each size is
+                          resampled from the nearest larger PNG instead.
+    import                Turn an animated GIF or PNG into an ani or Xcursor file, 32 pixels
+                          square unless --sizes says otherwise. The hotspot is in pixels of
+                          the animation.
+    generate              Draw a cursor from a design, a shape followed by settings such as
+                          \"arrow size=64 fill=yellow outline=black text=LOL\". Shapes are
+                          arrow, circle, square, diamond, cross, star, heart and smiley, and
+                          settings size, fill, outline, outline-width, hotspot, text and
+                          text-colour.
+    hotspot               Suggest a hotspot for each image of cursor files or PNGs, found the
+                          way that suits the --kind of cursor: the top left pixel of arrows,
+                          the tip of pointers like Hand and Up, and the middle of the rest.
+                          With --output, also write the cursor with the suggested hotspot.
+    preview               Show every size and frame of cursor files in the terminal, with the
+                          hotspot in magenta. With --output, also write a PNG contact sheet,
+                          or a sheet per cursor into the --output directory for several.
+    validate              Check cursor files for problems, each reported with the byte offset
+                          it was found at. Exits with 1 if any file cannot be used.
+
+Options:
+    --cursor [<kind>=]<path>
+                          Cursor file to use for a kind of cursor, the normal pointer if no
+                          kind is given. May be repeated. A path of `system` stands for the
+                          cursor already in use, and `generate:<design>` draws one.
+    --theme <dir>         Directory with a cursor file for each kind, named like Normal.cur
+                          or IBeam.ani, and optionally a theme.conf with overrides. May also
+                          be the .inf file of a Windows cursor scheme.
+    --transform <effects> Alter the cursors with a comma separated list of flip-h, flip-v,
+                          rotate=<90|180|270>, invert, hue=<degrees>, scale=<factor> and
+                          opacity=<0 to 1>
+    --playlist [<kind>=]<path>
+                          Cursor to add to the ones a kind of cursor cycles through, the
+                          normal pointer if no kind is given. May be repeated, and takes the
+                          same paths as --cursor.
+    --pool [<kind>=]<path>[*<weight>]
+                          Cursor or directory of cursors to pick from at random for a kind of
+                          cursor, the normal pointer if no kind is given, or every kind on
+                          its own with `all=`. May be repeated. Cursors of weight 2 are picked
+                          twice as often as those of the default weight 1. Picked once, or at
+                          every change with an --interval.
+    --interval <interval> How long each cursor of the playlist or pool is shown: a duration
+                          like 30s, a range like 10s-2m to pick from at random, or a comma
+                          separated schedule like 5s,1m,30s. Durations are in ms, s, m or h.
+                          1m if not given for a playlist.
+    --seed <number>       Seed for the random picks, to repeat a run that printed it. Runs
+                          also keep their seed in the log next to the restoration journal
+    --delay <duration>    Wait this long before replacing the cursors, like 5m
+    --max-duration <duration>
+                          Put the cursors back and exit once they have been replaced for
+                          this long, like 45m, even if the unlock sequence is never typed
+    --sequence <keys>     Unlock sequence, e.g. \"prank{Space}bro\"
+    --config <path>       Config file to read instead of justaprankbro.conf
+    --dry-run             Print what would happen instead of touching the system, without a
+                          window, taking the unlock sequence as lines typed on stdin
+    -o, --output <path>   File to write when converting, importing, generating, suggesting a
+                          hotspot or previewing
+    --hotspot <x>,<y>     Hotspot of a converted or imported cursor, 0,0 if not given
+    --format <format>     Format of a converted, imported or generated cursor
+    --sizes <sizes>       Sizes for a converted, imported or generated cursor to have, as a
+                          comma separated list of pixels, or `standard` for 32,48,64,96,128
+    --kind <kind>         Kind of cursor to suggest a hotspot for, Normal if not given
+    --bit-depth <bits>    Bits per pixel of a converted cur or ani file: 32 with an alpha
+                          channel, the default, or 1, 4 or 8 with a palette
+    --json                Print validation results as JSON
+    -h, --help            Show this message
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Run,
+    Restore,
+    Convert,
+    Import,
+    Generate,
+    Hotspot,
+    Preview,
+    Validate,
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub dry_run: bool,
+    pub config: Option<PathBuf>,
+    pub sequence: Option<String>,
+    pub theme: Option<PathBuf>,
+    pub scheme: CursorScheme,
+    pub playlist: Playlist,
+    pub pool: Pool,
+    pub interval: Option<Interval>,
+    pub seed: Option<u64>,
+    pub delay: Option<Duration>,
+    pub max_duration: Option<Duration>,
+    /// Images to convert or import, or cursors to preview, validate or suggest a hotspot for
+    pub inputs: Vec<PathBuf>,
+    /// Words of the design to generate a cursor from
+    pub design: Vec<String>,
+    pub output: Option<PathBuf>,
+    pub hotspot: Option<Hotspot>,
+    pub format: Option<Format>,
+    /// Sizes to resample converted images to, or empty to keep them as they are
+    pub sizes: Vec<u32>,
+    pub bit_depth: Option<u16>,
+    /// Kind of cursor to suggest a hotspot for
+    pub kind: Option<CursorKind>,
+    pub json: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            command: Command::Run,
+            dry_run: false,
+            config: None,
+            sequence: None,
+            theme: None,
+            scheme: CursorScheme::new(),
+            playlist: Playlist::new(),
+            pool: Pool::new(),
+            interval: None,
+            seed: None,
+            delay: None,
+            max_duration: None,
+            inputs: Vec::new(),
+            design: Vec::new(),
+            output: None,
+            hotspot: None,
+            format: None,
+            sizes: Vec::new(),
+            bit_depth: None,
+            kind: None,
+            json: false,
+        }
+    }
+}
+
+/// Parses the arguments following the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, UsageError> {
+    let mut args = args.into_iter();
+    let mut parsed = Args::default();
+    let mut first_word = true;
+
+    while let Some(arg) = args.next() {
+        // Options also accept `--name=value`.
+        let (name, inline_value) = match arg.find('=') {
+            Some(eq) if arg.starts_with("--") => (&arg[..eq], Some(arg[eq + 1..].to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        // Only the first word that isn't an option names the command, so the ones after it
+        // are inputs even when they are called `convert` or `restore`.
+        let is_command = first_word && !name.starts_with('-');
+        first_word &= name.starts_with('-');
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| UsageError(format!("{} needs a value", name)))
+        };
+
+        match name {
+            "--restore" => parsed.command = Command::Restore,
+            "restore" if is_command => parsed.command = Command::Restore,
+            "convert" if is_command => parsed.command = Command::Convert,
+            "import" if is_command => parsed.command = Command::Import,
+            "generate" if is_command => parsed.command = Command::Generate,
+            "hotspot" if is_command => parsed.command = Command::Hotspot,
+            "preview" if is_command => parsed.command = Command::Preview,
+            "validate" if is_command => parsed.command = Command::Validate,
+            "-h" | "--help" => parsed.command = Command::Help,
+            "--dry-run" => parsed.dry_run = true,
+            "--json" => parsed.json = true,
+            "--config" => parsed.config = Some(PathBuf::from(value()?)),
+            "--sequence" => parsed.sequence = Some(value()?),
+            "--cursor" => parsed
+                .scheme
+                .set_from_str(&value()?)
+                .map_err(|err| UsageError(err.to_string()))?,
+            "--playlist" => parsed
+                .playlist
+                .push_from_str(&value()?)
+                .map_err(|err| UsageError(err.to_string()))?,
+            "--pool" => parsed
+                .pool
+                .push_from_str(&value()?)
+                .map_err(|err| UsageError(err.to_string()))?,
+            "--seed" => {
+                let value = value()?;
+                match value.parse() {
+                    Ok(seed) => parsed.seed = Some(seed),
+                    Err(_) => {
+                        return Err(UsageError(format!(
+                            "invalid seed `{}`, expected a whole number",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--delay" => {
+                parsed.delay = Some(
+                    rotation::parse_duration(&value()?)
+                        .map_err(|err| UsageError(err.to_string()))?,
+                )
+            }
+            "--max-duration" => {
+                parsed.max_duration = Some(
+                    rotation::parse_duration(&value()?)
+                        .map_err(|err| UsageError(err.to_string()))?,
+                )
+            }
+            "--interval" => {
+                parsed.interval = Some(
+                    value()?
+                        .parse::<Interval>()
+                        .map_err(|err| UsageError(err.to_string()))?,
+                )
+            }
+            "--theme" => parsed.theme = Some(PathBuf::from(value()?)),
+            "--transform" => parsed.scheme.set_transforms(
+                value()?
+                    .parse::<Pipeline>()
+                    .map_err(|err| UsageError(err.to_string()))?,
+            ),
+            "-o" | "--output" => parsed.output = Some(PathBuf::from(value()?)),
+            "--hotspot" => {
+                parsed.hotspot = Some(
+                    value()?
+                        .parse::<Hotspot>()
+                        .map_err(|err| UsageError(err.to_string()))?,
+                )
+            }
+            "--format" => {
+                parsed.format = Some(
+                    value()?
+                        .parse::<Format>()
+                        .map_err(|err| UsageError(err.to_string()))?,
+                )
+            }
+            "--sizes" => parsed.sizes = parse_sizes(&value()?)?,
+            "--kind" => {
+                parsed.kind = Some(
+                    value()?
+                        .parse::<CursorKind>()
+                        .map_err(|err| UsageError(err.to_string()))?,
+                )
+            }
+            "--bit-depth" => {
+                let value = value()?;
+                match value.parse() {
+                    Ok(bit_count) if cur::BIT_COUNTS.contains(&bit_count) => {
+                        parsed.bit_depth = Some(bit_count)
+                    }
+                    _ => {
+                        return Err(UsageError(format!(
+                            "invalid bit depth `{}`, expected 1, 4, 8 or 32",
+                            value
+                        )))
+                    }
+                }
+            }
+            _ if parsed.command == Command::Generate && !name.starts_with('-') => {
+                parsed.design.push(arg)
+            }
+            _ if matches!(
+                parsed.command,
+                Command::Convert
+                    | Command::Import
+                    | Command::Hotspot
+                    | Command::Preview
+                    | Command::Validate
+            ) && !name.starts_with('-') =>
+            {
+                parsed.inputs.push(PathBuf::from(arg))
+            }
+            _ => return Err(UsageError(format!("unexpected argument `{}`", arg))),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_sizes(value: &str) -> Result<Vec<u32>, UsageError> {
+    if value.eq_ignore_ascii_case("standard") {
+        return Ok(STANDARD_SIZES.to_vec());
+    }
+    value
+        .split(',')
+        .map(|size| match size.trim().parse() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(UsageError(format!(
+                "invalid size `{}`, expected a number of pixels",
+                size
+            ))),
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for UsageError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Args, UsageError> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    fn inputs(args: &Args) -> Vec<&str> {
+        args.inputs
+            .iter()
+            .map(|input| input.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parses_commands_and_their_inputs() {
+        assert_eq!(parse_line("").unwrap(), Args::default());
+        let args = parse_line("validate a.cur b.ani --json").unwrap();
+        assert_eq!(args.command, Command::Validate);
+        assert_eq!(inputs(&args), ["a.cur", "b.ani"]);
+        assert!(args.json);
+
+        let args = parse_line("--format ani import wiggle.gif -o wiggle.ani").unwrap();
+        assert_eq!(args.command, Command::Import);
+        assert_eq!(args.format, Some(Format::Ani));
+        assert_eq!(inputs(&args), ["wiggle.gif"]);
+        assert_eq!(args.output, Some(PathBuf::from("wiggle.ani")));
+
+        let args = parse_line("generate arrow size=64 text=LOL").unwrap();
+        assert_eq!(args.command, Command::Generate);
+        assert_eq!(args.design, ["arrow", "size=64", "text=LOL"]);
+
+        assert_eq!(
+            parse_line("--dry-run restore").unwrap().command,
+            Command::Restore
+        );
+        assert_eq!(
+            parse_line("--dry-run --restore").unwrap().command,
+            Command::Restore
+        );
+        assert_eq!(parse_line("preview -h").unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn only_the_first_word_names_the_command() {
+        let args = parse_line("validate convert restore").unwrap();
+        assert_eq!(args.command, Command::Validate);
+        assert_eq!(inputs(&args), ["convert", "restore"]);
+
+        let args = parse_line("hotspot --kind hand preview").unwrap();
+        assert_eq!(args.command, Command::Hotspot);
+        assert_eq!(args.kind, Some(CursorKind::Hand));
+        assert_eq!(inputs(&args), ["preview"]);
+
+        let args = parse_line("generate cross text=import import").unwrap();
+        assert_eq!(args.design, ["cross", "text=import", "import"]);
+
+        assert_eq!(
+            parse_line("restore convert").unwrap_err(),
+            UsageError("unexpected argument `convert`".to_owned())
+        );
+        assert_eq!(
+            parse_line("--dry-run frob").unwrap_err(),
+            UsageError("unexpected argument `frob`".to_owned())
+        );
+    }
+
+    #[test]
+    fn options_take_their_value_after_them_or_an_equals_sign() {
+        let args = parse_line("convert a.png --output=a.cur --hotspot 3,4").unwrap();
+        assert_eq!(args.output, Some(PathBuf::from("a.cur")));
+        assert_eq!(args.hotspot, Some(Hotspot::new(3, 4)));
+        let args = parse_line("--seed=42 --delay 5m --sequence prank{Space}bro").unwrap();
+        assert_eq!(args.seed, Some(42));
+        assert_eq!(args.delay, Some(Duration::from_secs(300)));
+        assert_eq!(args.sequence.as_deref(), Some("prank{Space}bro"));
+        assert_eq!(
+            parse_line("convert a.png --output").unwrap_err(),
+            UsageError("--output needs a value".to_owned())
+        );
+    }
+
+    #[test]
+    fn parses_sizes_and_bit_depths() {
+        let args = parse_line("convert a.png --sizes 16,32 --bit-depth 8").unwrap();
+        assert_eq!(args.sizes, [16, 32]);
+        assert_eq!(args.bit_depth, Some(8));
+        let args = parse_line("import a.gif --sizes standard").unwrap();
+        assert_eq!(args.sizes, STANDARD_SIZES);
+
+        assert_eq!(
+            parse_line("convert a.png --sizes 16,0").unwrap_err(),
+            UsageError("invalid size `0`, expected a number of pixels".to_owned())
+        );
+        assert_eq!(
+            parse_line("convert a.png --bit-depth 2").unwrap_err(),
+            UsageError("invalid bit depth `2`, expected 1, 4, 8 or 32".to_owned())
+        );
+        assert_eq!(
+            parse_line("--seed lucky").unwrap_err(),
+            UsageError("invalid seed `lucky`, expected a whole number".to_owned())
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Settings read from a `key = value` file, so a deployed prank needs no command line.
+//!
+//! ```text
+//! # Lines starting with a hash are comments
+//! sequence = "prank{Space}bro"
+//! cursor = normal.cur
+//! cursor.IBeam = beam.cur
+//! cursor.Hand = system
+//! cursor.Wait = generate:smiley fill=yellow size=48
+//! transform = flip-h,hue=30
+//! theme = themes/upside-down
+//! playlist = clown.cur
+//! playlist = generate:heart fill=red
+//! playlist.Hand = glove.ani
+//! pool.all = clowns/
+//! pool.Hand = glove.ani*3
+//! interval = 30s-2m
+//! seed = 1234
+//! delay = 5m
+//! max-duration = 45m
+//! ```
+//!
+//! Relative paths are relative to the config file, `system` stands for the cursor already in
+//! use and `generate:` starts a design to draw, as described in the `generate` module. Cursors
+//! set here take precedence over the theme's. Each `playlist` line adds a cursor to cycle
+//! through, changing every `interval`, as described in the `rotation` module, and each `pool`
+//! line a cursor to pick from at random, as described in the `pool` module.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    cursor::CursorKind,
+    pool::{self, Pool},
+    rotation::{self, Interval, Playlist},
+    scheme::{CursorScheme, SchemeCursor},
+    transform::Pipeline,
+};
+
+pub const FILE_NAME: &str = "justaprankbro.conf";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub sequence: Option<String>,
+    /// Directory of a cursor theme to load
+    pub theme: Option<PathBuf>,
+    pub scheme: CursorScheme,
+    pub playlist: Playlist,
+    pub pool: Pool,
+    pub interval: Option<Interval>,
+    pub seed: Option<u64>,
+    pub delay: Option<Duration>,
+    pub max_duration: Option<Duration>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&text, dir).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        })
+    }
+
+    /// Loads the config file next to the executable, if there is one.
+    pub fn load_default() -> io::Result<Self> {
+        match default_path() {
+            Some(path) if path.exists() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Parses a config file, resolving relative paths against `dir`.
+    pub fn parse(text: &str, dir: &Path) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for setting in settings(text) {
+            let (line, key, value) = setting?;
+            match key {
+                "sequence" => config.sequence = Some(value.to_owned()),
+                "theme" => config.theme = Some(dir.join(value)),
+                "cursor" => config.scheme.set(
+                    CursorKind::Normal,
+                    SchemeCursor::from_str_in(value, dir)
+                        .map_err(|err| format!("line {}: {}", line, err))?,
+                ),
+                _ if key.starts_with("cursor.") => {
+                    let kind = key["cursor.".len()..]
+                        .parse()
+                        .map_err(|err| format!("line {}: {}", line, err))?;
+                    config.scheme.set(
+                        kind,
+                        SchemeCursor::from_str_in(value, dir)
+                            .map_err(|err| format!("line {}: {}", line, err))?,
+                    );
+                }
+                "playlist" => config.playlist.push(
+                    CursorKind::Normal,
+                    SchemeCursor::from_str_in(value, dir)
+                        .map_err(|err| format!("line {}: {}", line, err))?,
+                ),
+                _ if key.starts_with("playlist.") => {
+                    let kind = key["playlist.".len()..]
+                        .parse()
+                        .map_err(|err| format!("line {}: {}", line, err))?;
+                    config.playlist.push(
+                        kind,
+                        SchemeCursor::from_str_in(value, dir)
+                            .map_err(|err| format!("line {}: {}", line, err))?,
+                    );
+                }
+                "pool" => config
+                    .pool
+                    .push_value(&[CursorKind::Normal], value, dir)
+                    .map_err(|err| format!("line {}: {}", line, err))?,
+                _ if key.starts_with("pool.") => {
+                    let kinds = pool::parse_kinds(&key["pool.".len()..])
+                        .map_err(|err| format!("line {}: {}", line, err))?;
+                    config
+                        .pool
+                        .push_value(&kinds, value, dir)
+                        .map_err(|err| format!("line {}: {}", line, err))?;
+                }
+                "seed" => {
+                    config.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("line {}: invalid seed `{}`", line, value))?,
+                    )
+                }
+                "delay" => {
+                    config.delay = Some(
+                        rotation::parse_duration(value)
+                            .map_err(|err| format!("line {}: {}", line, err))?,
+                    )
+                }
+                "max-duration" => {
+                    config.max_duration = Some(
+                        rotation::parse_duration(value)
+                            .map_err(|err| format!("line {}: {}", line, err))?,
+                    )
+                }
+                "interval" => {
+                    config.interval = Some(
+                        value
+                            .parse()
+                            .map_err(|err| format!("line {}: {}", line, err))?,
+                    )
+                }
+                "transform" => config.scheme.set_transforms(
+                    value
+                        .parse::<Pipeline>()
+                        .map_err(|err| format!("line {}: {}", line, err))?,
+                ),
+                _ => return Err(format!("line {}: unknown setting `{}`", line, key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// The `key = value` settings in `text` with their line numbers, skipping blank lines and
+/// comments.
+pub fn settings(text: &str) -> impl Iterator<Item = Result<(usize, &str, &str), String>> {
+    text.lines().enumerate().filter_map(|(idx, line)| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        Some(match line.find('=') {
+            Some(eq) => Ok((idx + 1, line[..eq].trim(), unquote(line[eq + 1..].trim()))),
+            None => Err(format!("line {}: expected `key = value`", idx + 1)),
+        })
+    })
+}
+
+fn default_path() -> Option<PathBuf> {
+    env::current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name(FILE_NAME))
+}
+
+/// Strips one pair of surrounding double quotes, which lets values start or end with spaces.
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_sequence() {
+        let config = Config::parse(
+            "# A comment\n\n  sequence = \" prank{Space}bro \"  \n",
+            Path::new("dir"),
+        )
+        .unwrap();
+        assert_eq!(config.sequence.as_deref(), Some(" prank{Space}bro "));
+
+        let config = Config::parse("sequence=bro\nsequence = again", Path::new("")).unwrap();
+        assert_eq!(config.sequence.as_deref(), Some("again"));
+        assert_eq!(Config::parse("", Path::new("")), Ok(Config::default()));
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_setting() {
+        assert_eq!(
+            Config::parse("sequence = a\nsequnce = b", Path::new("")),
+            Err("line 2: unknown setting `sequnce`".to_owned())
+        );
+        assert_eq!(
+            Config::parse("\n# sequence\njustaprankbro", Path::new("")),
+            Err("line 3: expected `key = value`".to_owned())
+        );
+    }
+
+    #[test]
+    fn unquotes_one_pair_of_quotes() {
+        assert_eq!(unquote("\"a\""), "a");
+        assert_eq!(unquote("\"\"a\"\""), "\"a\"");
+        assert_eq!(unquote("\""), "\"");
+        assert_eq!(unquote("a\""), "a\"");
+    }
+}
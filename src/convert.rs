@@ -0,0 +1,306 @@
+//! Turning PNG drawings into cursor files.
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    format::{self, cur, png, Format, FormatError},
+    image::{AnimatedCursor, AnimationFrame, CursorImage, Hotspot},
+    transform,
+};
+
+/// Writes the PNGs in `inputs`, each the same picture at a different size, to `output` as a
+/// single cursor. The hotspot is in pixels of the first PNG and is scaled to fit the others.
+///
+/// If `sizes` isn't empty, the cursor gets an image of each of those sizes instead, resampled
+/// from the PNGs, so a single large drawing is enough for every display scaling. A `bit_count`
+/// under 32 writes `.cur` and `.ani` images with a palette, which needs every pixel to be
+/// either opaque or fully transparent.
+pub fn convert(
+    inputs: &[PathBuf],
+    hotspot: Hotspot,
+    format: Format,
+    sizes: &[u32],
+    bit_count: Option<u16>,
+    output: &Path,
+) -> Result<(), ConvertError> {
+    if let Some(&size) = sizes.iter().find(|&&size| size > format.max_size()) {
+        return Err(ConvertError::SizeTooLarge { size, format });
+    }
+
+    let mut entries = Vec::with_capacity(inputs.len());
+    for path in inputs {
+        let bytes = fs::read(path).map_err(|err| ConvertError::Read(path.clone(), err))?;
+        let entry = png::decode(&bytes).map_err(|err| ConvertError::Decode(path.clone(), err))?;
+        let too_large = entry.width > format.max_size() || entry.height > format.max_size();
+        if too_large && sizes.is_empty() {
+            return Err(ConvertError::TooLarge {
+                path: path.clone(),
+                format,
+            });
+        }
+        entries.push(entry);
+    }
+
+    let (width, height) = match entries.first() {
+        Some(first) => (first.width, first.height),
+        None => return Err(ConvertError::NoInputs),
+    };
+    if u32::from(hotspot.x) >= width || u32::from(hotspot.y) >= height {
+        return Err(ConvertError::HotspotOutside {
+            hotspot,
+            width,
+            height,
+        });
+    }
+    let mut image = CursorImage::new(entries);
+    image.set_hotspot(hotspot, width, height);
+    if !sizes.is_empty() {
+        image = transform::multi_size(&image, sizes);
+    }
+    if let Some(bit_count) = bit_count {
+        for entry in &mut image.entries {
+            if bit_count < 32 && cur::palette(entry, bit_count).is_none() {
+                return Err(ConvertError::TooManyColours(bit_count));
+            }
+            entry.bit_count = bit_count;
+        }
+    }
+
+    let cursor = AnimatedCursor::new(vec![AnimationFrame::new(image, Duration::default())]);
+    fs::write(output, format::encode(&cursor, format))
+        .map_err(|err| ConvertError::Write(output.to_owned(), err))
+}
+
+#[derive(Debug)]
+pub enum ConvertError {
+    NoInputs,
+    Read(PathBuf, io::Error),
+    Decode(PathBuf, FormatError),
+    TooLarge {
+        path: PathBuf,
+        format: Format,
+    },
+    /// A size asked for with `--sizes` is more than the format can hold
+    SizeTooLarge {
+        size: u32,
+        format: Format,
+    },
+    HotspotOutside {
+        hotspot: Hotspot,
+        width: u32,
+        height: u32,
+    },
+    /// The images don't fit a palette for this many bits per pixel
+    TooManyColours(u16),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoInputs => f.write_str("no images to convert"),
+            Self::Read(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Decode(path, err) => write!(f, "{} is not a usable PNG: {}", path.display(), err),
+            Self::TooLarge { path, format } => write!(
+                f,
+                "{} is too large for a {} file, which holds at most {}x{} pixels",
+                path.display(),
+                format.as_str(),
+                format.max_size(),
+                format.max_size()
+            ),
+            Self::SizeTooLarge { size, format } => write!(
+                f,
+                "a {} file holds at most {}x{} pixels, too few for size {}",
+                format.as_str(),
+                format.max_size(),
+                format.max_size(),
+                size
+            ),
+            Self::HotspotOutside {
+                hotspot,
+                width,
+                height,
+            } => write!(
+                f,
+                "hotspot {},{} is outside the {}x{} image",
+                hotspot.x, hotspot.y, width, height
+            ),
+            Self::TooManyColours(bit_count) => write!(
+                f,
+                "a {}-bit cursor has at most {} colours and no partly transparent pixels, \
+                 which the images need",
+                bit_count,
+                1 << bit_count
+            ),
+            Self::Write(path, err) => write!(f, "could not write {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for ConvertError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(_, err) | Self::Write(_, err) => Some(err),
+            Self::Decode(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Each conversion is compared byte for byte with a file in `fixtures/golden`. After a change
+/// to an encoder that is meant to alter its output, check the new files by hand and bless them
+/// with `UPDATE_GOLDEN=1 cargo test`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+    fn input(name: &str) -> PathBuf {
+        Path::new(FIXTURES).join("convert").join(name)
+    }
+
+    fn arrows() -> Vec<PathBuf> {
+        vec![input("arrow-32.png"), input("arrow-16.png")]
+    }
+
+    fn check_golden(name: &str, bytes: &[u8]) {
+        let path = Path::new(FIXTURES).join("golden").join(name);
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, bytes).unwrap();
+            return;
+        }
+        let golden = fs::read(&path)
+            .unwrap_or_else(|err| panic!("could not read {}: {}", path.display(), err));
+        assert!(
+            golden == bytes,
+            "{} no longer matches, see the tests in convert.rs",
+            path.display()
+        );
+    }
+
+    /// Converts into the temporary directory and checks the result against its golden file,
+    /// returning it decoded.
+    fn convert_golden(
+        name: &str,
+        inputs: &[PathBuf],
+        hotspot: Hotspot,
+        format: Format,
+        sizes: &[u32],
+        bit_count: Option<u16>,
+    ) -> AnimatedCursor {
+        let output = env::temp_dir().join(format!(
+            "justaprankbro-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        convert(inputs, hotspot, format, sizes, bit_count, &output).unwrap();
+        let bytes = fs::read(&output).unwrap();
+        let _ = fs::remove_file(&output);
+        check_golden(name, &bytes);
+        format::decode(&bytes).unwrap()
+    }
+
+    fn png(name: &str) -> crate::image::CursorEntry {
+        png::decode(&fs::read(input(name)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_format_matches_its_golden_file() {
+        let hotspot = Hotspot::new(2, 2);
+        for &(name, format) in &[
+            ("arrow.cur", Format::Cur),
+            ("arrow.ani", Format::Ani),
+            ("arrow", Format::Xcursor),
+        ] {
+            let cursor = convert_golden(name, &arrows(), hotspot, format, &[], None);
+            let mut sizes: Vec<(u32, u32, Hotspot)> = cursor.frames[0]
+                .image
+                .entries
+                .iter()
+                .map(|entry| (entry.width, entry.height, entry.hotspot))
+                .collect();
+            sizes.sort_by_key(|&(width, _, _)| width);
+            assert_eq!(
+                sizes,
+                vec![(16, 16, Hotspot::new(1, 1)), (32, 32, hotspot)],
+                "{}",
+                name
+            );
+        }
+
+        // .cur keeps the drawing exactly, partly transparent shadow and all.
+        let cursor = convert_golden("arrow.cur", &arrows(), hotspot, Format::Cur, &[], None);
+        let large = cursor.frames[0].image.nearest(32).unwrap();
+        assert_eq!(large.pixels, png("arrow-32.png").pixels);
+    }
+
+    #[test]
+    fn resampled_sizes_match_their_golden_file() {
+        let inputs = [input("arrow-32.png")];
+        let cursor = convert_golden(
+            "arrow-sizes.cur",
+            &inputs,
+            Hotspot::new(2, 2),
+            Format::Cur,
+            &[16, 24, 48],
+            None,
+        );
+        let mut sizes: Vec<u32> = cursor.frames[0]
+            .image
+            .entries
+            .iter()
+            .map(|entry| entry.width)
+            .collect();
+        sizes.sort_unstable();
+        assert_eq!(sizes, vec![16, 24, 48]);
+    }
+
+    #[test]
+    fn paletted_cursors_match_their_golden_files() {
+        let inputs = [input("flat-16.png")];
+        for &(name, bit_count) in &[("flat-1bit.cur", 1), ("flat-4bit.cur", 4)] {
+            let cursor = convert_golden(
+                name,
+                &inputs,
+                Hotspot::default(),
+                Format::Cur,
+                &[],
+                Some(bit_count),
+            );
+            let entry = &cursor.frames[0].image.entries[0];
+            assert_eq!(entry.bit_count, bit_count);
+            assert_eq!(entry.pixels, png("flat-16.png").pixels, "{}", name);
+        }
+    }
+
+    #[test]
+    fn png_matches_its_golden_file() {
+        let arrow = png("arrow-32.png");
+        let bytes = png::encode(&arrow);
+        check_golden("arrow-32.png", &bytes);
+        assert_eq!(png::decode(&bytes).unwrap().pixels, arrow.pixels);
+    }
+
+    #[test]
+    fn partly_transparent_pixels_need_32_bits() {
+        let output = env::temp_dir().join("justaprankbro-test-never-written.cur");
+        let err = convert(
+            &arrows(),
+            Hotspot::default(),
+            Format::Cur,
+            &[],
+            Some(8),
+            &output,
+        );
+        assert!(matches!(err, Err(ConvertError::TooManyColours(8))));
+        assert!(!output.exists());
+    }
+}
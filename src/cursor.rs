@@ -1,108 +1,359 @@
-use std::{io, ops::Drop, path::Path, ptr};
-
-use winapi::{
-    shared::{ntdef::HANDLE, windef::HICON, winerror::ERROR_FILE_NOT_FOUND},
-    um::{
-        errhandlingapi::GetLastError,
-        winuser::{
-            CopyImage, LoadImageW, SetSystemCursor, IMAGE_CURSOR, LR_LOADFROMFILE, LR_SHARED,
-            MAKEINTRESOURCEW,
-        },
-    },
+mod error;
+mod mock;
+#[cfg(windows)]
+mod windows;
+#[cfg(all(unix, not(target_os = "macos")))]
+mod x11;
+
+use std::{
+    error::Error,
+    fmt, fs,
+    ops::Drop,
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+};
+
+use crate::{
+    format,
+    image::{AnimatedCursor, Hotspot},
+    journal::Journal,
+    scheme::{CursorScheme, SchemeCursor},
+    transform::Pipeline,
 };
 
+pub use self::error::CursorError;
+#[cfg(test)]
+pub use self::mock::Call as MockCall;
+pub use self::mock::MockBackend;
+#[cfg(windows)]
+pub use self::windows::WindowsBackend as SystemBackend;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub use self::x11::X11Backend as SystemBackend;
+
+#[cfg(windows)]
 use missing_from_winapi::{
     OCR_APPSTARTING, OCR_CROSS, OCR_HAND, OCR_IBEAM, OCR_NO, OCR_NORMAL, OCR_SIZEALL, OCR_SIZENESW,
     OCR_SIZENS, OCR_SIZENWSE, OCR_SIZEWE, OCR_UP, OCR_WAIT,
 };
 
+/// The cursor used for the normal pointer when no other is given, built into the executable so it
+/// works without any files next to it.
+pub const DEFAULT_CURSOR: &[u8] = include_bytes!("../normal.cur");
+
+/// The platform-specific part of loading and swapping out system cursors.
+pub trait CursorBackend {
+    type Handle: fmt::Debug;
+
+    fn load(&self, path: &Path) -> Result<Self::Handle, CursorError>;
+
+    /// Loads a cursor from the contents of a `.cur`, `.ani` or Xcursor file.
+    fn load_bytes(&self, bytes: &[u8]) -> Result<Self::Handle, CursorError>;
+
+    fn load_image(&self, image: &AnimatedCursor) -> Result<Self::Handle, CursorError>;
+
+    /// Reads back the images of a loaded cursor.
+    fn image(&self, cursor: &Self::Handle) -> Result<AnimatedCursor, CursorError>;
+
+    /// Loads a copy of the cursor currently used for `kind`, so it can be put back later.
+    fn load_system(&self, kind: CursorKind) -> Result<Self::Handle, CursorError>;
+
+    fn replace(&self, kind: CursorKind, cursor: Self::Handle) -> Result<(), CursorError>;
+
+    /// Puts back a cursor previously saved with `load_system`.
+    fn revert(&self, kind: CursorKind, original: Self::Handle) -> Result<(), CursorError>;
+
+    /// Describes where the cursor currently used for `kind` comes from, in a way that survives
+    /// this process dying.
+    fn system_source(&self, kind: CursorKind) -> Result<CursorSource, CursorError>;
+
+    /// Puts back a cursor described by `system_source`, possibly from another process.
+    fn restore(&self, kind: CursorKind, source: &CursorSource) -> Result<(), CursorError>;
+}
+
+/// Where a system cursor was loaded from, as recorded in the restoration journal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CursorSource {
+    /// The platform's own default for the cursor kind
+    Default,
+    File(PathBuf),
+    /// A cursor from an X11 cursor theme
+    Theme {
+        name: String,
+        size: u32,
+    },
+}
+
 #[derive(Debug)]
-pub struct ReplacedCursor {
-    cursor: Cursor,
+pub struct ReplacedCursor<B: CursorBackend> {
+    backend: Rc<B>,
+    journal: Journal,
+    original: Option<B::Handle>,
     kind: CursorKind,
 }
 
-impl ReplacedCursor {
-    pub fn revert(&self) {
-        unsafe { SetSystemCursor(self.cursor.handle as HICON, self.kind.as_id()) };
+impl<B: CursorBackend> ReplacedCursor<B> {
+    /// Shows `cursor` instead of the current replacement. The original and its journal record
+    /// stay as they were, so reverting still puts back the cursor from before the first
+    /// replacement. Does nothing once reverted.
+    pub fn swap(&self, cursor: Cursor<B>) -> Result<(), CursorError> {
+        if self.original.is_none() {
+            return Ok(());
+        }
+        self.backend
+            .replace(self.kind, cursor.handle)
+            .map_err(|err| CursorError::ReplaceFailed {
+                kind: self.kind,
+                source: Box::new(err),
+            })
+    }
+
+    /// Puts the original cursor back. Only the first call does anything.
+    pub fn revert(&mut self) -> Result<(), CursorError> {
+        match self.original.take() {
+            Some(original) => {
+                self.backend.revert(self.kind, original)?;
+                Ok(self.journal.remove(self.kind)?)
+            }
+            None => Ok(()),
+        }
     }
 }
 
-impl Drop for ReplacedCursor {
+impl<B: CursorBackend> Drop for ReplacedCursor<B> {
     fn drop(&mut self) {
-        self.revert();
+        let _ = self.revert();
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Cursor {
-    handle: HANDLE,
+/// Every cursor of a scheme, replaced together and reverted together.
+#[derive(Debug)]
+pub struct ReplacedScheme<B: CursorBackend> {
+    cursors: Vec<ReplacedCursor<B>>,
 }
 
-impl Cursor {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let path = path.as_ref();
-        let path = path.to_string_lossy();
+impl<B: CursorBackend> ReplacedScheme<B> {
+    /// Replaces every cursor in `scheme`, or just the normal pointer with `DEFAULT_CURSOR` if the
+    /// scheme is empty. If any of them fails, the ones already replaced are reverted before the
+    /// error is returned, so it is all or nothing.
+    pub fn replace(
+        backend: &Rc<B>,
+        scheme: &CursorScheme,
+        journal: &Journal,
+    ) -> Result<Self, CursorError> {
+        // Loading everything up front means a bad file fails before anything is touched.
+        let transforms = scheme.transforms();
+        let loaded = if scheme.is_empty() {
+            vec![(
+                CursorKind::Normal,
+                Cursor::from_scheme(backend, CursorKind::Normal, None, None, transforms)?,
+            )]
+        } else {
+            scheme
+                .iter()
+                .map(|(kind, cursor)| {
+                    let hotspot = scheme.hotspot(kind);
+                    Ok((
+                        kind,
+                        Cursor::from_scheme(backend, kind, Some(cursor), hotspot, transforms)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, CursorError>>()?
+        };
 
-        let utf16_path: Vec<u16> = path.encode_utf16().chain(0..=0).collect();
-        let handle = unsafe {
-            LoadImageW(
-                ptr::null_mut(),
-                utf16_path.as_ptr(),
-                IMAGE_CURSOR,
-                0,
-                0,
-                LR_SHARED | LR_LOADFROMFILE,
-            )
+        let mut replaced = Self {
+            cursors: Vec::with_capacity(loaded.len()),
         };
+        for (kind, cursor) in loaded {
+            match cursor.replace_system(kind, journal) {
+                Ok(cursor) => replaced.cursors.push(cursor),
+                Err(err) => {
+                    let _ = replaced.revert();
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(replaced)
+    }
 
-        if handle.is_null() {
-            let error_code = unsafe { GetLastError() };
+    /// Swaps in the cursors of `scheme` for the kinds this replaced, leaving any others alone.
+    /// Everything is loaded before anything is swapped, so a bad file leaves the cursors as
+    /// they were.
+    pub fn swap(&self, scheme: &CursorScheme) -> Result<(), CursorError> {
+        let transforms = scheme.transforms();
+        let loaded = scheme
+            .iter()
+            .filter_map(|(kind, cursor)| {
+                let replaced = self.cursors.iter().find(|replaced| replaced.kind == kind)?;
+                let hotspot = scheme.hotspot(kind);
+                Some(
+                    Cursor::from_scheme(&replaced.backend, kind, Some(cursor), hotspot, transforms)
+                        .map(|cursor| (replaced, cursor)),
+                )
+            })
+            .collect::<Result<Vec<_>, CursorError>>()?;
 
-            let err = io::Error::from_raw_os_error(error_code as i32);
-            if error_code == ERROR_FILE_NOT_FOUND {
-                Err(err)
-            } else {
-                panic!("Unexpected error while loading cursor from file: {}", err)
+        // Carries on past failures like `revert`, so one stuck kind doesn't hold up the rest.
+        let mut result = Ok(());
+        for (replaced, cursor) in loaded {
+            let swapped = replaced.swap(cursor);
+            if result.is_ok() {
+                result = swapped;
             }
-        } else {
-            Ok(Self { handle })
         }
+        result
     }
 
-    pub fn load_system(kind: CursorKind) -> Self {
-        let cursor = unsafe {
-            LoadImageW(
-                ptr::null_mut(),
-                MAKEINTRESOURCEW(kind.as_id() as u16),
-                IMAGE_CURSOR,
-                0,
-                0,
-                LR_SHARED,
-            )
-        };
-        if cursor.is_null() {
-            panic!("TODO: Handle errors")
+    /// Reverts every cursor, most recently replaced first. Carries on past failures and
+    /// returns the first one.
+    pub fn revert(&mut self) -> Result<(), CursorError> {
+        let mut result = Ok(());
+        for cursor in self.cursors.iter_mut().rev() {
+            let reverted = cursor.revert();
+            if result.is_ok() {
+                result = reverted;
+            }
         }
+        result
+    }
+}
+
+impl<B: CursorBackend> Drop for ReplacedScheme<B> {
+    fn drop(&mut self) {
+        let _ = self.revert();
+    }
+}
+
+#[derive(Debug)]
+pub struct Cursor<B: CursorBackend> {
+    backend: Rc<B>,
+    handle: B::Handle,
+}
+
+impl<B: CursorBackend> Cursor<B> {
+    pub fn from_file<P: AsRef<Path>>(backend: &Rc<B>, path: P) -> Result<Self, CursorError> {
+        let handle = backend.load(path.as_ref())?;
+        Ok(Self {
+            backend: Rc::clone(backend),
+            handle,
+        })
+    }
 
-        let handle = unsafe { CopyImage(cursor, IMAGE_CURSOR, 0, 0, 0) };
-        if handle.is_null() {
-            panic!("TODO: Handle errors")
+    pub fn from_bytes(backend: &Rc<B>, bytes: &[u8]) -> Result<Self, CursorError> {
+        let handle = backend.load_bytes(bytes)?;
+        Ok(Self {
+            backend: Rc::clone(backend),
+            handle,
+        })
+    }
+
+    pub fn from_image(backend: &Rc<B>, image: &AnimatedCursor) -> Result<Self, CursorError> {
+        let handle = backend.load_image(image)?;
+        Ok(Self {
+            backend: Rc::clone(backend),
+            handle,
+        })
+    }
+
+    pub fn load_system(backend: &Rc<B>, kind: CursorKind) -> Result<Self, CursorError> {
+        let handle = backend.load_system(kind)?;
+        Ok(Self {
+            backend: Rc::clone(backend),
+            handle,
+        })
+    }
+
+    /// Loads the cursor a scheme has for `kind`, or `DEFAULT_CURSOR` if it has none, moves its
+    /// hotspot if the scheme overrides it and applies `transforms` to it.
+    fn from_scheme(
+        backend: &Rc<B>,
+        kind: CursorKind,
+        cursor: Option<&SchemeCursor>,
+        hotspot: Option<Hotspot>,
+        transforms: &Pipeline,
+    ) -> Result<Self, CursorError> {
+        if hotspot.is_none() && transforms.is_empty() {
+            return match cursor {
+                None => Self::from_bytes(backend, DEFAULT_CURSOR),
+                Some(SchemeCursor::File(path)) => Self::from_file(backend, path),
+                Some(SchemeCursor::System) => Self::load_system(backend, kind),
+                Some(SchemeCursor::Generated(design)) => {
+                    Self::from_image(backend, &design.cursor())
+                }
+            };
         }
 
-        Self { handle }
+        // Files are decoded here rather than read back from the backend, which might only
+        // manage the first frame of an animation.
+        let mut image = match cursor {
+            None => decode(DEFAULT_CURSOR, None)?,
+            Some(SchemeCursor::File(path)) => {
+                let bytes =
+                    fs::read(path).map_err(|err| CursorError::from_read(path.clone(), err))?;
+                decode(&bytes, Some(path))?
+            }
+            Some(SchemeCursor::System) => Self::load_system(backend, kind)?.image()?,
+            Some(SchemeCursor::Generated(design)) => design.cursor(),
+        };
+        if let Some(hotspot) = hotspot {
+            for frame in &mut image.frames {
+                if let Some((width, height)) = frame
+                    .image
+                    .largest()
+                    .map(|entry| (entry.width, entry.height))
+                {
+                    frame.image.set_hotspot(hotspot, width, height);
+                }
+            }
+        }
+        Self::from_image(backend, &transforms.apply(&image))
+    }
+
+    pub fn image(&self) -> Result<AnimatedCursor, CursorError> {
+        self.backend.image(&self.handle)
     }
 
-    pub fn replace_system(self, kind: CursorKind) -> ReplacedCursor {
-        let cursor = Self::load_system(kind);
-        // TODO: Handle errors
-        unsafe { SetSystemCursor(self.handle as HICON, kind.as_id()) };
-        ReplacedCursor { cursor, kind }
+    /// Replaces the system cursor for `kind`, recording how to undo it in `journal` first. A
+    /// failure leaves the journal as it was.
+    pub fn replace_system(
+        self,
+        kind: CursorKind,
+        journal: &Journal,
+    ) -> Result<ReplacedCursor<B>, CursorError> {
+        let failed = |err: CursorError| CursorError::ReplaceFailed {
+            kind,
+            source: Box::new(err),
+        };
+
+        let original = self.backend.load_system(kind).map_err(failed)?;
+        let recorded = journal
+            .record(kind, &self.backend.system_source(kind).map_err(failed)?)
+            .map_err(|err| failed(err.into()))?;
+        if let Err(err) = self.backend.replace(kind, self.handle) {
+            if recorded {
+                let _ = journal.remove(kind);
+            }
+            return Err(failed(err));
+        }
+        Ok(ReplacedCursor {
+            backend: self.backend,
+            journal: journal.clone(),
+            original: Some(original),
+            kind,
+        })
     }
 }
 
+fn decode(bytes: &[u8], path: Option<&Path>) -> Result<AnimatedCursor, CursorError> {
+    format::decode(bytes).map_err(|err| CursorError::InvalidFormat {
+        path: path.map(Path::to_owned),
+        reason: err.to_string(),
+    })
+}
+
 #[allow(dead_code)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CursorKind {
     /// Standard arrow and small hourglass
     AppStarting,
@@ -133,6 +384,23 @@ pub enum CursorKind {
 }
 
 impl CursorKind {
+    pub const ALL: [CursorKind; 13] = [
+        Self::AppStarting,
+        Self::Normal,
+        Self::Crosshair,
+        Self::Hand,
+        Self::Ibeam,
+        Self::No,
+        Self::SizeAll,
+        Self::SizeNeSw,
+        Self::SizeNs,
+        Self::SizeNwSe,
+        Self::SizeWe,
+        Self::Up,
+        Self::Wait,
+    ];
+
+    #[cfg(windows)]
     pub fn as_id(self) -> u32 {
         match self {
             Self::AppStarting => OCR_APPSTARTING,
@@ -151,7 +419,6 @@ impl CursorKind {
         }
     }
 
-    #[allow(dead_code)]
     pub fn as_str(self) -> &'static str {
         match self {
             Self::AppStarting => "AppStarting",
@@ -169,8 +436,82 @@ impl CursorKind {
             Self::Wait => "Wait",
         }
     }
+
+    /// The value under `HKEY_CURRENT_USER\Control Panel\Cursors` that holds the file for this
+    /// cursor, which Windows cursor schemes also use to name it
+    pub fn registry_name(self) -> &'static str {
+        match self {
+            Self::AppStarting => "AppStarting",
+            Self::Normal => "Arrow",
+            Self::Crosshair => "Crosshair",
+            Self::Hand => "Hand",
+            Self::Ibeam => "IBeam",
+            Self::No => "No",
+            Self::SizeAll => "SizeAll",
+            Self::SizeNeSw => "SizeNESW",
+            Self::SizeNs => "SizeNS",
+            Self::SizeNwSe => "SizeNWSE",
+            Self::SizeWe => "SizeWE",
+            Self::Up => "UpArrow",
+            Self::Wait => "Wait",
+        }
+    }
+
+    /// Names under which X11 cursor themes and applications know this cursor, most common first
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn x11_names(self) -> &'static [&'static str] {
+        match self {
+            Self::AppStarting => &["left_ptr_watch", "progress", "half-busy"],
+            Self::Normal => &["left_ptr", "default", "arrow", "top_left_arrow"],
+            Self::Crosshair => &["crosshair", "cross", "tcross"],
+            Self::Hand => &["hand2", "pointer", "hand1", "pointing_hand"],
+            Self::Ibeam => &["xterm", "text", "ibeam"],
+            Self::No => &["crossed_circle", "not-allowed", "forbidden"],
+            Self::SizeAll => &["fleur", "move", "all-scroll", "size_all"],
+            Self::SizeNeSw => &["size_bdiag", "nesw-resize"],
+            Self::SizeNs => &[
+                "sb_v_double_arrow",
+                "ns-resize",
+                "size_ver",
+                "v_double_arrow",
+            ],
+            Self::SizeNwSe => &["size_fdiag", "nwse-resize"],
+            Self::SizeWe => &[
+                "sb_h_double_arrow",
+                "ew-resize",
+                "size_hor",
+                "h_double_arrow",
+            ],
+            Self::Up => &["sb_up_arrow", "up_arrow", "center_ptr"],
+            Self::Wait => &["watch", "wait"],
+        }
+    }
 }
 
+impl FromStr for CursorKind {
+    type Err = UnknownCursorKind;
+
+    /// Parses the names produced by `as_str`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownCursorKind(s.to_owned()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownCursorKind(pub String);
+
+impl fmt::Display for UnknownCursorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown cursor kind `{}`", self.0)
+    }
+}
+
+impl Error for UnknownCursorKind {}
+
 #[allow(dead_code)]
 mod missing_from_winapi {
     pub const OCR_NORMAL: u32 = 32512;
@@ -193,3 +534,169 @@ mod missing_from_winapi {
     pub const OCR_HAND: u32 = 32649;
     pub const OCR_APPSTARTING: u32 = 32650;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        mock::{Call, MockSource},
+        *,
+    };
+
+    fn scheme(kinds: &[(CursorKind, &str)]) -> CursorScheme {
+        let mut scheme = CursorScheme::new();
+        for &(kind, path) in kinds {
+            scheme.set(kind, Path::new(path));
+        }
+        scheme
+    }
+
+    #[test]
+    fn replace_then_revert_on_drop() {
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("replace-then-revert");
+
+        let replaced = Cursor::from_file(&backend, "prank.cur")
+            .unwrap()
+            .replace_system(CursorKind::Normal, &journal)
+            .unwrap();
+        let shown = backend.current(CursorKind::Normal).unwrap();
+        assert_eq!(shown.source, MockSource::File(PathBuf::from("prank.cur")));
+        assert_eq!(journal.entries().unwrap().len(), 1);
+
+        drop(replaced);
+        assert_eq!(backend.current(CursorKind::Normal), None);
+        assert!(journal.entries().unwrap().is_empty());
+        assert!(matches!(
+            backend.calls().as_slice(),
+            [
+                Call::Load(_),
+                Call::LoadSystem(CursorKind::Normal),
+                Call::Replace(CursorKind::Normal, _),
+                Call::Revert(CursorKind::Normal, _),
+            ]
+        ));
+    }
+
+    #[test]
+    fn double_revert_is_a_no_op() {
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("double-revert");
+
+        let mut replaced = Cursor::from_file(&backend, "prank.cur")
+            .unwrap()
+            .replace_system(CursorKind::Hand, &journal)
+            .unwrap();
+        replaced.revert().unwrap();
+        replaced.revert().unwrap();
+        drop(replaced);
+
+        let reverts = backend
+            .calls()
+            .iter()
+            .filter(|call| matches!(call, Call::Revert(..)))
+            .count();
+        assert_eq!(reverts, 1);
+        assert_eq!(backend.current(CursorKind::Hand), None);
+    }
+
+    #[test]
+    fn partial_failure_rolls_back() {
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("partial-failure");
+        backend.fail_replace(CursorKind::Hand);
+
+        let scheme = scheme(&[
+            (CursorKind::Normal, "normal.cur"),
+            (CursorKind::Ibeam, "beam.cur"),
+            (CursorKind::Hand, "hand.cur"),
+            (CursorKind::Wait, "wait.ani"),
+        ]);
+        let err = ReplacedScheme::replace(&backend, &scheme, &journal).unwrap_err();
+        assert!(matches!(
+            err,
+            CursorError::ReplaceFailed {
+                kind: CursorKind::Hand,
+                ..
+            }
+        ));
+
+        for &kind in CursorKind::ALL.iter() {
+            assert_eq!(
+                backend.current(kind),
+                None,
+                "{} left replaced",
+                kind.as_str()
+            );
+        }
+        assert!(journal.entries().unwrap().is_empty());
+        // The kind after the failure is never touched.
+        assert!(!backend
+            .calls()
+            .iter()
+            .any(|call| matches!(call, Call::Replace(CursorKind::Wait, _))));
+    }
+
+    #[test]
+    fn failed_load_of_the_original_leaves_no_journal_entry() {
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("failed-load");
+        backend.fail_load_system(CursorKind::Normal);
+
+        let err = Cursor::from_file(&backend, "prank.cur")
+            .unwrap()
+            .replace_system(CursorKind::Normal, &journal)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CursorError::ReplaceFailed {
+                kind: CursorKind::Normal,
+                ..
+            }
+        ));
+        assert!(journal.entries().unwrap().is_empty());
+        assert_eq!(backend.current(CursorKind::Normal), None);
+    }
+
+    #[test]
+    fn failed_replace_keeps_an_entry_left_by_a_crash() {
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("failed-replace-after-crash");
+        let crashed = CursorSource::File(PathBuf::from("original.cur"));
+        journal.record(CursorKind::Hand, &crashed).unwrap();
+        backend.fail_replace(CursorKind::Hand);
+
+        assert!(Cursor::from_file(&backend, "prank.cur")
+            .unwrap()
+            .replace_system(CursorKind::Hand, &journal)
+            .is_err());
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].source, crashed);
+    }
+
+    #[test]
+    fn swap_keeps_the_original() {
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("swap");
+
+        let replaced = ReplacedScheme::replace(
+            &backend,
+            &scheme(&[(CursorKind::Normal, "first.cur")]),
+            &journal,
+        )
+        .unwrap();
+        let recorded = journal.entries().unwrap();
+        for path in &["second.cur", "third.cur"] {
+            replaced
+                .swap(&scheme(&[(CursorKind::Normal, path)]))
+                .unwrap();
+            let shown = backend.current(CursorKind::Normal).unwrap();
+            assert_eq!(shown.source, MockSource::File(PathBuf::from(path)));
+        }
+        assert_eq!(journal.entries().unwrap(), recorded);
+
+        drop(replaced);
+        assert_eq!(backend.current(CursorKind::Normal), None);
+        assert!(journal.entries().unwrap().is_empty());
+    }
+}
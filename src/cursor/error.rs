@@ -0,0 +1,94 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use super::CursorKind;
+
+#[derive(Debug)]
+pub enum CursorError {
+    NotFound(PathBuf),
+    /// The file exists, but is not a cursor we can use. `path` is `None` for cursors loaded from
+    /// memory.
+    InvalidFormat {
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    /// An operating system call failed with the given error code
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Os {
+        operation: &'static str,
+        code: i32,
+    },
+    /// The cursor backend could not do its job, for reasons other than an OS error code
+    Backend(String),
+    ReplaceFailed {
+        kind: CursorKind,
+        source: Box<CursorError>,
+    },
+    Io(io::Error),
+}
+
+impl CursorError {
+    /// An `Os` error for the calling thread's last OS error.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn last_os_error(operation: &'static str) -> Self {
+        Self::Os {
+            operation,
+            code: io::Error::last_os_error().raw_os_error().unwrap_or(0),
+        }
+    }
+
+    /// Turns errors from reading a cursor file into `NotFound` where appropriate.
+    pub fn from_read(path: PathBuf, err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::NotFound {
+            Self::NotFound(path)
+        } else {
+            Self::Io(err)
+        }
+    }
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "cursor file {} does not exist", path.display()),
+            Self::InvalidFormat {
+                path: Some(path),
+                reason,
+            } => write!(f, "{} is not a usable cursor: {}", path.display(), reason),
+            Self::InvalidFormat { path: None, reason } => {
+                write!(f, "not a usable cursor: {}", reason)
+            }
+            Self::Os { operation, code } => write!(
+                f,
+                "{} failed: {}",
+                operation,
+                io::Error::from_raw_os_error(*code)
+            ),
+            Self::Backend(message) => f.write_str(message),
+            Self::ReplaceFailed { kind, source } => {
+                write!(
+                    f,
+                    "could not replace the {} cursor: {}",
+                    kind.as_str(),
+                    source
+                )
+            }
+            Self::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for CursorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReplaceFailed { source, .. } => Some(&**source),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CursorError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
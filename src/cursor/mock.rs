@@ -0,0 +1,205 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{decode, CursorBackend, CursorError, CursorKind, CursorSource, DEFAULT_CURSOR};
+use crate::image::AnimatedCursor;
+
+/// A backend that never touches the real system cursors. It keeps track of which cursor each
+/// kind would be showing and records every call made to it, which makes it useful for dry runs.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    calls: RefCell<Vec<Call>>,
+    current: RefCell<HashMap<CursorKind, MockCursor>>,
+    failing: RefCell<HashSet<CursorKind>>,
+    failing_loads: RefCell<HashSet<CursorKind>>,
+    /// Images of the cursors created from one, by cursor id
+    images: RefCell<HashMap<usize, AnimatedCursor>>,
+    next_id: Cell<usize>,
+    echo: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    Load(PathBuf),
+    /// Loading a cursor from memory, with the number of bytes given
+    LoadBytes(usize),
+    /// Loading a cursor from images, with the number of frames given
+    LoadImage(usize),
+    Image(MockCursor),
+    LoadSystem(CursorKind),
+    Replace(CursorKind, MockCursor),
+    Revert(CursorKind, MockCursor),
+    Restore(CursorKind, CursorSource),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockCursor {
+    /// Distinguishes two loads of the same source
+    pub id: usize,
+    pub source: MockSource,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockSource {
+    File(PathBuf),
+    Bytes,
+    Image,
+    System(CursorKind),
+}
+
+impl MockBackend {
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `new`, but also prints every call to stderr as it happens.
+    pub fn echoing() -> Self {
+        Self {
+            echo: true,
+            ..Self::default()
+        }
+    }
+
+    /// Makes every later attempt to replace `kind` fail.
+    #[cfg(test)]
+    pub fn fail_replace(&self, kind: CursorKind) {
+        self.failing.borrow_mut().insert(kind);
+    }
+
+    /// Makes every later attempt to load the system cursor for `kind` fail.
+    #[cfg(test)]
+    pub fn fail_load_system(&self, kind: CursorKind) {
+        self.failing_loads.borrow_mut().insert(kind);
+    }
+
+    #[cfg(test)]
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.borrow().clone()
+    }
+
+    /// The cursor currently shown for `kind`, or `None` if it is still the untouched original
+    pub fn current(&self, kind: CursorKind) -> Option<MockCursor> {
+        self.current.borrow().get(&kind).cloned()
+    }
+
+    fn record(&self, call: Call) {
+        if self.echo {
+            eprintln!("{:?}", call);
+        }
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn create(&self, source: MockSource) -> MockCursor {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        MockCursor { id, source }
+    }
+}
+
+impl CursorBackend for MockBackend {
+    type Handle = MockCursor;
+
+    fn load(&self, path: &Path) -> Result<MockCursor, CursorError> {
+        self.record(Call::Load(path.to_owned()));
+        Ok(self.create(MockSource::File(path.to_owned())))
+    }
+
+    fn load_bytes(&self, bytes: &[u8]) -> Result<MockCursor, CursorError> {
+        self.record(Call::LoadBytes(bytes.len()));
+        let image = decode(bytes, None)?;
+        let cursor = self.create(MockSource::Bytes);
+        self.images.borrow_mut().insert(cursor.id, image);
+        Ok(cursor)
+    }
+
+    fn load_image(&self, image: &AnimatedCursor) -> Result<MockCursor, CursorError> {
+        self.record(Call::LoadImage(image.frames.len()));
+        let cursor = self.create(MockSource::Image);
+        self.images.borrow_mut().insert(cursor.id, image.clone());
+        Ok(cursor)
+    }
+
+    /// There are no real system cursors to read, so they all look like `DEFAULT_CURSOR`.
+    fn image(&self, cursor: &MockCursor) -> Result<AnimatedCursor, CursorError> {
+        self.record(Call::Image(cursor.clone()));
+        match &cursor.source {
+            MockSource::File(path) => {
+                let bytes =
+                    fs::read(path).map_err(|err| CursorError::from_read(path.clone(), err))?;
+                decode(&bytes, Some(path))
+            }
+            MockSource::Bytes | MockSource::Image => self
+                .images
+                .borrow()
+                .get(&cursor.id)
+                .cloned()
+                .ok_or_else(|| CursorError::Backend("mock cursor has no image".to_owned())),
+            MockSource::System(_) => decode(DEFAULT_CURSOR, None),
+        }
+    }
+
+    fn load_system(&self, kind: CursorKind) -> Result<MockCursor, CursorError> {
+        self.record(Call::LoadSystem(kind));
+        if self.failing_loads.borrow().contains(&kind) {
+            return Err(CursorError::Backend(format!(
+                "mock failure loading {}",
+                kind.as_str()
+            )));
+        }
+        Ok(self
+            .current(kind)
+            .unwrap_or_else(|| self.create(MockSource::System(kind))))
+    }
+
+    fn replace(&self, kind: CursorKind, cursor: MockCursor) -> Result<(), CursorError> {
+        self.record(Call::Replace(kind, cursor.clone()));
+        if self.failing.borrow().contains(&kind) {
+            return Err(CursorError::Backend(format!(
+                "mock failure replacing {}",
+                kind.as_str()
+            )));
+        }
+        self.current.borrow_mut().insert(kind, cursor);
+        Ok(())
+    }
+
+    fn revert(&self, kind: CursorKind, original: MockCursor) -> Result<(), CursorError> {
+        self.record(Call::Revert(kind, original.clone()));
+        match original.source {
+            MockSource::System(original_kind) if original_kind == kind => {
+                self.current.borrow_mut().remove(&kind);
+            }
+            _ => {
+                self.current.borrow_mut().insert(kind, original);
+            }
+        }
+        Ok(())
+    }
+
+    fn system_source(&self, _kind: CursorKind) -> Result<CursorSource, CursorError> {
+        Ok(CursorSource::Default)
+    }
+
+    fn restore(&self, kind: CursorKind, source: &CursorSource) -> Result<(), CursorError> {
+        self.record(Call::Restore(kind, source.clone()));
+        match source {
+            CursorSource::Default => {
+                self.current.borrow_mut().remove(&kind);
+            }
+            _ => {
+                let source = match source {
+                    CursorSource::File(path) => MockSource::File(path.clone()),
+                    _ => MockSource::System(kind),
+                };
+                let cursor = self.create(source);
+                self.current.borrow_mut().insert(kind, cursor);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,457 @@
+use std::{
+    fs, mem,
+    path::{Path, PathBuf},
+    ptr,
+    time::Duration,
+};
+
+use winapi::{
+    shared::{
+        minwindef::{DWORD, FALSE},
+        ntdef::HANDLE,
+        windef::{HBITMAP, HDC, HICON},
+        winerror::{ERROR_FILE_NOT_FOUND, ERROR_MORE_DATA, ERROR_PATH_NOT_FOUND, ERROR_SUCCESS},
+    },
+    um::{
+        errhandlingapi::GetLastError,
+        wingdi::{
+            DeleteObject, GetDIBits, GetDeviceCaps, GetObjectW, BITMAP, BITMAPINFO,
+            BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, LOGPIXELSX,
+        },
+        winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ},
+        winuser::{
+            CopyImage, CreateIconFromResourceEx, GetDC, GetIconInfo, LoadImageW, ReleaseDC,
+            SetProcessDPIAware, SetSystemCursor, SystemParametersInfoW, ICONINFO, IMAGE_CURSOR,
+            LR_DEFAULTCOLOR, LR_LOADFROMFILE, LR_SHARED, MAKEINTRESOURCEW, SPI_SETCURSORS,
+        },
+    },
+};
+
+use super::{CursorBackend, CursorError, CursorKind, CursorSource};
+use crate::{
+    format::{self, ani, cur, Format, FormatError, Reader},
+    image::{AnimatedCursor, AnimationFrame, CursorEntry, CursorImage, Hotspot},
+    transform,
+};
+
+/// The resource format version `CreateIconFromResourceEx` expects
+const RESOURCE_VERSION: DWORD = 0x0003_0000;
+/// The size of a cursor at 100% display scaling, which is 96 DPI
+const BASE_SIZE: u32 = 32;
+const BASE_DPI: u32 = 96;
+
+#[derive(Debug)]
+pub struct WindowsBackend {
+    /// The size in pixels cursors are fitted to before being handed to Windows
+    size: u32,
+}
+
+impl WindowsBackend {
+    /// Sizes cursors for the scaling of the primary display.
+    pub fn new() -> Result<Self, CursorError> {
+        // Without declaring ourselves DPI aware, Windows claims the screen is 96 DPI.
+        unsafe { SetProcessDPIAware() };
+        let dc = unsafe { GetDC(ptr::null_mut()) };
+        let dpi = if dc.is_null() {
+            BASE_DPI
+        } else {
+            let dpi = unsafe { GetDeviceCaps(dc, LOGPIXELSX) };
+            unsafe { ReleaseDC(ptr::null_mut(), dc) };
+            if dpi > 0 {
+                dpi as u32
+            } else {
+                BASE_DPI
+            }
+        };
+        Ok(Self::with_size((BASE_SIZE * dpi + BASE_DPI / 2) / BASE_DPI))
+    }
+
+    pub fn with_size(size: u32) -> Self {
+        Self { size }
+    }
+
+    /// Has Windows load a file our decoder can't read, at the size we want.
+    fn load_file(&self, path: &Path) -> Result<HANDLE, CursorError> {
+        let utf16_path = to_utf16(&path.to_string_lossy());
+        let handle = unsafe {
+            LoadImageW(
+                ptr::null_mut(),
+                utf16_path.as_ptr(),
+                IMAGE_CURSOR,
+                self.size as i32,
+                self.size as i32,
+                LR_LOADFROMFILE,
+            )
+        };
+
+        if handle.is_null() {
+            // LoadImageW fails without setting an error code when the file is not a cursor.
+            match unsafe { GetLastError() } {
+                ERROR_FILE_NOT_FOUND | ERROR_PATH_NOT_FOUND => {
+                    Err(CursorError::NotFound(path.to_owned()))
+                }
+                0 => Err(CursorError::InvalidFormat {
+                    path: Some(path.to_owned()),
+                    reason: "Windows could not load it as a cursor".to_owned(),
+                }),
+                code => Err(CursorError::Os {
+                    operation: "LoadImageW",
+                    code: code as i32,
+                }),
+            }
+        } else {
+            Ok(handle)
+        }
+    }
+}
+
+impl CursorBackend for WindowsBackend {
+    type Handle = HANDLE;
+
+    fn load(&self, path: &Path) -> Result<HANDLE, CursorError> {
+        let bytes = fs::read(path).map_err(|err| CursorError::from_read(path.to_owned(), err))?;
+        match format::decode(&bytes) {
+            Ok(cursor) => self.load_image(&cursor).map_err(|err| match err {
+                CursorError::InvalidFormat { path: None, reason } => CursorError::InvalidFormat {
+                    path: Some(path.to_owned()),
+                    reason,
+                },
+                err => err,
+            }),
+            // Windows knows nothing of Xcursor files, but reads some `.cur` variants we can't.
+            Err(_) if !bytes.starts_with(b"Xcur") => self.load_file(path),
+            Err(err) => Err(CursorError::InvalidFormat {
+                path: Some(path.to_owned()),
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    fn load_bytes(&self, bytes: &[u8]) -> Result<HANDLE, CursorError> {
+        let err = match format::decode(bytes) {
+            Ok(cursor) => return self.load_image(&cursor),
+            Err(err) => err,
+        };
+        if bytes.starts_with(b"Xcur") {
+            return Err(invalid(err));
+        }
+
+        // Handing the file over as it is leaves picking a size to Windows. Animated cursors are
+        // loaded from the whole RIFF file, static ones from a single image.
+        let mut bits = if bytes.starts_with(b"RIFF") {
+            bytes.to_vec()
+        } else {
+            cursor_resource(bytes).map_err(invalid)?
+        };
+        create_cursor(&mut bits)
+    }
+
+    /// Fits every frame to the size for the display first, so Windows has nothing to scale.
+    fn load_image(&self, image: &AnimatedCursor) -> Result<HANDLE, CursorError> {
+        let frames = image
+            .frames
+            .iter()
+            .map(|frame| {
+                let entry = transform::fit(&frame.image, self.size).ok_or_else(|| {
+                    CursorError::InvalidFormat {
+                        path: None,
+                        reason: "cursor frame contains no images".to_owned(),
+                    }
+                })?;
+                Ok(AnimationFrame::new(
+                    CursorImage::new(vec![entry]),
+                    frame.delay,
+                ))
+            })
+            .collect::<Result<Vec<_>, CursorError>>()?;
+
+        let too_large = frames
+            .iter()
+            .flat_map(|frame| &frame.image.entries)
+            .any(|entry| entry.width.max(entry.height) > Format::Cur.max_size());
+        if too_large {
+            return Err(CursorError::InvalidFormat {
+                path: None,
+                reason: format!(
+                    "Windows cursors can be at most {0}x{0} pixels",
+                    Format::Cur.max_size()
+                ),
+            });
+        }
+
+        let mut bits = match frames.as_slice() {
+            [frame] => cursor_resource(&cur::encode(&frame.image)).map_err(invalid)?,
+            _ => ani::encode(&AnimatedCursor::new(frames)),
+        };
+        create_cursor(&mut bits)
+    }
+
+    /// Reads back the image Windows shows for `cursor`. Only the current frame of an animated
+    /// cursor can be read.
+    fn image(&self, cursor: &HANDLE) -> Result<AnimatedCursor, CursorError> {
+        let mut info: ICONINFO = unsafe { mem::zeroed() };
+        if unsafe { GetIconInfo(*cursor as HICON, &mut info) } == 0 {
+            return Err(CursorError::last_os_error("GetIconInfo"));
+        }
+
+        let dc = unsafe { GetDC(ptr::null_mut()) };
+        let entry = read_icon(dc, &info);
+        unsafe {
+            ReleaseDC(ptr::null_mut(), dc);
+            DeleteObject(info.hbmMask as _);
+            if !info.hbmColor.is_null() {
+                DeleteObject(info.hbmColor as _);
+            }
+        }
+
+        Ok(AnimatedCursor::new(vec![AnimationFrame::new(
+            CursorImage::new(vec![entry?]),
+            Duration::default(),
+        )]))
+    }
+
+    fn load_system(&self, kind: CursorKind) -> Result<HANDLE, CursorError> {
+        let cursor = unsafe {
+            LoadImageW(
+                ptr::null_mut(),
+                MAKEINTRESOURCEW(kind.as_id() as u16),
+                IMAGE_CURSOR,
+                0,
+                0,
+                LR_SHARED,
+            )
+        };
+        if cursor.is_null() {
+            return Err(CursorError::last_os_error("LoadImageW"));
+        }
+
+        let handle = unsafe { CopyImage(cursor, IMAGE_CURSOR, 0, 0, 0) };
+        if handle.is_null() {
+            return Err(CursorError::last_os_error("CopyImage"));
+        }
+
+        Ok(handle)
+    }
+
+    fn replace(&self, kind: CursorKind, cursor: HANDLE) -> Result<(), CursorError> {
+        if unsafe { SetSystemCursor(cursor as HICON, kind.as_id()) } == 0 {
+            Err(CursorError::last_os_error("SetSystemCursor"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn revert(&self, kind: CursorKind, original: HANDLE) -> Result<(), CursorError> {
+        self.replace(kind, original)
+    }
+
+    /// The cursor scheme lives in the registry, which we never touch, so whatever is there is
+    /// the original.
+    fn system_source(&self, kind: CursorKind) -> Result<CursorSource, CursorError> {
+        let subkey = to_utf16(r"Control Panel\Cursors");
+        let value = to_utf16(kind.registry_name());
+
+        let mut buf: Vec<u16> = vec![0; 260];
+        loop {
+            let mut len = (buf.len() * 2) as DWORD;
+            let status = unsafe {
+                RegGetValueW(
+                    HKEY_CURRENT_USER,
+                    subkey.as_ptr(),
+                    value.as_ptr(),
+                    RRF_RT_REG_SZ,
+                    ptr::null_mut(),
+                    buf.as_mut_ptr() as *mut _,
+                    &mut len,
+                )
+            } as DWORD;
+
+            match status {
+                ERROR_SUCCESS => {
+                    let path: String = char::decode_utf16(buf.iter().copied())
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .take_while(|&c| c != '\0')
+                        .collect();
+                    return Ok(if path.is_empty() {
+                        CursorSource::Default
+                    } else {
+                        CursorSource::File(PathBuf::from(path))
+                    });
+                }
+                ERROR_FILE_NOT_FOUND => return Ok(CursorSource::Default),
+                ERROR_MORE_DATA => buf.resize(len as usize / 2 + 1, 0),
+                _ => {
+                    return Err(CursorError::Os {
+                        operation: "RegGetValueW",
+                        code: status as i32,
+                    })
+                }
+            }
+        }
+    }
+
+    fn restore(&self, kind: CursorKind, source: &CursorSource) -> Result<(), CursorError> {
+        match source {
+            CursorSource::File(path) => {
+                let handle = self.load(path)?;
+                self.replace(kind, handle)
+            }
+            // Reloads every system cursor from the scheme in the registry.
+            CursorSource::Default | CursorSource::Theme { .. } => {
+                if unsafe { SystemParametersInfoW(SPI_SETCURSORS, 0, ptr::null_mut(), 0) } == 0 {
+                    Err(CursorError::last_os_error("SystemParametersInfoW"))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn invalid(err: FormatError) -> CursorError {
+    CursorError::InvalidFormat {
+        path: None,
+        reason: err.to_string(),
+    }
+}
+
+/// Creates a cursor from a cursor resource or a whole `.ani` file.
+fn create_cursor(bits: &mut [u8]) -> Result<HANDLE, CursorError> {
+    let handle = unsafe {
+        CreateIconFromResourceEx(
+            bits.as_mut_ptr(),
+            bits.len() as DWORD,
+            FALSE,
+            RESOURCE_VERSION,
+            0,
+            0,
+            LR_DEFAULTCOLOR,
+        )
+    };
+    if handle.is_null() {
+        Err(CursorError::last_os_error("CreateIconFromResourceEx"))
+    } else {
+        Ok(handle as HANDLE)
+    }
+}
+
+/// Builds an entry from the bitmaps `GetIconInfo` returned.
+///
+/// Monochrome cursors keep their AND mask above their XOR mask in a single bitmap.
+fn read_icon(dc: HDC, info: &ICONINFO) -> Result<CursorEntry, CursorError> {
+    let (width, mask_height) = bitmap_size(info.hbmMask)?;
+    let height = if info.hbmColor.is_null() {
+        mask_height / 2
+    } else {
+        mask_height
+    };
+    let mask = dib_pixels(dc, info.hbmMask, width, mask_height)?;
+    let colour = if info.hbmColor.is_null() {
+        None
+    } else {
+        Some(dib_pixels(dc, info.hbmColor, width, height)?)
+    };
+    // Cursors from before alpha channels leave it zero and rely on the mask alone.
+    let has_alpha = colour
+        .as_ref()
+        .is_some_and(|bgra| bgra.chunks(4).any(|pixel| pixel[3] != 0));
+
+    let hotspot = Hotspot::new(info.xHotspot as u16, info.yHotspot as u16);
+    let mut entry = CursorEntry::new(width, height, hotspot);
+    for y in 0..height {
+        for x in 0..width {
+            let index = ((y * width + x) * 4) as usize;
+            // GetDIBits turns set bits of a monochrome bitmap white.
+            let and = mask[index] != 0;
+            let rgba = match &colour {
+                Some(bgra) if has_alpha => [
+                    bgra[index + 2],
+                    bgra[index + 1],
+                    bgra[index],
+                    bgra[index + 3],
+                ],
+                Some(_) if and => [0, 0, 0, 0],
+                Some(bgra) => [bgra[index + 2], bgra[index + 1], bgra[index], 255],
+                None => {
+                    let xor = mask[index + (width * height * 4) as usize];
+                    match (and, xor != 0) {
+                        (true, false) => [0, 0, 0, 0],
+                        (false, _) => [xor, xor, xor, 255],
+                        (true, true) => {
+                            entry.set_inverted(x, y);
+                            continue;
+                        }
+                    }
+                }
+            };
+            entry.set_pixel(x, y, rgba);
+        }
+    }
+    entry.bit_count = if colour.is_some() { 32 } else { 1 };
+    Ok(entry)
+}
+
+fn bitmap_size(bitmap: HBITMAP) -> Result<(u32, u32), CursorError> {
+    let mut info: BITMAP = unsafe { mem::zeroed() };
+    let len = mem::size_of::<BITMAP>() as i32;
+    if unsafe { GetObjectW(bitmap as _, len, &mut info as *mut BITMAP as _) } != len {
+        return Err(CursorError::last_os_error("GetObjectW"));
+    }
+    Ok((info.bmWidth as u32, info.bmHeight.unsigned_abs()))
+}
+
+/// The pixels of `bitmap` as 32-bit BGRA, top row first.
+fn dib_pixels(dc: HDC, bitmap: HBITMAP, width: u32, height: u32) -> Result<Vec<u8>, CursorError> {
+    let mut info: BITMAPINFO = unsafe { mem::zeroed() };
+    info.bmiHeader = BITMAPINFOHEADER {
+        biSize: mem::size_of::<BITMAPINFOHEADER>() as DWORD,
+        biWidth: width as i32,
+        // A negative height asks for the rows top down.
+        biHeight: -(height as i32),
+        biPlanes: 1,
+        biBitCount: 32,
+        biCompression: BI_RGB,
+        ..info.bmiHeader
+    };
+
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    let lines = unsafe {
+        GetDIBits(
+            dc,
+            bitmap,
+            0,
+            height,
+            pixels.as_mut_ptr() as _,
+            &mut info,
+            DIB_RGB_COLORS,
+        )
+    };
+    if lines as u32 != height {
+        return Err(CursorError::last_os_error("GetDIBits"));
+    }
+    Ok(pixels)
+}
+
+/// The first image of a `.cur` file as a cursor resource: its hotspot followed by its DIB.
+fn cursor_resource(bytes: &[u8]) -> Result<Vec<u8>, FormatError> {
+    let mut reader = Reader::new(bytes);
+    reader.seek(4)?;
+    if reader.u16()? == 0 {
+        return Err(FormatError::new(4, "cursor contains no images"));
+    }
+    reader.seek(10)?;
+    let hotspot = reader.bytes(4)?;
+    let size = reader.u32()? as usize;
+    let offset = reader.u32()? as usize;
+    reader.seek(offset)?;
+    let dib = reader.bytes(size)?;
+
+    let mut resource = Vec::with_capacity(hotspot.len() + dib.len());
+    resource.extend_from_slice(hotspot);
+    resource.extend_from_slice(dib);
+    Ok(resource)
+}
+
+fn to_utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(0..=0).collect()
+}
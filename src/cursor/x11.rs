@@ -0,0 +1,504 @@
+use std::{
+    ffi::{CStr, CString},
+    fmt, fs, mem,
+    os::raw::{c_char, c_int, c_void},
+    path::Path,
+    ptr,
+    rc::Rc,
+    slice,
+    time::Duration,
+};
+
+use x11_dl::{
+    xcursor::{self, XcursorImages},
+    xlib::{self, Display},
+};
+
+use super::{decode, CursorBackend, CursorError, CursorKind, CursorSource};
+use crate::{
+    format::xcursor::{premultiplied_argb, unpremultiplied_rgba},
+    image::{AnimatedCursor, AnimationFrame, CursorEntry, CursorImage, Hotspot},
+    transform,
+};
+
+struct Connection {
+    xlib: xlib::Xlib,
+    xcursor: xcursor::Xcursor,
+    xfixes: XFixes,
+    display: *mut Display,
+}
+
+impl Connection {
+    fn open() -> Result<Self, CursorError> {
+        let xlib = xlib::Xlib::open().map_err(backend_error)?;
+        let xcursor = xcursor::Xcursor::open().map_err(backend_error)?;
+        let xfixes = XFixes::open()?;
+
+        let display = unsafe { (xlib.XOpenDisplay)(ptr::null()) };
+        if display.is_null() {
+            return Err(backend_error("could not connect to the X server"));
+        }
+
+        Ok(Self {
+            xlib,
+            xcursor,
+            xfixes,
+            display,
+        })
+    }
+
+    fn root(&self) -> xlib::Window {
+        unsafe { (self.xlib.XDefaultRootWindow)(self.display) }
+    }
+
+    /// The name of the current cursor theme and the preferred cursor size
+    fn theme(&self) -> (String, u32) {
+        let theme = unsafe { (self.xcursor.XcursorGetTheme)(self.display) };
+        let theme = if theme.is_null() {
+            "default".to_owned()
+        } else {
+            unsafe { CStr::from_ptr(theme) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        (theme, self.default_size())
+    }
+
+    fn default_size(&self) -> u32 {
+        let size = unsafe { (self.xcursor.XcursorGetDefaultSize)(self.display) };
+        if size > 0 {
+            size as u32
+        } else {
+            32
+        }
+    }
+
+    /// Points every cursor of the given kind, in every client and on the root window, at the
+    /// given images.
+    fn change_cursor(
+        &self,
+        images: *const XcursorImages,
+        kind: CursorKind,
+    ) -> Result<(), CursorError> {
+        let cursor = unsafe { (self.xcursor.XcursorImagesLoadCursor)(self.display, images) };
+        if cursor == 0 {
+            return Err(backend_error("XcursorImagesLoadCursor failed"));
+        }
+
+        for name in kind.x11_names() {
+            let name = CString::new(*name).unwrap();
+            unsafe { (self.xfixes.change_cursor_by_name)(self.display, cursor, name.as_ptr()) };
+        }
+        if let CursorKind::Normal = kind {
+            unsafe { (self.xlib.XDefineCursor)(self.display, self.root(), cursor) };
+        }
+
+        unsafe {
+            (self.xlib.XFreeCursor)(self.display, cursor);
+            (self.xlib.XFlush)(self.display);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { (self.xlib.XCloseDisplay)(self.display) };
+    }
+}
+
+type ChangeCursorByName = unsafe extern "C" fn(*mut Display, xlib::Cursor, *const c_char);
+
+/// The one XFixes function we need, which `x11-dl` has no bindings for.
+struct XFixes {
+    library: *mut c_void,
+    change_cursor_by_name: ChangeCursorByName,
+}
+
+impl XFixes {
+    fn open() -> Result<Self, CursorError> {
+        let library = ["libXfixes.so.3", "libXfixes.so"]
+            .iter()
+            .map(|name| {
+                let name = CString::new(*name).unwrap();
+                unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_LAZY) }
+            })
+            .find(|library| !library.is_null())
+            .ok_or_else(|| backend_error("could not load libXfixes"))?;
+
+        let symbol = CString::new("XFixesChangeCursorByName").unwrap();
+        let change_cursor_by_name = unsafe { libc::dlsym(library, symbol.as_ptr()) };
+        if change_cursor_by_name.is_null() {
+            unsafe { libc::dlclose(library) };
+            return Err(backend_error("libXfixes has no XFixesChangeCursorByName"));
+        }
+
+        Ok(Self {
+            library,
+            change_cursor_by_name: unsafe {
+                mem::transmute::<*mut c_void, ChangeCursorByName>(change_cursor_by_name)
+            },
+        })
+    }
+}
+
+impl Drop for XFixes {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.library) };
+    }
+}
+
+pub struct X11Backend {
+    connection: Rc<Connection>,
+}
+
+impl X11Backend {
+    pub fn new() -> Result<Self, CursorError> {
+        Ok(Self {
+            connection: Rc::new(Connection::open()?),
+        })
+    }
+
+    fn load_theme(
+        &self,
+        kind: CursorKind,
+        theme: &str,
+        size: u32,
+    ) -> Result<X11Cursor, CursorError> {
+        let xcursor = &self.connection.xcursor;
+        let theme_name = CString::new(theme).map_err(backend_error)?;
+
+        let images = kind
+            .x11_names()
+            .iter()
+            .map(|name| {
+                let name = CString::new(*name).unwrap();
+                unsafe {
+                    (xcursor.XcursorLibraryLoadImages)(
+                        name.as_ptr(),
+                        theme_name.as_ptr(),
+                        size as c_int,
+                    )
+                }
+            })
+            .find(|images| !images.is_null())
+            .ok_or_else(|| {
+                backend_error(format!(
+                    "no {} cursor in the {} theme",
+                    kind.as_str(),
+                    theme
+                ))
+            })?;
+
+        Ok(X11Cursor {
+            connection: Rc::clone(&self.connection),
+            images,
+        })
+    }
+}
+
+impl CursorBackend for X11Backend {
+    type Handle = X11Cursor;
+
+    fn load(&self, path: &Path) -> Result<X11Cursor, CursorError> {
+        let bytes = fs::read(path).map_err(|err| CursorError::from_read(path.to_owned(), err))?;
+        self.load_bytes(&bytes).map_err(|err| match err {
+            CursorError::InvalidFormat { path: None, reason } => CursorError::InvalidFormat {
+                path: Some(path.to_owned()),
+                reason,
+            },
+            err => err,
+        })
+    }
+
+    fn load_bytes(&self, bytes: &[u8]) -> Result<X11Cursor, CursorError> {
+        self.load_image(&decode(bytes, None)?)
+    }
+
+    fn load_image(&self, animated: &AnimatedCursor) -> Result<X11Cursor, CursorError> {
+        let xcursor = &self.connection.xcursor;
+        let size = self.connection.default_size();
+
+        let images = unsafe { (xcursor.XcursorImagesCreate)(animated.frames.len() as c_int) };
+        if images.is_null() {
+            return Err(backend_error("XcursorImagesCreate failed"));
+        }
+        // Owning the images straight away frees them if a later frame fails.
+        let cursor = X11Cursor {
+            connection: Rc::clone(&self.connection),
+            images,
+        };
+
+        for frame in &animated.frames {
+            // Scaling here rather than leaving it to the X server keeps the resampling smooth.
+            let entry = transform::fit(&frame.image, size)
+                .ok_or_else(|| backend_error("cursor frame contains no images"))?;
+            let image = unsafe {
+                (xcursor.XcursorImageCreate)(entry.width as c_int, entry.height as c_int)
+            };
+            if image.is_null() {
+                return Err(backend_error("XcursorImageCreate failed"));
+            }
+
+            unsafe {
+                (*image).xhot = entry.hotspot.x.into();
+                (*image).yhot = entry.hotspot.y.into();
+                (*image).delay = frame.delay.as_millis() as u32;
+                let pixels = slice::from_raw_parts_mut(
+                    (*image).pixels,
+                    (entry.width * entry.height) as usize,
+                );
+                for (pixel, rgba) in pixels.iter_mut().zip(entry.pixels.chunks(4)) {
+                    *pixel = premultiplied_argb(rgba);
+                }
+
+                let images = &mut *images;
+                *images.images.add(images.nimage as usize) = image;
+                images.nimage += 1;
+            }
+        }
+
+        Ok(cursor)
+    }
+
+    /// Every image of the cursor becomes a frame of its own, as that's how libXcursor loads
+    /// animations of a single size.
+    fn image(&self, cursor: &X11Cursor) -> Result<AnimatedCursor, CursorError> {
+        let images = unsafe { &*cursor.images };
+        let images = unsafe { slice::from_raw_parts(images.images, images.nimage as usize) };
+        let frames = images
+            .iter()
+            .map(|&image| {
+                let image = unsafe { &*image };
+                let mut entry = CursorEntry::new(
+                    image.width,
+                    image.height,
+                    Hotspot::new(image.xhot as u16, image.yhot as u16),
+                );
+                entry.nominal_size = image.size;
+                let pixels = unsafe {
+                    slice::from_raw_parts(image.pixels, (image.width * image.height) as usize)
+                };
+                for (i, &argb) in pixels.iter().enumerate() {
+                    let (x, y) = (i as u32 % image.width, i as u32 / image.width);
+                    entry.set_pixel(x, y, unpremultiplied_rgba(argb));
+                }
+                AnimationFrame::new(
+                    CursorImage::new(vec![entry]),
+                    Duration::from_millis(image.delay.into()),
+                )
+            })
+            .collect();
+        Ok(AnimatedCursor::new(frames))
+    }
+
+    fn load_system(&self, kind: CursorKind) -> Result<X11Cursor, CursorError> {
+        let (theme, size) = self.connection.theme();
+        self.load_theme(kind, &theme, size)
+    }
+
+    fn replace(&self, kind: CursorKind, cursor: X11Cursor) -> Result<(), CursorError> {
+        self.connection.change_cursor(cursor.images, kind)
+    }
+
+    fn revert(&self, kind: CursorKind, original: X11Cursor) -> Result<(), CursorError> {
+        self.connection.change_cursor(original.images, kind)
+    }
+
+    fn system_source(&self, _kind: CursorKind) -> Result<CursorSource, CursorError> {
+        let (name, size) = self.connection.theme();
+        Ok(CursorSource::Theme { name, size })
+    }
+
+    fn restore(&self, kind: CursorKind, source: &CursorSource) -> Result<(), CursorError> {
+        let original = match source {
+            CursorSource::File(path) => self.load(path)?,
+            CursorSource::Theme { name, size } => self.load_theme(kind, name, *size)?,
+            CursorSource::Default => {
+                let (theme, size) = self.connection.theme();
+                self.load_theme(kind, &theme, size)?
+            }
+        };
+        self.revert(kind, original)
+    }
+}
+
+/// A cursor held client-side as Xcursor images, so it can be applied any number of times.
+pub struct X11Cursor {
+    connection: Rc<Connection>,
+    images: *mut XcursorImages,
+}
+
+impl Drop for X11Cursor {
+    fn drop(&mut self) {
+        unsafe { (self.connection.xcursor.XcursorImagesDestroy)(self.images) };
+    }
+}
+
+impl fmt::Debug for X11Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("X11Cursor")
+            .field("images", &self.images)
+            .finish()
+    }
+}
+
+fn backend_error<E: ToString>(error: E) -> CursorError {
+    CursorError::Backend(error.to_string())
+}
+
+/// These talk to a real X server, so they only run when asked to, under Xvfb or any other
+/// display: `xvfb-run cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format;
+    use std::os::raw::{c_short, c_ulong, c_ushort};
+
+    const NORMAL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/normal.cur");
+
+    fn backend() -> X11Backend {
+        X11Backend::new().expect("could not connect to an X server, is DISPLAY set?")
+    }
+
+    /// `XFixesCursorImage` from `X11/extensions/Xfixes.h`
+    #[repr(C)]
+    struct XFixesCursorImage {
+        x: c_short,
+        y: c_short,
+        width: c_ushort,
+        height: c_ushort,
+        xhot: c_ushort,
+        yhot: c_ushort,
+        cursor_serial: c_ulong,
+        pixels: *mut c_ulong,
+        atom: xlib::Atom,
+        name: *const c_char,
+    }
+
+    type GetCursorImage = unsafe extern "C" fn(*mut Display) -> *mut XFixesCursorImage;
+
+    /// The cursor the X server is showing, which is the root window's as long as nothing else
+    /// is under the pointer
+    fn shown_cursor(backend: &X11Backend) -> CursorEntry {
+        let connection = &backend.connection;
+        let symbol = CString::new("XFixesGetCursorImage").unwrap();
+        let get_cursor_image = unsafe { libc::dlsym(connection.xfixes.library, symbol.as_ptr()) };
+        assert!(
+            !get_cursor_image.is_null(),
+            "libXfixes has no XFixesGetCursorImage"
+        );
+        let get_cursor_image =
+            unsafe { mem::transmute::<*mut c_void, GetCursorImage>(get_cursor_image) };
+
+        unsafe { (connection.xlib.XSync)(connection.display, xlib::False) };
+        let image = unsafe { get_cursor_image(connection.display) };
+        assert!(!image.is_null(), "XFixesGetCursorImage failed");
+        let image_ref = unsafe { &*image };
+        let (width, height) = (u32::from(image_ref.width), u32::from(image_ref.height));
+        let mut entry =
+            CursorEntry::new(width, height, Hotspot::new(image_ref.xhot, image_ref.yhot));
+        // The pixels are longs, but only the low 32 bits hold the colour.
+        let pixels = unsafe { slice::from_raw_parts(image_ref.pixels, (width * height) as usize) };
+        for (i, &argb) in pixels.iter().enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            entry.set_pixel(x, y, unpremultiplied_rgba(argb as u32));
+        }
+        unsafe { (connection.xlib.XFree)(image as *mut c_void) };
+        entry
+    }
+
+    /// What an entry looks like on screen, leaving out the size it was made for
+    fn looks(entry: &CursorEntry) -> (u32, u32, Hotspot, &[u8]) {
+        (entry.width, entry.height, entry.hotspot, &entry.pixels)
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn reads_back_a_loaded_cursor() {
+        let backend = backend();
+        let cursor = backend.load(Path::new(NORMAL)).unwrap();
+
+        let decoded = format::decode(&fs::read(NORMAL).unwrap()).unwrap();
+        let size = backend.connection.default_size();
+        let expected = transform::fit(&decoded.frames[0].image, size).unwrap();
+        let read_back = backend.image(&cursor).unwrap();
+        assert_eq!(read_back.frames.len(), 1);
+        let entry = &read_back.frames[0].image.entries[0];
+        assert_eq!(
+            (entry.width, entry.height),
+            (expected.width, expected.height)
+        );
+        assert_eq!(entry.hotspot, expected.hotspot);
+        // Xcursor premultiplies alpha, so colours come back as near as rounding allows.
+        let round_trip = |rgba: &[u8]| unpremultiplied_rgba(premultiplied_argb(rgba));
+        for (pixel, rgba) in entry.pixels.chunks(4).zip(expected.pixels.chunks(4)) {
+            assert_eq!(pixel, round_trip(rgba));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn replaces_and_restores_the_normal_pointer() {
+        let backend = backend();
+        let theme = backend.load_system(CursorKind::Normal).unwrap();
+        let theme = backend.image(&theme).unwrap();
+        let is_theme_frame = |entry: &CursorEntry| {
+            theme
+                .frames
+                .iter()
+                .any(|frame| looks(&frame.image.entries[0]) == looks(entry))
+        };
+
+        let size = backend.connection.default_size();
+        let mut green = CursorEntry::new(size, size, Hotspot::new(1, 1));
+        for y in 0..size {
+            for x in 0..size {
+                green.set_pixel(x, y, [0, 0xff, 0, 0xff]);
+            }
+        }
+        let prank = AnimatedCursor::new(vec![AnimationFrame::new(
+            CursorImage::new(vec![green]),
+            Duration::default(),
+        )]);
+        let cursor = backend.load_image(&prank).unwrap();
+        let expected = backend.image(&cursor).unwrap().frames[0].image.entries[0].clone();
+        assert!(
+            !is_theme_frame(&expected),
+            "the theme's pointer is solid green"
+        );
+
+        backend.replace(CursorKind::Normal, cursor).unwrap();
+        assert_eq!(looks(&shown_cursor(&backend)), looks(&expected));
+
+        let original = backend.load_system(CursorKind::Normal).unwrap();
+        backend.revert(CursorKind::Normal, original).unwrap();
+        assert!(is_theme_frame(&shown_cursor(&backend)));
+
+        let cursor = backend.load_image(&prank).unwrap();
+        backend.replace(CursorKind::Normal, cursor).unwrap();
+        let source = backend.system_source(CursorKind::Normal).unwrap();
+        backend.restore(CursorKind::Normal, &source).unwrap();
+        assert!(is_theme_frame(&shown_cursor(&backend)));
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn bad_file_names_its_path() {
+        let backend = backend();
+        match backend.load_bytes(b"not a cursor") {
+            Err(CursorError::InvalidFormat { path: None, .. }) => {}
+            other => panic!("expected a format error, found {:?}", other),
+        }
+        match backend.load(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/Cargo.toml"
+        ))) {
+            Err(CursorError::InvalidFormat {
+                path: Some(path), ..
+            }) => assert!(path.ends_with("Cargo.toml")),
+            other => panic!("expected a format error, found {:?}", other),
+        }
+    }
+}
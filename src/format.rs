@@ -0,0 +1,535 @@
+pub mod ani;
+pub mod cur;
+pub mod gif;
+pub mod png;
+pub mod xcursor;
+mod zlib;
+
+use std::{error::Error, fmt, path::Path, str::FromStr, time::Duration};
+
+use crate::image::{AnimatedCursor, AnimationFrame, CursorEntry, Hotspot};
+
+/// Decodes a cursor file in any of the supported formats. Static cursors come back as a single
+/// frame.
+pub fn decode(bytes: &[u8]) -> Result<AnimatedCursor, FormatError> {
+    if bytes.starts_with(b"RIFF") {
+        ani::decode(bytes)
+    } else if bytes.starts_with(b"Xcur") {
+        xcursor::decode(bytes)
+    } else {
+        let image = cur::decode(bytes)?;
+        Ok(AnimatedCursor::new(vec![AnimationFrame::new(
+            image,
+            Duration::default(),
+        )]))
+    }
+}
+
+/// Decodes a GIF or PNG, animated or not, into the patches it draws on its canvas.
+pub fn decode_animation(bytes: &[u8]) -> Result<Animation, FormatError> {
+    if bytes.starts_with(b"GIF8") {
+        gif::decode(bytes)
+    } else if bytes.starts_with(b"\x89PNG") {
+        png::decode_animation(bytes)
+    } else {
+        Err(FormatError::new(0, "not a GIF or PNG"))
+    }
+}
+
+/// Checks a cursor file for problems, carrying on past the first so they can all be fixed at
+/// once. Anything the checks miss that still stops the file from decoding is reported too.
+pub fn lint(bytes: &[u8]) -> Vec<Diagnostic> {
+    let mut lint = Lint::default();
+    if bytes.starts_with(b"RIFF") {
+        ani::lint(bytes, &mut lint);
+    } else if !bytes.starts_with(b"Xcur") {
+        cur::lint(bytes, &mut lint);
+    }
+    if !lint.has_errors() {
+        if let Err(err) = decode(bytes) {
+            lint.error(err.offset(), err.message());
+        }
+    }
+    lint.diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+    lint.diagnostics
+}
+
+/// Encodes a cursor in the given format. `.cur` files can't animate, so they only get the first
+/// frame.
+pub fn encode(cursor: &AnimatedCursor, format: Format) -> Vec<u8> {
+    match format {
+        Format::Cur => cursor
+            .frames
+            .first()
+            .map(|frame| cur::encode(&frame.image))
+            .unwrap_or_default(),
+        Format::Ani => ani::encode(cursor),
+        Format::Xcursor => xcursor::encode(cursor),
+    }
+}
+
+/// An animation the way GIF and APNG files store it: a canvas that each frame draws a patch
+/// of, on top of whatever the frames before it left behind.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Patch>,
+}
+
+/// One frame of an `Animation`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch {
+    /// Where the patch goes on the canvas
+    pub x: u32,
+    pub y: u32,
+    pub image: CursorEntry,
+    pub delay: Duration,
+    /// What happens to the patch once the frame is over
+    pub dispose: Dispose,
+    /// Whether the patch is drawn over the canvas rather than replacing what it covers
+    pub blend: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dispose {
+    /// Leave the patch where it is
+    #[default]
+    Keep,
+    /// Clear the area it covered to transparent
+    Background,
+    /// Put back the canvas as it was before the patch was drawn
+    Previous,
+}
+
+impl Animation {
+    /// Plays the animation, returning the whole canvas as it looks during each frame along with
+    /// how long it is shown. Patches are clipped to the canvas.
+    pub fn composite(&self) -> Vec<(CursorEntry, Duration)> {
+        let mut canvas = CursorEntry::new(self.width, self.height, Hotspot::default());
+        let mut composited = Vec::with_capacity(self.frames.len());
+
+        for patch in &self.frames {
+            let saved = match patch.dispose {
+                Dispose::Previous => Some(canvas.clone()),
+                _ => None,
+            };
+            let right = (patch.x + patch.image.width).min(self.width);
+            let bottom = (patch.y + patch.image.height).min(self.height);
+            for y in patch.y..bottom {
+                for x in patch.x..right {
+                    let source = patch.image.pixel(x - patch.x, y - patch.y);
+                    let rgba = if patch.blend {
+                        over(source, canvas.pixel(x, y))
+                    } else {
+                        source
+                    };
+                    canvas.set_pixel(x, y, rgba);
+                }
+            }
+            composited.push((canvas.clone(), patch.delay));
+
+            match saved {
+                Some(saved) => canvas = saved,
+                None if patch.dispose == Dispose::Background => {
+                    for y in patch.y..bottom {
+                        for x in patch.x..right {
+                            canvas.set_pixel(x, y, [0; 4]);
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        composited
+    }
+}
+
+/// Draws a non-premultiplied colour over another.
+fn over(top: [u8; 4], bottom: [u8; 4]) -> [u8; 4] {
+    match (top[3], bottom[3]) {
+        (0xff, _) | (_, 0) => top,
+        (0, _) => bottom,
+        _ => {
+            let top_alpha = f32::from(top[3]) / 255.0;
+            let bottom_alpha = f32::from(bottom[3]) / 255.0 * (1.0 - top_alpha);
+            let alpha = top_alpha + bottom_alpha;
+            let channel = |i: usize| {
+                ((f32::from(top[i]) * top_alpha + f32::from(bottom[i]) * bottom_alpha) / alpha)
+                    .round() as u8
+            };
+            [
+                channel(0),
+                channel(1),
+                channel(2),
+                (alpha * 255.0).round() as u8,
+            ]
+        }
+    }
+}
+
+/// The cursor file formats we can write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Cur,
+    Ani,
+    Xcursor,
+}
+
+impl Format {
+    /// Guesses the format from a file's extension. Files without one are taken to be Xcursor
+    /// files, which is how cursor themes name them.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension() {
+            None => Some(Self::Xcursor),
+            Some(extension) => extension.to_str()?.parse().ok(),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cur => "cur",
+            Self::Ani => "ani",
+            Self::Xcursor => "xcursor",
+        }
+    }
+
+    /// The largest image the format can hold, in either dimension
+    pub fn max_size(self) -> u32 {
+        match self {
+            Self::Cur | Self::Ani => 256,
+            Self::Xcursor => 0x7fff,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    /// Parses the names produced by `as_str`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Self::Cur, Self::Ani, Self::Xcursor]
+            .iter()
+            .copied()
+            .find(|format| format.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownFormat(s.to_owned()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownFormat(pub String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown cursor format `{}`, expected cur, ani or xcursor",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownFormat {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatError {
+    offset: usize,
+    message: String,
+}
+
+impl FormatError {
+    pub fn new<S: Into<String>>(offset: usize, message: S) -> Self {
+        Self {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    /// The byte offset into the file at which the problem was found
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at byte {})", self.message, self.offset)
+    }
+}
+
+impl Error for FormatError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The file won't load
+    Error,
+    /// The file loads, but something about it is off
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found in a cursor file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The byte offset into the file at which the problem was found
+    pub offset: usize,
+    pub message: String,
+}
+
+/// Collects diagnostics, with offsets relative to the data being checked, which may itself sit
+/// at `base` within a larger file.
+#[derive(Debug, Default)]
+pub struct Lint {
+    diagnostics: Vec<Diagnostic>,
+    base: usize,
+}
+
+impl Lint {
+    pub fn error<S: Into<String>>(&mut self, offset: usize, message: S) {
+        self.push(Severity::Error, offset, message.into());
+    }
+
+    pub fn warning<S: Into<String>>(&mut self, offset: usize, message: S) {
+        self.push(Severity::Warning, offset, message.into());
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Runs `check` on data embedded at `offset`, so its diagnostics point into the whole file.
+    pub fn nested<F: FnOnce(&mut Self)>(&mut self, offset: usize, check: F) {
+        self.base += offset;
+        check(self);
+        self.base -= offset;
+    }
+
+    fn push(&mut self, severity: Severity, offset: usize, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            offset: self.base + offset,
+            message,
+        });
+    }
+}
+
+/// Cursor over a byte slice that remembers where it is, so errors can point at the offending
+/// byte. Reads are little-endian unless noted otherwise.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub fn seek(&mut self, pos: usize) -> Result<(), FormatError> {
+        if pos > self.bytes.len() {
+            return Err(FormatError::new(
+                self.pos,
+                format!(
+                    "offset {} is past the end of the file ({} bytes)",
+                    pos,
+                    self.bytes.len()
+                ),
+            ));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        if len > self.remaining() {
+            return Err(FormatError::new(
+                self.pos,
+                format!(
+                    "unexpected end of file: needed {} bytes, found {}",
+                    len,
+                    self.remaining()
+                ),
+            ));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, FormatError> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, FormatError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Reads a big-endian `u32`, as used by PNG.
+    pub fn u32_be(&mut self) -> Result<u32, FormatError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, FormatError> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// Reads a four character code such as `RIFF`.
+    pub fn tag(&mut self) -> Result<[u8; 4], FormatError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [0xff, 0, 0, 0xff];
+    const GREEN: [u8; 4] = [0, 0xff, 0, 0xff];
+    const BLUE: [u8; 4] = [0, 0, 0xff, 0xff];
+
+    /// The fixtures in `fixtures/import` fill a 4x4 canvas with red, draw a 2x2 green patch at
+    /// 1,1 whose top left pixel is transparent and which is disposed of as the file name says,
+    /// then draw a blue pixel at 0,0.
+    fn fixture(name: &str) -> Animation {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/import/");
+        decode_animation(&std::fs::read(format!("{}{}", path, name)).unwrap()).unwrap()
+    }
+
+    fn patch(x: u32, y: u32, width: u32, height: u32, rgba: [u8; 4]) -> Patch {
+        let mut image = CursorEntry::new(width, height, Hotspot::default());
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, rgba);
+            }
+        }
+        Patch {
+            x,
+            y,
+            image,
+            delay: Duration::from_millis(50),
+            dispose: Dispose::Keep,
+            blend: true,
+        }
+    }
+
+    /// The pixels under the green patch, left to right and top to bottom
+    fn patch_area(canvas: &CursorEntry) -> [[u8; 4]; 4] {
+        [
+            canvas.pixel(1, 1),
+            canvas.pixel(2, 1),
+            canvas.pixel(1, 2),
+            canvas.pixel(2, 2),
+        ]
+    }
+
+    #[test]
+    fn disposes_of_gif_and_apng_frames() {
+        let cases = [
+            ("keep", Dispose::Keep, [RED, GREEN, GREEN, GREEN]),
+            ("background", Dispose::Background, [[0; 4]; 4]),
+            ("previous", Dispose::Previous, [RED; 4]),
+        ];
+        for &(name, dispose, after) in &cases {
+            for extension in &["gif", "png"] {
+                let file = format!("dispose-{}.{}", name, extension);
+                let animation = fixture(&file);
+                assert_eq!(animation.frames[1].dispose, dispose, "{}", file);
+
+                let composited = animation.composite();
+                let delays: Vec<_> = composited.iter().map(|&(_, delay)| delay).collect();
+                let expected = [70, 10, 0].iter().map(|&ms| Duration::from_millis(ms));
+                assert_eq!(delays, expected.collect::<Vec<_>>(), "{}", file);
+
+                let frames: Vec<_> = composited.into_iter().map(|(frame, _)| frame).collect();
+                assert_eq!((frames[0].width, frames[0].height), (4, 4), "{}", file);
+                assert_eq!(patch_area(&frames[0]), [RED; 4], "{}", file);
+                // The transparent pixel of the patch shows the red below it.
+                assert_eq!(
+                    patch_area(&frames[1]),
+                    [RED, GREEN, GREEN, GREEN],
+                    "{}",
+                    file
+                );
+                assert_eq!(patch_area(&frames[2]), after, "{}", file);
+                assert_eq!(frames[2].pixel(0, 0), BLUE, "{}", file);
+                assert_eq!(frames[2].pixel(3, 3), RED, "{}", file);
+            }
+        }
+    }
+
+    #[test]
+    fn clips_patches_to_the_canvas() {
+        let animation = Animation {
+            width: 2,
+            height: 2,
+            frames: vec![patch(1, 1, 3, 3, GREEN)],
+        };
+        let composited = animation.composite();
+        let (canvas, delay) = &composited[0];
+        assert_eq!((canvas.width, canvas.height), (2, 2));
+        assert_eq!(*delay, Duration::from_millis(50));
+        assert_eq!(canvas.pixel(1, 1), GREEN);
+        assert_eq!(canvas.pixel(0, 0), [0; 4]);
+        assert_eq!(canvas.pixel(1, 0), [0; 4]);
+        assert_eq!(canvas.pixel(0, 1), [0; 4]);
+    }
+
+    #[test]
+    fn blends_or_replaces() {
+        let translucent = [0, 0, 0xff, 0x80];
+        let mut replaced = patch(0, 0, 1, 1, translucent);
+        replaced.blend = false;
+        let animation = Animation {
+            width: 2,
+            height: 1,
+            frames: vec![
+                patch(0, 0, 2, 1, RED),
+                patch(1, 0, 1, 1, translucent),
+                replaced,
+            ],
+        };
+        let composited = animation.composite();
+        assert_eq!(composited[1].0.pixel(1, 0), [0x7f, 0, 0x80, 0xff]);
+        assert_eq!(composited[2].0.pixel(0, 0), translucent);
+        assert_eq!(composited[2].0.pixel(1, 0), [0x7f, 0, 0x80, 0xff]);
+        // Drawing over a transparent pixel leaves the colour as it is.
+        assert_eq!(over(translucent, [0; 4]), translucent);
+        assert_eq!(over([0; 4], RED), RED);
+    }
+}
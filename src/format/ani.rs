@@ -0,0 +1,582 @@
+//! Animated cursors: a RIFF `ACON` container holding an `anih` header, optional `rate` and
+//! `seq ` chunks and a `LIST fram` of embedded `.cur` files.
+
+use std::time::Duration;
+
+use crate::image::{AnimatedCursor, AnimationFrame, CursorImage};
+
+use super::{cur, FormatError, Lint, Reader};
+
+const ANIHEADER_LEN: u32 = 36;
+
+/// Frames are stored as icon or cursor files rather than raw bitmaps
+const AF_ICON: u32 = 0x1;
+/// The file contains a `seq ` chunk
+const AF_SEQUENCE: u32 = 0x2;
+
+const NANOS_PER_JIFFY: u64 = 1_000_000_000 / 60;
+
+struct Header {
+    frames: u32,
+    steps: u32,
+    rate: u32,
+    flags: u32,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<AnimatedCursor, FormatError> {
+    let mut reader = Reader::new(bytes);
+
+    if &reader.tag()? != b"RIFF" {
+        return Err(FormatError::new(0, "missing RIFF signature"));
+    }
+    let riff_len = reader.u32()? as usize;
+    if &reader.tag()? != b"ACON" {
+        return Err(FormatError::new(8, "RIFF form type is not ACON"));
+    }
+    let end = (8 + riff_len).min(bytes.len());
+
+    let mut header = None;
+    let mut rates = None;
+    let mut sequence = None;
+    let mut images = Vec::new();
+
+    while reader.position() + 8 <= end {
+        let id = reader.tag()?;
+        let len = reader.u32()? as usize;
+        let data_offset = reader.position();
+        let mut data = Reader::new(reader.bytes(len)?);
+
+        match &id {
+            b"anih" => header = Some(decode_header(&mut data, data_offset)?),
+            b"rate" => rates = Some(decode_u32s(&mut data)?),
+            b"seq " => sequence = Some(decode_u32s(&mut data)?),
+            b"LIST" if len >= 4 && data.tag()? == *b"fram" => {
+                while data.remaining() >= 8 {
+                    let icon_offset = data_offset + data.position();
+                    let id = data.tag()?;
+                    let icon_len = data.u32()? as usize;
+                    let icon = data.bytes(icon_len)?;
+                    if &id == b"icon" {
+                        let image = cur::decode(icon).map_err(|err| {
+                            FormatError::new(icon_offset + 8 + err.offset(), err.message())
+                        })?;
+                        images.push(image);
+                    }
+                    skip_padding(&mut data, icon_len)?;
+                }
+            }
+            // Everything else, like the `LIST INFO` metadata, is of no use to us.
+            _ => {}
+        }
+
+        skip_padding(&mut reader, len)?;
+    }
+
+    let header = header.ok_or_else(|| FormatError::new(12, "missing anih chunk"))?;
+    if header.flags & AF_ICON == 0 {
+        return Err(FormatError::new(
+            12,
+            "frames stored as raw bitmaps are not supported",
+        ));
+    }
+    if images.len() != header.frames as usize {
+        return Err(FormatError::new(
+            12,
+            format!(
+                "anih declares {} frames but the file contains {}",
+                header.frames,
+                images.len()
+            ),
+        ));
+    }
+
+    let sequence = match sequence {
+        Some(sequence) => sequence,
+        None => (0..header.frames).collect(),
+    };
+    let steps = if header.flags & AF_SEQUENCE != 0 {
+        header.steps as usize
+    } else {
+        sequence.len()
+    };
+    if sequence.len() < steps {
+        return Err(FormatError::new(
+            12,
+            format!(
+                "{} animation steps declared but only {} sequenced",
+                steps,
+                sequence.len()
+            ),
+        ));
+    }
+    if let Some(rates) = &rates {
+        if rates.len() < steps {
+            return Err(FormatError::new(
+                12,
+                format!(
+                    "{} animation steps declared but only {} rates given",
+                    steps,
+                    rates.len()
+                ),
+            ));
+        }
+    }
+
+    let mut frames = Vec::with_capacity(steps);
+    for step in 0..steps {
+        let index = sequence[step] as usize;
+        let image = images.get(index).ok_or_else(|| {
+            FormatError::new(
+                12,
+                format!("step {} refers to missing frame {}", step, index),
+            )
+        })?;
+        let jiffies = rates.as_ref().map_or(header.rate, |rates| rates[step]);
+        frames.push(AnimationFrame::new(image.clone(), from_jiffies(jiffies)));
+    }
+
+    Ok(AnimatedCursor::new(frames))
+}
+
+/// Checks the RIFF structure of an animated cursor and every frame in it.
+pub fn lint(bytes: &[u8], lint: &mut Lint) {
+    let u32_at = |offset: usize| {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(buf)
+    };
+
+    if bytes.len() < 12 {
+        lint.error(0, "RIFF header is truncated");
+        return;
+    }
+    if &bytes[8..12] != b"ACON" {
+        lint.error(8, "RIFF form type is not ACON");
+        return;
+    }
+    let riff_end = 8 + u32_at(4) as usize;
+    if riff_end > bytes.len() {
+        lint.error(
+            4,
+            format!(
+                "RIFF chunk claims {} bytes but the file holds {}",
+                riff_end,
+                bytes.len()
+            ),
+        );
+    } else if riff_end < bytes.len() {
+        lint.warning(
+            riff_end,
+            format!("{} bytes follow the RIFF chunk", bytes.len() - riff_end),
+        );
+    }
+    let end = riff_end.min(bytes.len());
+
+    let mut header = None;
+    let mut sequence = None;
+    let mut icons = 0;
+    for (offset, id, data) in chunks(bytes, 12, end, lint) {
+        match &id {
+            b"anih" => {
+                if data.len() != ANIHEADER_LEN as usize || u32_at(offset + 8) != ANIHEADER_LEN {
+                    lint.error(offset, "anih chunk is not 36 bytes long");
+                    continue;
+                }
+                let flags = u32_at(offset + 8 + 32);
+                if flags & AF_ICON == 0 {
+                    lint.error(
+                        offset + 8 + 32,
+                        "frames stored as raw bitmaps are not supported",
+                    );
+                }
+                header = Some((offset, u32_at(offset + 12)));
+            }
+            b"seq " => sequence = Some((offset + 8, data)),
+            b"LIST" if data.starts_with(b"fram") => {
+                let list_end = offset + 8 + data.len();
+                for (icon_offset, id, icon) in chunks(bytes, offset + 12, list_end, lint) {
+                    if &id == b"icon" {
+                        icons += 1;
+                        lint.nested(icon_offset + 8, |lint| cur::lint(icon, lint));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (header_offset, frames) = match header {
+        Some(header) => header,
+        None => {
+            lint.error(12, "missing anih chunk");
+            return;
+        }
+    };
+    if icons != frames {
+        lint.error(
+            header_offset + 12,
+            format!(
+                "anih declares {} frames but the file contains {}",
+                frames, icons
+            ),
+        );
+    }
+    if let Some((offset, sequence)) = sequence {
+        for (step, index) in sequence.chunks_exact(4).enumerate() {
+            let index = u32::from_le_bytes([index[0], index[1], index[2], index[3]]);
+            if index >= frames {
+                lint.error(
+                    offset + step * 4,
+                    format!("step {} refers to missing frame {}", step, index),
+                );
+            }
+        }
+    }
+}
+
+/// The chunks between `start` and `end` with their offsets, reporting any that run past `end`.
+fn chunks<'a>(
+    bytes: &'a [u8],
+    start: usize,
+    end: usize,
+    lint: &mut Lint,
+) -> Vec<(usize, [u8; 4], &'a [u8])> {
+    let mut chunks = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end {
+        let mut id = [0; 4];
+        id.copy_from_slice(&bytes[pos..pos + 4]);
+        let mut len = [0; 4];
+        len.copy_from_slice(&bytes[pos + 4..pos + 8]);
+        let len = u32::from_le_bytes(len) as usize;
+        if len > end - pos - 8 {
+            lint.error(
+                pos + 4,
+                format!(
+                    "chunk `{}` claims {} bytes but only {} remain",
+                    String::from_utf8_lossy(&id),
+                    len,
+                    end - pos - 8
+                ),
+            );
+            break;
+        }
+        chunks.push((pos, id, &bytes[pos + 8..pos + 8 + len]));
+        // Chunks are padded to an even length.
+        pos += 8 + len + len % 2;
+    }
+    chunks
+}
+
+fn decode_header(reader: &mut Reader, offset: usize) -> Result<Header, FormatError> {
+    let len = reader.u32()?;
+    if len != ANIHEADER_LEN {
+        return Err(FormatError::new(
+            offset,
+            format!("unexpected anih size {}", len),
+        ));
+    }
+    let frames = reader.u32()?;
+    let steps = reader.u32()?;
+    // Width, height, bit count and planes only apply to raw bitmap frames.
+    reader.bytes(16)?;
+    let rate = reader.u32()?;
+    let flags = reader.u32()?;
+
+    Ok(Header {
+        frames,
+        steps,
+        rate,
+        flags,
+    })
+}
+
+fn decode_u32s(reader: &mut Reader) -> Result<Vec<u32>, FormatError> {
+    let mut values = Vec::with_capacity(reader.remaining() / 4);
+    while reader.remaining() >= 4 {
+        values.push(reader.u32()?);
+    }
+    Ok(values)
+}
+
+fn skip_padding(reader: &mut Reader, len: usize) -> Result<(), FormatError> {
+    if len % 2 == 1 && reader.remaining() > 0 {
+        reader.u8()?;
+    }
+    Ok(())
+}
+
+/// Encodes an animated cursor. Identical images are only stored once.
+pub fn encode(cursor: &AnimatedCursor) -> Vec<u8> {
+    let mut images: Vec<&CursorImage> = Vec::new();
+    let mut sequence = Vec::with_capacity(cursor.frames.len());
+    for frame in &cursor.frames {
+        let index = match images.iter().position(|&image| *image == frame.image) {
+            Some(index) => index,
+            None => {
+                images.push(&frame.image);
+                images.len() - 1
+            }
+        };
+        sequence.push(index as u32);
+    }
+    let rates: Vec<u32> = cursor
+        .frames
+        .iter()
+        .map(|frame| to_jiffies(frame.delay))
+        .collect();
+
+    let needs_sequence = sequence.iter().enumerate().any(|(i, &idx)| i as u32 != idx);
+    let uniform_rate = rates.windows(2).all(|pair| pair[0] == pair[1]);
+
+    let mut flags = AF_ICON;
+    if needs_sequence {
+        flags |= AF_SEQUENCE;
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(b"ACON");
+
+    let mut header = Vec::with_capacity(ANIHEADER_LEN as usize);
+    header.extend_from_slice(&ANIHEADER_LEN.to_le_bytes());
+    header.extend_from_slice(&(images.len() as u32).to_le_bytes());
+    header.extend_from_slice(&(sequence.len() as u32).to_le_bytes());
+    header.extend_from_slice(&[0; 16]);
+    header.extend_from_slice(&rates.first().copied().unwrap_or(0).to_le_bytes());
+    header.extend_from_slice(&flags.to_le_bytes());
+    write_chunk(&mut body, b"anih", &header);
+
+    if !uniform_rate {
+        write_chunk(&mut body, b"rate", &u32s_to_bytes(&rates));
+    }
+    if needs_sequence {
+        write_chunk(&mut body, b"seq ", &u32s_to_bytes(&sequence));
+    }
+
+    let mut list = Vec::new();
+    list.extend_from_slice(b"fram");
+    for image in images {
+        write_chunk(&mut list, b"icon", &cur::encode(image));
+    }
+    write_chunk(&mut body, b"LIST", &list);
+
+    let mut out = Vec::with_capacity(body.len() + 8);
+    write_chunk(&mut out, b"RIFF", &body);
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+fn u32s_to_bytes(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn from_jiffies(jiffies: u32) -> Duration {
+    Duration::from_nanos(u64::from(jiffies) * NANOS_PER_JIFFY)
+}
+
+fn to_jiffies(delay: Duration) -> u32 {
+    let nanos = delay.as_nanos() as u64;
+    ((nanos + NANOS_PER_JIFFY / 2) / NANOS_PER_JIFFY) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        format::{Diagnostic, Severity},
+        image::Hotspot,
+    };
+
+    const STEADY: &[u8] = include_bytes!("../../fixtures/steady.ani");
+    const SEQUENCED: &[u8] = include_bytes!("../../fixtures/sequenced.ani");
+    const TRUNCATED: &[u8] = include_bytes!("../../fixtures/truncated.ani");
+    const MISSING_FRAME: &[u8] = include_bytes!("../../fixtures/missing_frame.ani");
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 128];
+
+    /// The colour of the top left pixel of each frame, which tells the fixtures' frames apart
+    fn colours(cursor: &AnimatedCursor) -> Vec<[u8; 4]> {
+        cursor
+            .frames
+            .iter()
+            .map(|frame| frame.image.entries[0].pixel(0, 0))
+            .collect()
+    }
+
+    fn delays(cursor: &AnimatedCursor) -> Vec<u32> {
+        cursor
+            .frames
+            .iter()
+            .map(|frame| to_jiffies(frame.delay))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_frames_at_a_steady_rate() {
+        let cursor = decode(STEADY).unwrap();
+        assert_eq!(colours(&cursor), vec![RED, GREEN]);
+        assert_eq!(delays(&cursor), vec![6, 6]);
+
+        let red = &cursor.frames[0].image.entries[0];
+        assert_eq!((red.width, red.height), (2, 2));
+        assert_eq!(red.hotspot, Hotspot::new(0, 0));
+        assert_eq!(red.pixel(1, 0)[3], 0);
+        assert_eq!(red.pixel(1, 1), RED);
+        assert_eq!(
+            cursor.frames[1].image.entries[0].hotspot,
+            Hotspot::new(1, 1)
+        );
+    }
+
+    #[test]
+    fn follows_the_sequence_and_rates() {
+        let cursor = decode(SEQUENCED).unwrap();
+        assert_eq!(colours(&cursor), vec![RED, BLUE, GREEN, BLUE]);
+        assert_eq!(delays(&cursor), vec![3, 6, 12, 30]);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let err = decode(TRUNCATED).unwrap_err();
+        assert!(
+            err.to_string().contains("unexpected end of file"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn step_past_the_frames_is_an_error() {
+        let err = decode(MISSING_FRAME).unwrap_err();
+        assert!(
+            err.to_string().contains("step 1 refers to missing frame 5"),
+            "{}",
+            err
+        );
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<(Severity, usize, &str)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let message = diagnostic.message.as_str();
+                (diagnostic.severity, diagnostic.offset, message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lints_fixtures() {
+        assert_eq!(crate::format::lint(STEADY), vec![]);
+        assert_eq!(crate::format::lint(SEQUENCED), vec![]);
+
+        let diagnostics = crate::format::lint(TRUNCATED);
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                (
+                    Severity::Error,
+                    4,
+                    "RIFF chunk claims 256 bytes but the file holds 236"
+                ),
+                (
+                    Severity::Error,
+                    24,
+                    "anih declares 2 frames but the file contains 0"
+                ),
+                (
+                    Severity::Error,
+                    60,
+                    "chunk `LIST` claims 192 bytes but only 172 remain"
+                ),
+            ]
+        );
+        // Each offset points at the length or count it complains about.
+        assert_eq!(u32_at(TRUNCATED, 4) + 8, 256);
+        assert_eq!(u32_at(TRUNCATED, 24), 2);
+        assert_eq!(&TRUNCATED[56..60], b"LIST");
+        assert_eq!(u32_at(TRUNCATED, 60), 192);
+
+        assert_eq!(
+            messages(&crate::format::lint(MISSING_FRAME)),
+            vec![(Severity::Error, 68, "step 1 refers to missing frame 5")]
+        );
+        assert_eq!(u32_at(MISSING_FRAME, 68), 5);
+    }
+
+    #[test]
+    fn lints_frames_at_their_place_in_the_file() {
+        let icon = STEADY
+            .windows(4)
+            .position(|window| window == b"icon")
+            .unwrap();
+        // The bit depth of the first frame's bitmap, past the chunk header and the ICONDIR
+        let depth = icon + 8 + 6 + 16 + 14;
+        let mut bytes = STEADY.to_vec();
+        bytes[depth] = 24;
+        assert_eq!(
+            messages(&crate::format::lint(&bytes)),
+            vec![(
+                Severity::Error,
+                depth,
+                "unsupported bit depth 24 in image 0"
+            )]
+        );
+    }
+
+    #[test]
+    fn lints_the_riff_structure() {
+        let mut trailing = STEADY.to_vec();
+        trailing.extend_from_slice(&[0; 4]);
+        assert_eq!(
+            messages(&crate::format::lint(&trailing)),
+            vec![(
+                Severity::Warning,
+                STEADY.len(),
+                "4 bytes follow the RIFF chunk"
+            )]
+        );
+
+        let mut not_acon = STEADY.to_vec();
+        not_acon[8..12].copy_from_slice(b"WAVE");
+        assert_eq!(
+            messages(&crate::format::lint(&not_acon)),
+            vec![(Severity::Error, 8, "RIFF form type is not ACON")]
+        );
+
+        assert_eq!(
+            messages(&crate::format::lint(&STEADY[..10])),
+            vec![(Severity::Error, 0, "RIFF header is truncated")]
+        );
+    }
+
+    #[test]
+    fn fixtures_round_trip() {
+        for fixture in &[STEADY, SEQUENCED] {
+            let cursor = decode(fixture).unwrap();
+            assert_eq!(decode(&encode(&cursor)).unwrap(), cursor);
+        }
+    }
+}
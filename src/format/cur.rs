@@ -0,0 +1,803 @@
+//! The `.cur` format: an ICONDIR followed by one BITMAPINFOHEADER-prefixed DIB per entry.
+//!
+//! Bitmaps of 32 bits per pixel carry an alpha channel. The others, with 1, 4 or 8 bits
+//! indexing a palette, rely on the AND mask alone: where it is set, the colour is XORed onto the
+//! screen, so black leaves the screen alone and white inverts it. Inverting pixels are kept as
+//! described at `image::INVERTED`.
+
+use crate::image::{CursorEntry, CursorImage, Hotspot};
+
+use super::{FormatError, Lint, Reader};
+
+const ICONDIR_LEN: usize = 6;
+const ICONDIRENTRY_LEN: usize = 16;
+const BITMAPINFOHEADER_LEN: u32 = 40;
+
+const TYPE_ICON: u16 = 1;
+const TYPE_CURSOR: u16 = 2;
+const BI_RGB: u32 = 0;
+/// PNG compressed entries, which Windows Vista added, start with the PNG signature instead of a
+/// bitmap header.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG";
+
+/// Bit depths of the bitmaps we can decode and encode
+pub const BIT_COUNTS: &[u16] = &[1, 4, 8, 32];
+/// The colour XORed onto the screen by transparent pixels of palette bitmaps, leaving it alone
+const BLACK: [u8; 3] = [0, 0, 0];
+/// The colour XORed onto the screen by inverting pixels
+const WHITE: [u8; 3] = [255, 255, 255];
+
+pub fn decode(bytes: &[u8]) -> Result<CursorImage, FormatError> {
+    let mut reader = Reader::new(bytes);
+
+    let reserved = reader.u16()?;
+    if reserved != 0 {
+        return Err(FormatError::new(0, "reserved ICONDIR field is not zero"));
+    }
+    let kind = reader.u16()?;
+    if kind != TYPE_CURSOR {
+        return Err(FormatError::new(
+            2,
+            format!("expected a cursor (type 2), found type {}", kind),
+        ));
+    }
+    let count = reader.u16()?;
+    if count == 0 {
+        return Err(FormatError::new(4, "cursor contains no images"));
+    }
+
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let dir_offset = reader.position();
+        let _width = reader.u8()?;
+        let _height = reader.u8()?;
+        let _color_count = reader.u8()?;
+        let _reserved = reader.u8()?;
+        let hotspot = Hotspot::new(reader.u16()?, reader.u16()?);
+        let size = reader.u32()? as usize;
+        let offset = reader.u32()? as usize;
+
+        let mut bitmap = Reader::new(bytes);
+        bitmap.seek(offset).map_err(|_| {
+            FormatError::new(
+                dir_offset + 12,
+                format!("image offset {} is past the end of the file", offset),
+            )
+        })?;
+        if size > bitmap.remaining() {
+            return Err(FormatError::new(
+                dir_offset + 8,
+                format!(
+                    "image at offset {} claims {} bytes but only {} remain",
+                    offset,
+                    size,
+                    bitmap.remaining()
+                ),
+            ));
+        }
+        entries.push(decode_bitmap(&mut bitmap, hotspot)?);
+    }
+
+    Ok(CursorImage::new(entries))
+}
+
+/// Checks a `.cur` file for everything that would stop it from loading here or on Windows.
+pub fn lint(bytes: &[u8], lint: &mut Lint) {
+    if bytes.len() < ICONDIR_LEN {
+        lint.error(
+            0,
+            format!(
+                "ICONDIR is truncated: needed {} bytes, found {}",
+                ICONDIR_LEN,
+                bytes.len()
+            ),
+        );
+        return;
+    }
+    if u16_at(bytes, 0) != 0 {
+        lint.error(0, "reserved ICONDIR field is not zero");
+    }
+    match u16_at(bytes, 2) {
+        TYPE_CURSOR => {}
+        TYPE_ICON => lint.error(2, "this is an icon (type 1), which has no hotspot"),
+        kind => lint.error(
+            2,
+            format!("expected a cursor (type 2), found type {}", kind),
+        ),
+    }
+    let count = usize::from(u16_at(bytes, 4));
+    if count == 0 {
+        lint.error(4, "cursor contains no images");
+    }
+
+    let directory_end = ICONDIR_LEN + count * ICONDIRENTRY_LEN;
+    let complete = (bytes.len() - ICONDIR_LEN) / ICONDIRENTRY_LEN;
+    if complete < count {
+        lint.error(
+            ICONDIR_LEN + complete * ICONDIRENTRY_LEN,
+            format!(
+                "directory of {} entries is truncated after {}",
+                count, complete
+            ),
+        );
+    }
+
+    for index in 0..count.min(complete) {
+        let dir_offset = ICONDIR_LEN + index * ICONDIRENTRY_LEN;
+        // A dimension of 256 is stored as 0.
+        let dimension = |offset: usize| match bytes[offset] {
+            0 => 256,
+            size => u32::from(size),
+        };
+        let (dir_width, dir_height) = (dimension(dir_offset), dimension(dir_offset + 1));
+        let hotspot = Hotspot::new(u16_at(bytes, dir_offset + 4), u16_at(bytes, dir_offset + 6));
+        let size = u32_at(bytes, dir_offset + 8) as usize;
+        let offset = u32_at(bytes, dir_offset + 12) as usize;
+
+        if bytes[dir_offset + 3] != 0 {
+            lint.warning(
+                dir_offset + 3,
+                format!("reserved field of image {} is not zero", index),
+            );
+        }
+        if offset < directory_end {
+            lint.error(
+                dir_offset + 12,
+                format!(
+                    "image {} starts at offset {}, inside the directory",
+                    index, offset
+                ),
+            );
+            continue;
+        }
+        if offset >= bytes.len() {
+            lint.error(
+                dir_offset + 12,
+                format!(
+                    "image {} offset {} is past the end of the file",
+                    index, offset
+                ),
+            );
+            continue;
+        }
+        if size > bytes.len() - offset {
+            lint.error(
+                dir_offset + 8,
+                format!(
+                    "image {} claims {} bytes but only {} remain",
+                    index,
+                    size,
+                    bytes.len() - offset
+                ),
+            );
+        }
+        let bitmap = &bytes[offset..bytes.len().min(offset + size)];
+        lint_bitmap(bitmap, offset, index, lint);
+
+        if bitmap.len() >= 12 && !bitmap.starts_with(PNG_SIGNATURE) {
+            let width = u32_at(bytes, offset + 4) as i32;
+            let height = u32_at(bytes, offset + 8) as i32 / 2;
+            if width > 0 && height > 0 {
+                let (width, height) = (width as u32, height as u32);
+                if (dir_width, dir_height) != (width, height) {
+                    lint.warning(
+                        dir_offset,
+                        format!(
+                            "directory gives image {} as {}x{} but its bitmap is {}x{}",
+                            index, dir_width, dir_height, width, height
+                        ),
+                    );
+                }
+                if u32::from(hotspot.x) >= width || u32::from(hotspot.y) >= height {
+                    lint.error(
+                        dir_offset + 4,
+                        format!(
+                            "hotspot {},{} of image {} is outside its {}x{} bitmap",
+                            hotspot.x, hotspot.y, index, width, height
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Checks the DIB of image `index`, found at `offset` in the file.
+fn lint_bitmap(bitmap: &[u8], offset: usize, index: usize, lint: &mut Lint) {
+    if bitmap.starts_with(PNG_SIGNATURE) {
+        lint.error(
+            offset,
+            format!("image {} is PNG compressed, which is not supported", index),
+        );
+        return;
+    }
+    if bitmap.len() < BITMAPINFOHEADER_LEN as usize {
+        lint.error(
+            offset,
+            format!(
+                "bitmap header of image {} is truncated: needed {} bytes, found {}",
+                index,
+                BITMAPINFOHEADER_LEN,
+                bitmap.len()
+            ),
+        );
+        return;
+    }
+
+    let header_len = u32_at(bitmap, 0);
+    let width = u32_at(bitmap, 4) as i32;
+    let double_height = u32_at(bitmap, 8) as i32;
+    let planes = u16_at(bitmap, 12);
+    let bit_count = u16_at(bitmap, 14);
+    let compression = u32_at(bitmap, 16);
+
+    if header_len < BITMAPINFOHEADER_LEN {
+        lint.error(
+            offset,
+            format!(
+                "bitmap header size {} of image {} is too small",
+                header_len, index
+            ),
+        );
+        return;
+    }
+    if width <= 0 || double_height <= 0 || double_height % 2 != 0 {
+        lint.error(
+            offset + 4,
+            format!(
+                "invalid bitmap dimensions {}x{} for image {}, whose height should be \
+                 doubled to cover the AND mask",
+                width, double_height, index
+            ),
+        );
+        return;
+    }
+    let (width, height) = (width as u32, double_height as u32 / 2);
+    if width > 256 || height > 256 {
+        lint.error(
+            offset + 4,
+            format!(
+                "image {} is {}x{}, but Windows refuses cursors over 256x256",
+                index, width, height
+            ),
+        );
+    }
+    if planes != 1 {
+        lint.warning(
+            offset + 12,
+            format!("image {} has {} planes instead of 1", index, planes),
+        );
+    }
+    if !BIT_COUNTS.contains(&bit_count) {
+        lint.error(
+            offset + 14,
+            format!("unsupported bit depth {} in image {}", bit_count, index),
+        );
+        return;
+    }
+    if compression != BI_RGB {
+        lint.error(
+            offset + 16,
+            format!(
+                "unsupported bitmap compression {} in image {}",
+                compression, index
+            ),
+        );
+        return;
+    }
+
+    let mut xor_start = header_len as usize;
+    if bit_count <= 8 {
+        let colours_used = u32_at(bitmap, 32);
+        if colours_used > 1 << bit_count {
+            lint.error(
+                offset + 32,
+                format!(
+                    "palette of image {} has {} colours, more than {} bits can index",
+                    index, colours_used, bit_count
+                ),
+            );
+            return;
+        }
+        xor_start += palette_len(bit_count, colours_used) * 4;
+        if bitmap.len() < xor_start {
+            lint.error(
+                offset + header_len as usize,
+                format!("palette of image {} is truncated", index),
+            );
+            return;
+        }
+    }
+    let xor_len = row_stride(width, bit_count) * height as usize;
+    let and_len = row_stride(width, 1) * height as usize;
+    if bitmap.len() < xor_start + xor_len {
+        lint.error(
+            offset + xor_start.min(bitmap.len()),
+            format!(
+                "pixels of image {} are truncated: needed {} bytes, found {}",
+                index,
+                xor_len,
+                bitmap.len().saturating_sub(xor_start)
+            ),
+        );
+    } else if bitmap.len() < xor_start + xor_len + and_len {
+        let found = bitmap.len() - xor_start - xor_len;
+        let message = if found == 0 {
+            format!("image {} has no AND mask", index)
+        } else {
+            format!(
+                "AND mask of image {} is truncated: needed {} bytes, found {}",
+                index, and_len, found
+            )
+        };
+        lint.error(offset + xor_start + xor_len, message);
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Decodes a single DIB as stored in a cursor: the colour bitmap stacked on top of the AND mask.
+fn decode_bitmap(reader: &mut Reader, hotspot: Hotspot) -> Result<CursorEntry, FormatError> {
+    let start = reader.position();
+
+    let header_len = reader.u32()?;
+    if header_len < BITMAPINFOHEADER_LEN {
+        return Err(FormatError::new(
+            start,
+            format!("unsupported bitmap header size {}", header_len),
+        ));
+    }
+    let width = reader.i32()?;
+    let double_height = reader.i32()?;
+    let _planes = reader.u16()?;
+    let bit_count = reader.u16()?;
+    let compression = reader.u32()?;
+
+    if width <= 0 || double_height <= 0 || double_height % 2 != 0 {
+        return Err(FormatError::new(
+            start + 4,
+            format!("invalid bitmap dimensions {}x{}", width, double_height),
+        ));
+    }
+    if !BIT_COUNTS.contains(&bit_count) {
+        return Err(FormatError::new(
+            start + 14,
+            format!("unsupported bit depth {}", bit_count),
+        ));
+    }
+    if compression != BI_RGB {
+        return Err(FormatError::new(
+            start + 16,
+            format!("unsupported bitmap compression {}", compression),
+        ));
+    }
+
+    let width = width as u32;
+    let height = double_height as u32 / 2;
+
+    let mut palette = Vec::new();
+    if bit_count <= 8 {
+        reader.seek(start + 32)?;
+        let colours_used = reader.u32()?;
+        if colours_used > 1 << bit_count {
+            return Err(FormatError::new(
+                start + 32,
+                format!(
+                    "palette of {} colours is too large for bit depth {}",
+                    colours_used, bit_count
+                ),
+            ));
+        }
+        reader.seek(start + header_len as usize)?;
+        palette = reader
+            .bytes(palette_len(bit_count, colours_used) * 4)?
+            .chunks(4)
+            .map(|bgrx| [bgrx[2], bgrx[1], bgrx[0]])
+            .collect();
+    } else {
+        reader.seek(start + header_len as usize)?;
+    }
+
+    let xor_stride = row_stride(width, bit_count);
+    let xor = reader.bytes(xor_stride * height as usize)?;
+    let and_stride = row_stride(width, 1);
+    let and = reader.bytes(and_stride * height as usize)?;
+
+    let mut entry = CursorEntry::new(width, height, hotspot);
+    entry.bit_count = bit_count;
+    // Rows are stored bottom-up.
+    for y in 0..height {
+        let xor_row = &xor[(height - 1 - y) as usize * xor_stride..];
+        let and_row = &and[(height - 1 - y) as usize * and_stride..];
+        for x in 0..width {
+            let idx = (y * width + x) as usize;
+            let rgba = if bit_count == 32 {
+                let bgra = &xor_row[x as usize * 4..x as usize * 4 + 4];
+                [bgra[2], bgra[1], bgra[0], bgra[3]]
+            } else {
+                // Windows draws indices past the end of the palette black.
+                let [r, g, b] = palette
+                    .get(palette_index(xor_row, x, bit_count))
+                    .copied()
+                    .unwrap_or(BLACK);
+                [r, g, b, 0]
+            };
+            entry.pixels[idx * 4..idx * 4 + 4].copy_from_slice(&rgba);
+            entry.and_mask[idx] = and_row[x as usize / 8] & (0x80 >> (x % 8)) != 0;
+        }
+    }
+
+    // Old 32-bit cursors leave the alpha channel empty and rely on the AND mask alone, just
+    // like palette ones.
+    if bit_count != 32 || entry.pixels.chunks(4).all(|px| px[3] == 0) {
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b, _] = entry.pixel(x, y);
+                match (entry.and_mask[(y * width + x) as usize], [r, g, b] == BLACK) {
+                    (false, _) => entry.set_pixel(x, y, [r, g, b, 0xff]),
+                    (true, true) => entry.set_pixel(x, y, [0; 4]),
+                    (true, false) => entry.set_inverted(x, y),
+                }
+            }
+        }
+    }
+
+    Ok(entry)
+}
+
+/// The number of colours in the palette of a bitmap, where zero colours used means all of them.
+fn palette_len(bit_count: u16, colours_used: u32) -> usize {
+    match colours_used {
+        0 => 1 << bit_count,
+        used => used as usize,
+    }
+}
+
+/// The palette index of pixel `x` in a row of a 1, 4 or 8-bit bitmap, packed high bits first.
+fn palette_index(row: &[u8], x: u32, bit_count: u16) -> usize {
+    let bit = x as usize * usize::from(bit_count);
+    let shift = 8 - usize::from(bit_count) - bit % 8;
+    usize::from((row[bit / 8] >> shift) & ((1u16 << bit_count) - 1) as u8)
+}
+
+/// The colours of a palette of at most `2^bit_count` colours that holds every pixel of `entry`,
+/// if there is one. Partly transparent pixels can't be had without an alpha channel.
+pub fn palette(entry: &CursorEntry, bit_count: u16) -> Option<Vec<[u8; 3]>> {
+    if bit_count > 8 || !BIT_COUNTS.contains(&bit_count) {
+        return None;
+    }
+    let mut colours = Vec::new();
+    for y in 0..entry.height {
+        for x in 0..entry.width {
+            let colour = match entry.pixel(x, y) {
+                _ if entry.is_inverted(x, y) => WHITE,
+                [_, _, _, 0] => BLACK,
+                [r, g, b, 0xff] => [r, g, b],
+                _ => return None,
+            };
+            if !colours.contains(&colour) {
+                if colours.len() == 1 << bit_count {
+                    return None;
+                }
+                colours.push(colour);
+            }
+        }
+    }
+    Some(colours)
+}
+
+/// Encodes a cursor. Entries keep the bit depth they were read with if their colours still fit
+/// a palette of that size, and are written as 32-bit bitmaps with an AND mask otherwise.
+pub fn encode(image: &CursorImage) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&TYPE_CURSOR.to_le_bytes());
+    out.extend_from_slice(&(image.entries.len() as u16).to_le_bytes());
+
+    let bitmaps: Vec<(Vec<u8>, usize)> = image.entries.iter().map(encode_bitmap).collect();
+
+    let mut offset = ICONDIR_LEN + ICONDIRENTRY_LEN * image.entries.len();
+    for (entry, (bitmap, colours)) in image.entries.iter().zip(&bitmaps) {
+        // A dimension of 256 is stored as 0, and so is a colour count of 256 or more.
+        out.push(entry.width as u8);
+        out.push(entry.height as u8);
+        out.push(if *colours < 256 { *colours as u8 } else { 0 });
+        out.push(0);
+        out.extend_from_slice(&entry.hotspot.x.to_le_bytes());
+        out.extend_from_slice(&entry.hotspot.y.to_le_bytes());
+        out.extend_from_slice(&(bitmap.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += bitmap.len();
+    }
+
+    for (bitmap, _) in bitmaps {
+        out.extend_from_slice(&bitmap);
+    }
+
+    out
+}
+
+/// Encodes the DIB of an entry, returning it with the number of colours in its palette.
+fn encode_bitmap(entry: &CursorEntry) -> (Vec<u8>, usize) {
+    let (width, height) = (entry.width, entry.height);
+    let palette = palette(entry, entry.bit_count).unwrap_or_default();
+    let bit_count = if palette.is_empty() {
+        32
+    } else {
+        entry.bit_count
+    };
+    let xor_stride = row_stride(width, bit_count);
+    let and_stride = row_stride(width, 1);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&BITMAPINFOHEADER_LEN.to_le_bytes());
+    out.extend_from_slice(&(width as i32).to_le_bytes());
+    out.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&bit_count.to_le_bytes());
+    out.extend_from_slice(&BI_RGB.to_le_bytes());
+    // Like most editors, count the image size over the doubled height.
+    out.extend_from_slice(&((xor_stride * height as usize * 2) as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+
+    for [r, g, b] in &palette {
+        out.extend_from_slice(&[*b, *g, *r, 0]);
+    }
+    for y in (0..height).rev() {
+        if palette.is_empty() {
+            for x in 0..width {
+                let [r, g, b, a] = entry.pixel(x, y);
+                out.extend_from_slice(&[b, g, r, a]);
+            }
+            continue;
+        }
+        let mut row = vec![0u8; xor_stride];
+        for x in 0..width {
+            let colour = match entry.pixel(x, y) {
+                _ if entry.is_inverted(x, y) => WHITE,
+                [_, _, _, 0] => BLACK,
+                [r, g, b, _] => [r, g, b],
+            };
+            // `palette` found every colour, so this can't miss.
+            let index = palette.iter().position(|&c| c == colour).unwrap_or(0);
+            let bit = x as usize * usize::from(bit_count);
+            row[bit / 8] |= (index as u8) << (8 - usize::from(bit_count) - bit % 8);
+        }
+        out.extend_from_slice(&row);
+    }
+
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; and_stride];
+        for x in 0..width {
+            if entry.and_mask[(y * width + x) as usize] {
+                row[x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        out.extend_from_slice(&row);
+    }
+
+    (out, palette.len())
+}
+
+/// Bytes per row of a DIB, which are padded to a multiple of four.
+pub fn row_stride(width: u32, bit_count: u16) -> usize {
+    (width as usize * bit_count as usize).div_ceil(32) * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::format::{Diagnostic, Severity};
+
+    const NORMAL: &[u8] = include_bytes!("../../normal.cur");
+    /// Where normal.cur's only image starts, right after its directory
+    const BITMAP: usize = ICONDIR_LEN + ICONDIRENTRY_LEN;
+    /// The length of normal.cur's 32x32 bitmap, up to the AND mask
+    const AND_MASK: usize = BITMAP + BITMAPINFOHEADER_LEN as usize + 32 * 32 * 4;
+
+    fn error(offset: usize, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            offset,
+            message: message.to_owned(),
+        }
+    }
+
+    fn warning(offset: usize, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..error(offset, message)
+        }
+    }
+
+    /// normal.cur with `corrupt` applied to a copy of it
+    fn corrupted<F: FnOnce(&mut Vec<u8>)>(corrupt: F) -> Vec<u8> {
+        let mut bytes = NORMAL.to_vec();
+        corrupt(&mut bytes);
+        bytes
+    }
+
+    fn set_u16(bytes: &mut [u8], offset: usize, value: u16) {
+        bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn decodes_normal_cur() {
+        let image = decode(NORMAL).unwrap();
+        assert_eq!(image.entries.len(), 1);
+        let entry = &image.entries[0];
+        assert_eq!((entry.width, entry.height), (32, 32));
+        assert_eq!(entry.bit_count, 32);
+        assert_eq!(entry.pixels.len(), 32 * 32 * 4);
+        assert_eq!(entry.and_mask.len(), 32 * 32);
+    }
+
+    #[test]
+    fn normal_cur_lints_clean() {
+        assert_eq!(crate::format::lint(NORMAL), vec![]);
+    }
+
+    #[test]
+    fn lints_truncated_headers() {
+        assert_eq!(
+            crate::format::lint(&NORMAL[..4]),
+            vec![error(0, "ICONDIR is truncated: needed 6 bytes, found 4")]
+        );
+        assert_eq!(
+            crate::format::lint(&NORMAL[..14]),
+            vec![error(6, "directory of 1 entries is truncated after 0")]
+        );
+        let truncated = corrupted(|bytes| bytes.truncate(BITMAP + 20));
+        assert_eq!(
+            crate::format::lint(&truncated),
+            vec![
+                error(14, "image 0 claims 4264 bytes but only 20 remain"),
+                error(
+                    BITMAP,
+                    "bitmap header of image 0 is truncated: needed 40 bytes, found 20"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lints_the_directory() {
+        let icon = corrupted(|bytes| set_u16(bytes, 2, TYPE_ICON));
+        assert_eq!(
+            crate::format::lint(&icon),
+            vec![error(2, "this is an icon (type 1), which has no hotspot")]
+        );
+
+        // A directory that disagrees with the bitmap still loads.
+        let inconsistent = corrupted(|bytes| bytes[ICONDIR_LEN] = 16);
+        assert_eq!(
+            crate::format::lint(&inconsistent),
+            vec![warning(
+                ICONDIR_LEN,
+                "directory gives image 0 as 16x32 but its bitmap is 32x32"
+            )]
+        );
+
+        let inside = corrupted(|bytes| set_u32(bytes, ICONDIR_LEN + 12, 10));
+        assert_eq!(
+            crate::format::lint(&inside),
+            vec![error(
+                18,
+                "image 0 starts at offset 10, inside the directory"
+            )]
+        );
+    }
+
+    #[test]
+    fn lints_a_hotspot_outside_the_image() {
+        let bytes = corrupted(|bytes| {
+            set_u16(bytes, ICONDIR_LEN + 4, 40);
+            set_u16(bytes, ICONDIR_LEN + 6, 2);
+        });
+        assert_eq!(
+            crate::format::lint(&bytes),
+            vec![error(
+                10,
+                "hotspot 40,2 of image 0 is outside its 32x32 bitmap"
+            )]
+        );
+        assert_eq!(bytes[10], 40);
+    }
+
+    #[test]
+    fn lints_a_missing_and_mask() {
+        let bytes = corrupted(|bytes| {
+            bytes.truncate(AND_MASK);
+            set_u32(bytes, ICONDIR_LEN + 8, (AND_MASK - BITMAP) as u32);
+        });
+        assert_eq!(
+            crate::format::lint(&bytes),
+            vec![error(AND_MASK, "image 0 has no AND mask")]
+        );
+
+        let partial = corrupted(|bytes| {
+            bytes.truncate(AND_MASK + 5);
+            set_u32(bytes, ICONDIR_LEN + 8, (AND_MASK + 5 - BITMAP) as u32);
+        });
+        assert_eq!(
+            crate::format::lint(&partial),
+            vec![error(
+                AND_MASK,
+                "AND mask of image 0 is truncated: needed 128 bytes, found 5"
+            )]
+        );
+    }
+
+    #[test]
+    fn lints_unsupported_bitmaps() {
+        let depth = corrupted(|bytes| set_u16(bytes, BITMAP + 14, 24));
+        assert_eq!(
+            crate::format::lint(&depth),
+            vec![error(BITMAP + 14, "unsupported bit depth 24 in image 0")]
+        );
+        assert_eq!(depth[BITMAP + 14], 24);
+
+        let compressed = corrupted(|bytes| set_u32(bytes, BITMAP + 16, 3));
+        assert_eq!(
+            crate::format::lint(&compressed),
+            vec![error(
+                BITMAP + 16,
+                "unsupported bitmap compression 3 in image 0"
+            )]
+        );
+
+        let planes = corrupted(|bytes| set_u16(bytes, BITMAP + 12, 2));
+        assert_eq!(
+            crate::format::lint(&planes),
+            vec![warning(BITMAP + 12, "image 0 has 2 planes instead of 1")]
+        );
+    }
+
+    #[test]
+    fn lints_oversized_images() {
+        let bytes = corrupted(|bytes| {
+            set_u32(bytes, BITMAP + 4, 300);
+            set_u32(bytes, BITMAP + 8, 600);
+        });
+        let diagnostics = crate::format::lint(&bytes);
+        assert!(diagnostics.contains(&error(
+            BITMAP + 4,
+            "image 0 is 300x300, but Windows refuses cursors over 256x256"
+        )));
+        // Nor are there the bytes for that many pixels.
+        assert!(diagnostics.contains(&error(
+            BITMAP + 40,
+            "pixels of image 0 are truncated: needed 360000 bytes, found 4224"
+        )));
+        // Diagnostics come sorted by where they are found.
+        assert!(diagnostics
+            .windows(2)
+            .all(|pair| pair[0].offset <= pair[1].offset));
+    }
+
+    #[test]
+    fn normal_cur_round_trips() {
+        let image = decode(NORMAL).unwrap();
+        let encoded = encode(&image);
+        assert_eq!(decode(&encoded).unwrap(), image);
+        assert_eq!(encoded, NORMAL);
+    }
+}
@@ -0,0 +1,370 @@
+//! GIF images, the usual home of animated prank material. Only decoding is supported: each
+//! image in the file becomes a patch of the animation, see `Animation`.
+
+use std::time::Duration;
+
+use crate::image::{CursorEntry, Hotspot};
+
+use super::{png::MAX_DIMENSION, Animation, Dispose, FormatError, Patch, Reader};
+
+const EXTENSION: u8 = 0x21;
+const IMAGE: u8 = 0x2c;
+const TRAILER: u8 = 0x3b;
+const GRAPHIC_CONTROL: u8 = 0xf9;
+
+/// The widest LZW code, which caps the code table at 4096 entries
+const MAX_CODE_BITS: u32 = 12;
+/// The first row and row step of each interlacing pass
+const INTERLACE: [(u32, u32); 4] = [(0, 8), (4, 8), (2, 4), (1, 2)];
+
+/// What a graphic control extension says about the image following it
+#[derive(Default)]
+struct Control {
+    delay: Duration,
+    dispose: Dispose,
+    transparent: Option<u8>,
+}
+
+/// Decodes every image of a GIF, along with the delays and disposal methods set for them.
+///
+/// Files that stop short of their trailer are accepted as long as they hold an image, since
+/// browsers play them and plenty are found in the wild.
+pub fn decode(bytes: &[u8]) -> Result<Animation, FormatError> {
+    let mut reader = Reader::new(bytes);
+    match reader.bytes(6).ok() {
+        Some(b"GIF87a") | Some(b"GIF89a") => {}
+        _ => return Err(FormatError::new(0, "missing GIF signature")),
+    }
+    let width = u32::from(reader.u16()?);
+    let height = u32::from(reader.u16()?);
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(FormatError::new(
+            6,
+            format!("unsupported canvas size {}x{}", width, height),
+        ));
+    }
+    let flags = reader.u8()?;
+    // The background colour and pixel aspect ratio. Browsers clear to transparent rather than
+    // to the background colour, and nobody sets the aspect ratio.
+    reader.bytes(2)?;
+    let global_palette = if flags & 0x80 != 0 {
+        Some(palette(&mut reader, flags)?)
+    } else {
+        None
+    };
+
+    let mut control = Control::default();
+    let mut frames = Vec::new();
+    loop {
+        if reader.remaining() == 0 && !frames.is_empty() {
+            break;
+        }
+        let offset = reader.position();
+        match reader.u8()? {
+            EXTENSION => {
+                let label = reader.u8()?;
+                let data_offset = reader.position();
+                let data = sub_blocks(&mut reader)?;
+                if label == GRAPHIC_CONTROL {
+                    control = decode_control(&data, data_offset)?;
+                }
+            }
+            IMAGE => {
+                let palette = global_palette.as_deref();
+                let canvas = (width, height);
+                frames.push(decode_image(
+                    &mut reader,
+                    offset,
+                    canvas,
+                    palette,
+                    &control,
+                )?);
+                // A graphic control extension only applies to the image right after it.
+                control = Control::default();
+            }
+            TRAILER => break,
+            kind => {
+                return Err(FormatError::new(
+                    offset,
+                    format!("unknown block type 0x{:02x}", kind),
+                ))
+            }
+        }
+    }
+    if frames.is_empty() {
+        return Err(FormatError::new(bytes.len(), "GIF has no images"));
+    }
+
+    Ok(Animation {
+        width,
+        height,
+        frames,
+    })
+}
+
+/// Reads a colour table of the size given in the low bits of `flags`.
+fn palette(reader: &mut Reader, flags: u8) -> Result<Vec<[u8; 3]>, FormatError> {
+    let len = 2 << (flags & 0x07);
+    Ok(reader
+        .bytes(len * 3)?
+        .chunks(3)
+        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+        .collect())
+}
+
+/// Reads a run of length-prefixed sub-blocks up to the empty one that ends it, joining their
+/// contents.
+fn sub_blocks(reader: &mut Reader) -> Result<Vec<u8>, FormatError> {
+    let mut data = Vec::new();
+    loop {
+        let len = usize::from(reader.u8()?);
+        if len == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(reader.bytes(len)?);
+    }
+}
+
+fn decode_control(data: &[u8], offset: usize) -> Result<Control, FormatError> {
+    if data.len() < 4 {
+        return Err(FormatError::new(
+            offset,
+            format!("graphic control extension of {} bytes", data.len()),
+        ));
+    }
+    let flags = data[0];
+    let dispose = match (flags >> 2) & 0x07 {
+        2 => Dispose::Background,
+        3 => Dispose::Previous,
+        // 0 leaves the choice to the viewer, and 4 to 7 are undefined.
+        _ => Dispose::Keep,
+    };
+    let centiseconds = u16::from_le_bytes([data[1], data[2]]);
+    Ok(Control {
+        delay: Duration::from_millis(u64::from(centiseconds) * 10),
+        dispose,
+        transparent: if flags & 0x01 != 0 {
+            Some(data[3])
+        } else {
+            None
+        },
+    })
+}
+
+fn decode_image(
+    reader: &mut Reader,
+    offset: usize,
+    canvas: (u32, u32),
+    global_palette: Option<&[[u8; 3]]>,
+    control: &Control,
+) -> Result<Patch, FormatError> {
+    let x = u32::from(reader.u16()?);
+    let y = u32::from(reader.u16()?);
+    let width = u32::from(reader.u16()?);
+    let height = u32::from(reader.u16()?);
+    if width == 0 || height == 0 {
+        return Err(FormatError::new(
+            offset + 5,
+            format!("unsupported image size {}x{}", width, height),
+        ));
+    }
+    // The canvas is at most MAX_DIMENSION across, so this also keeps the allocations below in
+    // bounds.
+    if width > canvas.0 || height > canvas.1 {
+        return Err(FormatError::new(
+            offset + 5,
+            format!(
+                "{}x{} image is larger than the {}x{} canvas",
+                width, height, canvas.0, canvas.1
+            ),
+        ));
+    }
+    let flags = reader.u8()?;
+    let local_palette = if flags & 0x80 != 0 {
+        Some(palette(reader, flags)?)
+    } else {
+        None
+    };
+    let palette = match local_palette.as_deref().or(global_palette) {
+        Some(palette) => palette,
+        None => return Err(FormatError::new(offset, "image has no colour table")),
+    };
+
+    let code_size_offset = reader.position();
+    let min_code_size = u32::from(reader.u8()?);
+    if !(2..=8).contains(&min_code_size) {
+        return Err(FormatError::new(
+            code_size_offset,
+            format!("LZW minimum code size {} is not allowed", min_code_size),
+        ));
+    }
+    let data = sub_blocks(reader)?;
+    let indices = decompress(&data, min_code_size, (width * height) as usize)
+        .map_err(|message| FormatError::new(code_size_offset + 1, message))?;
+
+    let rows: Vec<u32> = if flags & 0x40 != 0 {
+        INTERLACE
+            .iter()
+            .flat_map(|&(first, step)| (first..height).step_by(step as usize))
+            .collect()
+    } else {
+        (0..height).collect()
+    };
+    // Pixels missing from a short image stay transparent.
+    let mut image = CursorEntry::new(width, height, Hotspot::default());
+    for (i, &index) in indices.iter().enumerate() {
+        let (column, row) = (i as u32 % width, rows[i / width as usize]);
+        if control.transparent == Some(index) {
+            continue;
+        }
+        if let Some(&[r, g, b]) = palette.get(usize::from(index)) {
+            image.set_pixel(column, row, [r, g, b, 0xff]);
+        }
+    }
+
+    Ok(Patch {
+        x,
+        y,
+        image,
+        delay: control.delay,
+        dispose: control.dispose,
+        blend: true,
+    })
+}
+
+/// Undoes GIF's variable-width LZW compression, stopping after `len` colour indices.
+fn decompress(data: &[u8], min_code_size: u32, len: usize) -> Result<Vec<u8>, String> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    // Each code stands for the string of the code before it plus one more byte.
+    let mut prefixes = vec![0u16; 1 << MAX_CODE_BITS];
+    let mut suffixes = vec![0u8; 1 << MAX_CODE_BITS];
+    let mut firsts = vec![0u8; 1 << MAX_CODE_BITS];
+    let mut lengths = vec![0u16; 1 << MAX_CODE_BITS];
+    for code in 0..clear {
+        suffixes[usize::from(code)] = code as u8;
+        firsts[usize::from(code)] = code as u8;
+        lengths[usize::from(code)] = 1;
+    }
+
+    let mut out = Vec::with_capacity(len);
+    let mut next = clear + 2;
+    let mut code_bits = min_code_size + 1;
+    let mut previous: Option<u16> = None;
+    let (mut buffer, mut buffered) = (0u32, 0u32);
+    let mut bytes = data.iter();
+
+    while out.len() < len {
+        while buffered < code_bits {
+            match bytes.next() {
+                Some(&byte) => {
+                    buffer |= u32::from(byte) << buffered;
+                    buffered += 8;
+                }
+                // Running out before the end code is common enough to forgive.
+                None => return Ok(out),
+            }
+        }
+        let code = (buffer & ((1 << code_bits) - 1)) as u16;
+        buffer >>= code_bits;
+        buffered -= code_bits;
+
+        if code == clear {
+            next = clear + 2;
+            code_bits = min_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let previous_code = match previous {
+            Some(previous_code) => previous_code,
+            None if code < clear => {
+                out.push(code as u8);
+                previous = Some(code);
+                continue;
+            }
+            None => return Err(format!("LZW code {} before any colour", code)),
+        };
+        if code > next {
+            return Err(format!("LZW code {} is not in the table", code));
+        }
+
+        if usize::from(next) < prefixes.len() {
+            // A code one past the table is the previous string plus its own first byte.
+            let first = if code == next {
+                firsts[usize::from(previous_code)]
+            } else {
+                firsts[usize::from(code)]
+            };
+            let new = usize::from(next);
+            prefixes[new] = previous_code;
+            suffixes[new] = first;
+            firsts[new] = firsts[usize::from(previous_code)];
+            lengths[new] = lengths[usize::from(previous_code)] + 1;
+            next += 1;
+            if u32::from(next) == 1 << code_bits && code_bits < MAX_CODE_BITS {
+                code_bits += 1;
+            }
+        } else if code == next {
+            return Err(format!("LZW code {} is not in the table", code));
+        }
+
+        // Strings are linked from their last byte, so they are written back to front.
+        let start = out.len();
+        out.resize(start + usize::from(lengths[usize::from(code)]), 0);
+        let mut link = code;
+        for byte in out[start..].iter_mut().rev() {
+            *byte = suffixes[usize::from(link)];
+            link = prefixes[usize::from(link)];
+        }
+        previous = Some(code);
+    }
+
+    out.truncate(len);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GIF with a 2x1 canvas and one `width`x`height` image of a single red pixel
+    fn gif(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        // A global palette of two colours, red and black
+        bytes.extend_from_slice(&[0x80, 0, 0, 0xff, 0, 0, 0, 0, 0]);
+        bytes.push(IMAGE);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.push(0);
+        // Minimum code size 2, then the codes clear, 0 and end in 3 bits each
+        bytes.extend_from_slice(&[2, 2, 0x44, 0x01, 0]);
+        bytes.push(TRAILER);
+        bytes
+    }
+
+    #[test]
+    fn decodes_an_image() {
+        let animation = decode(&gif(1, 1)).unwrap();
+        assert_eq!((animation.width, animation.height), (2, 1));
+        assert_eq!(animation.frames.len(), 1);
+        assert_eq!(animation.frames[0].image.pixel(0, 0), [0xff, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn image_larger_than_canvas_is_an_error() {
+        let err = decode(&gif(u16::MAX, u16::MAX)).unwrap_err();
+        assert!(
+            err.to_string().contains("larger than the 2x1 canvas"),
+            "{}",
+            err
+        );
+        assert!(decode(&gif(3, 1)).is_err());
+        assert!(decode(&gif(2, 2)).is_err());
+    }
+}
@@ -0,0 +1,696 @@
+//! PNG images, which is what cursors are drawn as in ordinary image editors, including the
+//! animated kind (APNG).
+
+use std::time::Duration;
+
+use crate::image::{CursorEntry, Hotspot};
+
+use super::{zlib, Animation, Dispose, FormatError, Patch, Reader};
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+/// Far larger than any cursor, but small enough that a hostile header can't exhaust memory
+pub const MAX_DIMENSION: u32 = 4096;
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+/// The starting column and row, and the column and row steps, of each Adam7 interlacing pass
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+#[derive(Clone, Copy)]
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> u32 {
+        match self.color_type {
+            GRAY | PALETTE => 1,
+            GRAY_ALPHA => 2,
+            RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> u32 {
+        self.channels() * u32::from(self.bit_depth)
+    }
+
+    /// Bytes in a row of `width` pixels, not counting the filter type byte
+    fn stride(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel() as usize).div_ceil(8)
+    }
+}
+
+/// The parts of a PNG needed to draw it, with the chunks checked but the image data still
+/// compressed
+struct Chunks {
+    header: Header,
+    palette: Vec<[u8; 4]>,
+    transparency: Transparency,
+    /// The image shown by viewers that don't animate
+    image: ImageData,
+    /// The `fcTL` chunk that makes the image the first frame of the animation, if there is one
+    image_control: Option<FrameControl>,
+    /// The frames of an APNG after the image, each with its `fdAT` data
+    frames: Vec<(FrameControl, ImageData)>,
+}
+
+/// Compressed image data joined up from its chunks, and where in the file each chunk's share
+/// of it came from
+#[derive(Default)]
+struct ImageData {
+    data: Vec<u8>,
+    chunks: Vec<(usize, usize)>,
+}
+
+impl ImageData {
+    fn push(&mut self, body: &[u8], offset: usize) {
+        self.chunks.push((self.data.len(), offset));
+        self.data.extend_from_slice(body);
+    }
+
+    /// The file offset of the compressed data, for errors about it as a whole
+    fn offset(&self) -> usize {
+        self.chunks.first().map_or(8, |&(_, offset)| offset)
+    }
+}
+
+/// Where and how an APNG frame is drawn, from its `fcTL` chunk
+struct FrameControl {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    delay: Duration,
+    dispose: Dispose,
+    blend: bool,
+}
+
+/// Decodes a PNG into a single cursor entry with its hotspot in the top left corner. The
+/// frames of animated PNGs are ignored, leaving the image viewers without animation show.
+pub fn decode(bytes: &[u8]) -> Result<CursorEntry, FormatError> {
+    let chunks = read_chunks(bytes)?;
+    decode_image(
+        &chunks,
+        chunks.header.width,
+        chunks.header.height,
+        &chunks.image,
+    )
+}
+
+/// Decodes the frames of an animated PNG. A PNG without animation is a single frame covering
+/// the whole canvas.
+pub fn decode_animation(bytes: &[u8]) -> Result<Animation, FormatError> {
+    let chunks = read_chunks(bytes)?;
+    let (width, height) = (chunks.header.width, chunks.header.height);
+    if chunks.image_control.is_none() && chunks.frames.is_empty() {
+        let image = decode_image(&chunks, width, height, &chunks.image)?;
+        let frame = Patch {
+            x: 0,
+            y: 0,
+            image,
+            delay: Duration::default(),
+            dispose: Dispose::Keep,
+            blend: false,
+        };
+        return Ok(Animation {
+            width,
+            height,
+            frames: vec![frame],
+        });
+    }
+
+    let first = chunks
+        .image_control
+        .as_ref()
+        .map(|control| (control, &chunks.image));
+    let rest = chunks.frames.iter().map(|(control, data)| (control, data));
+    let mut frames = Vec::with_capacity(chunks.frames.len() + 1);
+    for (control, data) in first.into_iter().chain(rest) {
+        if data.data.is_empty() {
+            return Err(FormatError::new(data.offset(), "frame has no fdAT chunk"));
+        }
+        frames.push(Patch {
+            x: control.x,
+            y: control.y,
+            image: decode_image(&chunks, control.width, control.height, data)?,
+            delay: control.delay,
+            dispose: control.dispose,
+            blend: control.blend,
+        });
+    }
+    Ok(Animation {
+        width,
+        height,
+        frames,
+    })
+}
+
+fn read_chunks(bytes: &[u8]) -> Result<Chunks, FormatError> {
+    let mut reader = Reader::new(bytes);
+    if reader.bytes(8).ok() != Some(&SIGNATURE[..]) {
+        return Err(FormatError::new(0, "missing PNG signature"));
+    }
+
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut transparency = None;
+    let mut image = ImageData::default();
+    let mut image_control = None;
+    let mut frames: Vec<(FrameControl, ImageData)> = Vec::new();
+
+    loop {
+        let chunk_offset = reader.position();
+        let len = reader.u32_be()? as usize;
+        let kind = reader.tag()?;
+        let body = reader.bytes(len)?;
+        let crc = reader.u32_be()?;
+        if crc32(&[&kind[..], body]) != crc {
+            return Err(FormatError::new(
+                chunk_offset + 8 + len,
+                format!("{} chunk checksum does not match", tag_name(kind)),
+            ));
+        }
+        let body_offset = chunk_offset + 8;
+
+        if header.is_none() && &kind != b"IHDR" {
+            return Err(FormatError::new(chunk_offset, "first chunk is not IHDR"));
+        }
+        match &kind {
+            b"IHDR" => header = Some(decode_header(body, body_offset)?),
+            b"PLTE" => {
+                if !len.is_multiple_of(3) || len > 256 * 3 {
+                    return Err(FormatError::new(
+                        chunk_offset,
+                        format!("palette of {} bytes", len),
+                    ));
+                }
+                palette = body
+                    .chunks(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2], 0xff])
+                    .collect();
+            }
+            b"tRNS" => transparency = Some((body, body_offset)),
+            b"IDAT" => image.push(body, body_offset),
+            // A frame control before the image makes the image part of the animation.
+            b"fcTL" => {
+                let control = decode_frame_control(header.as_ref().unwrap(), body, body_offset)?;
+                if image.data.is_empty() {
+                    image_control = Some(control);
+                } else {
+                    frames.push((control, ImageData::default()));
+                }
+            }
+            b"fdAT" => match frames.last_mut() {
+                // The data follows a sequence number.
+                Some((_, data)) if len >= 4 => data.push(&body[4..], body_offset + 4),
+                _ => {
+                    return Err(FormatError::new(
+                        chunk_offset,
+                        "fdAT chunk is not part of a frame",
+                    ))
+                }
+            },
+            b"IEND" => break,
+            // Lowercase first letters mark chunks that are safe to ignore.
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => {
+                return Err(FormatError::new(
+                    chunk_offset + 4,
+                    format!("unsupported critical chunk {}", tag_name(kind)),
+                ))
+            }
+        }
+    }
+
+    let header = header.unwrap();
+    if header.color_type == PALETTE && palette.is_empty() {
+        return Err(FormatError::new(8, "palette image has no PLTE chunk"));
+    }
+    if image.data.is_empty() {
+        return Err(FormatError::new(8, "image has no IDAT chunk"));
+    }
+    let transparency = match transparency {
+        Some((body, offset)) => Transparency::new(&header, body, offset, &mut palette)?,
+        None => Transparency::None,
+    };
+
+    Ok(Chunks {
+        header,
+        palette,
+        transparency,
+        image,
+        image_control,
+        frames,
+    })
+}
+
+/// Decompresses and unfilters a `width` by `height` image in the colour format of the PNG.
+fn decode_image(
+    chunks: &Chunks,
+    width: u32,
+    height: u32,
+    data: &ImageData,
+) -> Result<CursorEntry, FormatError> {
+    let header = Header {
+        width,
+        height,
+        ..chunks.header
+    };
+
+    // Map offsets in the compressed data back to the file.
+    let expected = filtered_len(&header);
+    let raw = zlib::decompress(&data.data, expected).map_err(|err| {
+        let (start, file_offset) = data
+            .chunks
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= err.offset())
+            .copied()
+            .unwrap_or((0, 0));
+        FormatError::new(file_offset + err.offset() - start, err.message())
+    })?;
+    if raw.len() != expected {
+        return Err(FormatError::new(
+            data.offset(),
+            format!(
+                "image data is {} bytes but should be {}",
+                raw.len(),
+                expected
+            ),
+        ));
+    }
+
+    let mut entry = CursorEntry::new(width, height, Hotspot::default());
+    let mut rows = raw.as_slice();
+    for (x0, y0, dx, dy, width, height) in passes(&header) {
+        let stride = header.stride(width);
+        let (pass, rest) = rows.split_at((stride + 1) * height as usize);
+        rows = rest;
+        let pixels = unfilter(&header, pass, stride, data.offset())?;
+        for row in 0..height {
+            let line = &pixels[row as usize * stride..(row as usize + 1) * stride];
+            for column in 0..width {
+                let rgba = pixel(&header, line, column, &chunks.palette, &chunks.transparency);
+                entry.set_pixel(x0 + column * dx, y0 + row * dy, rgba);
+            }
+        }
+    }
+
+    Ok(entry)
+}
+
+fn decode_frame_control(
+    header: &Header,
+    body: &[u8],
+    offset: usize,
+) -> Result<FrameControl, FormatError> {
+    if body.len() != 26 {
+        return Err(FormatError::new(offset, "fcTL chunk is not 26 bytes"));
+    }
+    let mut reader = Reader::new(&body[4..]);
+    let width = reader.u32_be()?;
+    let height = reader.u32_be()?;
+    let x = reader.u32_be()?;
+    let y = reader.u32_be()?;
+    let fits = |position: u32, len: u32, canvas: u32| {
+        position.checked_add(len).is_some_and(|end| end <= canvas)
+    };
+    if width == 0 || height == 0 || !fits(x, width, header.width) || !fits(y, height, header.height)
+    {
+        return Err(FormatError::new(
+            offset + 4,
+            format!(
+                "{}x{} frame at {},{} is outside the {}x{} image",
+                width, height, x, y, header.width, header.height
+            ),
+        ));
+    }
+
+    let numerator = u16::from_be_bytes([body[20], body[21]]);
+    // A denominator of zero means hundredths of a second.
+    let denominator = match u16::from_be_bytes([body[22], body[23]]) {
+        0 => 100,
+        denominator => denominator,
+    };
+    let dispose = match body[24] {
+        0 => Dispose::Keep,
+        1 => Dispose::Background,
+        2 => Dispose::Previous,
+        other => {
+            return Err(FormatError::new(
+                offset + 24,
+                format!("unknown dispose operation {}", other),
+            ))
+        }
+    };
+    let blend = match body[25] {
+        0 => false,
+        1 => true,
+        other => {
+            return Err(FormatError::new(
+                offset + 25,
+                format!("unknown blend operation {}", other),
+            ))
+        }
+    };
+
+    Ok(FrameControl {
+        width,
+        height,
+        x,
+        y,
+        delay: Duration::from_secs_f64(f64::from(numerator) / f64::from(denominator)),
+        dispose,
+        blend,
+    })
+}
+
+fn decode_header(body: &[u8], offset: usize) -> Result<Header, FormatError> {
+    if body.len() != 13 {
+        return Err(FormatError::new(offset, "IHDR chunk is not 13 bytes"));
+    }
+    let mut reader = Reader::new(body);
+    let width = reader.u32_be()?;
+    let height = reader.u32_be()?;
+    let header = Header {
+        width,
+        height,
+        bit_depth: body[8],
+        color_type: body[9],
+        interlaced: body[12] == 1,
+    };
+
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(FormatError::new(
+            offset,
+            format!("unsupported image size {}x{}", width, height),
+        ));
+    }
+    let depth_allowed = match header.color_type {
+        GRAY => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+        PALETTE => [1, 2, 4, 8].contains(&header.bit_depth),
+        RGB | GRAY_ALPHA | RGBA => [8, 16].contains(&header.bit_depth),
+        _ => {
+            return Err(FormatError::new(
+                offset + 9,
+                format!("unknown colour type {}", header.color_type),
+            ))
+        }
+    };
+    if !depth_allowed {
+        return Err(FormatError::new(
+            offset + 8,
+            format!(
+                "bit depth {} is not allowed for colour type {}",
+                header.bit_depth, header.color_type
+            ),
+        ));
+    }
+    if body[10] != 0 || body[11] != 0 {
+        return Err(FormatError::new(
+            offset + 10,
+            "unknown compression or filter method",
+        ));
+    }
+    if body[12] > 1 {
+        return Err(FormatError::new(offset + 12, "unknown interlace method"));
+    }
+
+    Ok(header)
+}
+
+/// Where each pass of the image starts, its steps, and its size in pixels. Images that are not
+/// interlaced are a single pass.
+fn passes(header: &Header) -> Vec<(u32, u32, u32, u32, u32, u32)> {
+    if !header.interlaced {
+        return vec![(0, 0, 1, 1, header.width, header.height)];
+    }
+    ADAM7
+        .iter()
+        .map(|&(x0, y0, dx, dy)| {
+            let width = header.width.saturating_sub(x0).div_ceil(dx);
+            let height = header.height.saturating_sub(y0).div_ceil(dy);
+            (x0, y0, dx, dy, width, height)
+        })
+        .filter(|&(.., width, height)| width > 0 && height > 0)
+        .collect()
+}
+
+/// The decompressed size of the image: every row of every pass plus its filter type byte
+fn filtered_len(header: &Header) -> usize {
+    passes(header)
+        .iter()
+        .map(|&(.., width, height)| (header.stride(width) + 1) * height as usize)
+        .sum()
+}
+
+/// Encodes an entry as an 8-bit RGBA PNG. PNG has no place for a hotspot, so it is lost.
+pub fn encode(entry: &CursorEntry) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&entry.width.to_be_bytes());
+    header.extend_from_slice(&entry.height.to_be_bytes());
+    // Bit depth, colour type, compression, filter method and no interlacing
+    header.extend_from_slice(&[8, RGBA, 0, 0, 0]);
+
+    let stride = entry.width as usize * 4;
+    let mut filtered = Vec::with_capacity((stride + 1) * entry.height as usize);
+    let mut previous: Option<&[u8]> = None;
+    for row in entry.pixels.chunks(stride) {
+        filter_row(row, previous, &mut filtered);
+        previous = Some(row);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &zlib::compress(&filtered));
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out.extend_from_slice(&crc32(&[kind, body]).to_be_bytes());
+}
+
+/// Appends `row` to `out` under whichever filter leaves the smallest differences, which
+/// usually compresses best.
+fn filter_row(row: &[u8], previous: Option<&[u8]>, out: &mut Vec<u8>) {
+    const BPP: usize = 4;
+    let filter = |filter: u8, i: usize| {
+        let left = if i >= BPP { row[i - BPP] } else { 0 };
+        let up = previous.map_or(0, |previous| previous[i]);
+        let up_left = match previous {
+            Some(previous) if i >= BPP => previous[i - BPP],
+            _ => 0,
+        };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        row[i].wrapping_sub(predicted)
+    };
+
+    let cost = |kind: u8| -> u32 {
+        (0..row.len())
+            .map(|i| u32::from((filter(kind, i) as i8).unsigned_abs()))
+            .sum()
+    };
+    let best = (0..5).min_by_key(|&kind| cost(kind)).unwrap_or(0);
+    out.push(best);
+    out.extend((0..row.len()).map(|i| filter(best, i)));
+}
+
+/// Undoes the per-row filters of one pass, returning its rows without the filter type bytes.
+fn unfilter(
+    header: &Header,
+    data: &[u8],
+    stride: usize,
+    offset: usize,
+) -> Result<Vec<u8>, FormatError> {
+    let bpp = (header.bits_per_pixel() as usize).div_ceil(8);
+    let mut out = vec![0u8; data.len() / (stride + 1) * stride];
+
+    for (row, line) in data.chunks(stride + 1).enumerate() {
+        let (filter, line) = (line[0], &line[1..]);
+        let (done, current) = out.split_at_mut(row * stride);
+        let previous = if row == 0 {
+            None
+        } else {
+            Some(&done[(row - 1) * stride..])
+        };
+        let current = &mut current[..stride];
+
+        for i in 0..stride {
+            let left = if i >= bpp { current[i - bpp] } else { 0 };
+            let up = previous.map_or(0, |previous| previous[i]);
+            let up_left = match previous {
+                Some(previous) if i >= bpp => previous[i - bpp],
+                _ => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => {
+                    return Err(FormatError::new(
+                        offset,
+                        format!("unknown filter type {} on row {}", filter, row),
+                    ))
+                }
+            };
+            current[i] = line[i].wrapping_add(predicted);
+        }
+    }
+
+    Ok(out)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance = |value: u8| (estimate - i16::from(value)).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// The single colour a `tRNS` chunk makes transparent, for images without an alpha channel
+enum Transparency {
+    None,
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+impl Transparency {
+    /// Palette transparency is applied to the palette straight away.
+    fn new(
+        header: &Header,
+        body: &[u8],
+        offset: usize,
+        palette: &mut [[u8; 4]],
+    ) -> Result<Self, FormatError> {
+        let sample = |i: usize| u16::from_be_bytes([body[i], body[i + 1]]);
+        match header.color_type {
+            PALETTE if body.len() <= palette.len() => {
+                for (color, &alpha) in palette.iter_mut().zip(body) {
+                    color[3] = alpha;
+                }
+                Ok(Transparency::None)
+            }
+            GRAY if body.len() == 2 => Ok(Transparency::Gray(sample(0))),
+            RGB if body.len() == 6 => Ok(Transparency::Rgb(sample(0), sample(2), sample(4))),
+            _ => Err(FormatError::new(
+                offset,
+                "tRNS chunk does not fit the colour type",
+            )),
+        }
+    }
+}
+
+fn pixel(
+    header: &Header,
+    line: &[u8],
+    column: u32,
+    palette: &[[u8; 4]],
+    transparency: &Transparency,
+) -> [u8; 4] {
+    let depth = u32::from(header.bit_depth);
+    let channels = header.channels();
+    let sample = |channel: u32| -> u16 {
+        let bit = (column * channels + channel) * depth;
+        let byte = (bit / 8) as usize;
+        match depth {
+            16 => u16::from_be_bytes([line[byte], line[byte + 1]]),
+            8 => u16::from(line[byte]),
+            _ => u16::from(line[byte] >> (8 - depth - bit % 8)) & ((1 << depth) - 1),
+        }
+    };
+    // Scales a sample up or down to eight bits.
+    let scale = |value: u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            8 => value as u8,
+            _ => (u32::from(value) * 255 / ((1 << depth) - 1)) as u8,
+        }
+    };
+
+    match header.color_type {
+        PALETTE => palette
+            .get(usize::from(sample(0)))
+            .copied()
+            .unwrap_or([0, 0, 0, 0xff]),
+        GRAY => {
+            let gray = sample(0);
+            let alpha = match transparency {
+                Transparency::Gray(key) if *key == gray => 0,
+                _ => 0xff,
+            };
+            let gray = scale(gray);
+            [gray, gray, gray, alpha]
+        }
+        GRAY_ALPHA => {
+            let gray = scale(sample(0));
+            [gray, gray, gray, scale(sample(1))]
+        }
+        RGB => {
+            let (r, g, b) = (sample(0), sample(1), sample(2));
+            let alpha = match transparency {
+                Transparency::Rgb(kr, kg, kb) if (*kr, *kg, *kb) == (r, g, b) => 0,
+                _ => 0xff,
+            };
+            [scale(r), scale(g), scale(b), alpha]
+        }
+        _ => [
+            scale(sample(0)),
+            scale(sample(1)),
+            scale(sample(2)),
+            scale(sample(3)),
+        ],
+    }
+}
+
+fn tag_name(kind: [u8; 4]) -> String {
+    String::from_utf8_lossy(&kind).into_owned()
+}
+
+/// The CRC-32 PNG puts after every chunk, computed over the concatenation of `parts`
+pub fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for part in parts {
+        for &byte in *part {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+    !crc
+}
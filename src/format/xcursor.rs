@@ -0,0 +1,298 @@
+//! Xcursor files, as used by X11 cursor themes: a header and table of contents pointing at
+//! chunks, of which only the image chunks matter to us. Each image has a nominal size, and the
+//! images sharing a nominal size make up the frames of an animation.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::image::{AnimatedCursor, AnimationFrame, CursorEntry, CursorImage, Hotspot};
+
+use super::{Format, FormatError, Reader};
+
+const MAGIC: &[u8; 4] = b"Xcur";
+const HEADER_LEN: u32 = 16;
+const VERSION: u32 = 0x0001_0000;
+const TOC_ENTRY_LEN: usize = 12;
+
+const IMAGE_TYPE: u32 = 0xfffd_0002;
+const IMAGE_HEADER_LEN: u32 = 36;
+const IMAGE_VERSION: u32 = 1;
+
+/// An image chunk, with the delay that doesn't fit in a `CursorEntry`
+struct Image {
+    entry: CursorEntry,
+    delay: Duration,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<AnimatedCursor, FormatError> {
+    let mut reader = Reader::new(bytes);
+
+    if &reader.tag()? != MAGIC {
+        return Err(FormatError::new(0, "missing Xcur signature"));
+    }
+    let header_len = reader.u32()?;
+    if header_len < HEADER_LEN {
+        return Err(FormatError::new(
+            4,
+            format!("header size {} is too small", header_len),
+        ));
+    }
+    let _version = reader.u32()?;
+    let toc_len = reader.u32()? as usize;
+    reader.seek(header_len as usize)?;
+    if toc_len > reader.remaining() / TOC_ENTRY_LEN {
+        return Err(FormatError::new(
+            12,
+            format!("table of contents claims {} entries", toc_len),
+        ));
+    }
+
+    // Images of the same nominal size are the frames of one animation, in file order.
+    let mut sizes: BTreeMap<u32, Vec<Image>> = BTreeMap::new();
+    for _ in 0..toc_len {
+        let toc_offset = reader.position();
+        let kind = reader.u32()?;
+        let nominal_size = reader.u32()?;
+        let position = reader.u32()? as usize;
+        // Comments and anything else we don't know are skipped.
+        if kind != IMAGE_TYPE {
+            continue;
+        }
+
+        let mut chunk = Reader::new(bytes);
+        chunk.seek(position).map_err(|_| {
+            FormatError::new(
+                toc_offset + 8,
+                format!("chunk offset {} is past the end of the file", position),
+            )
+        })?;
+        let image = decode_image(&mut chunk, nominal_size)?;
+        sizes.entry(nominal_size).or_default().push(image);
+    }
+
+    let frame_count = sizes.values().map(Vec::len).max().unwrap_or(0);
+    if frame_count == 0 {
+        return Err(FormatError::new(12, "cursor contains no images"));
+    }
+
+    // Sizes with fewer frames than the longest animation are cycled to keep up with it, and the
+    // longest animation sets the pace.
+    let pace = sizes
+        .values()
+        .find(|images| images.len() == frame_count)
+        .unwrap();
+    let frames = (0..frame_count)
+        .map(|index| {
+            let entries = sizes
+                .values()
+                .map(|images| images[index % images.len()].entry.clone())
+                .collect();
+            AnimationFrame::new(CursorImage::new(entries), pace[index].delay)
+        })
+        .collect();
+
+    Ok(AnimatedCursor::new(frames))
+}
+
+fn decode_image(reader: &mut Reader, nominal_size: u32) -> Result<Image, FormatError> {
+    let start = reader.position();
+
+    let header_len = reader.u32()?;
+    if header_len < IMAGE_HEADER_LEN {
+        return Err(FormatError::new(
+            start,
+            format!("image header size {} is too small", header_len),
+        ));
+    }
+    let kind = reader.u32()?;
+    let subtype = reader.u32()?;
+    if kind != IMAGE_TYPE || subtype != nominal_size {
+        return Err(FormatError::new(
+            start + 4,
+            "image chunk does not match its table of contents entry",
+        ));
+    }
+    let version = reader.u32()?;
+    if version > IMAGE_VERSION {
+        return Err(FormatError::new(
+            start + 12,
+            format!("unsupported image version {}", version),
+        ));
+    }
+
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    // Larger images are rejected by libXcursor.
+    let max_size = Format::Xcursor.max_size();
+    if width == 0 || height == 0 || width > max_size || height > max_size {
+        return Err(FormatError::new(
+            start + 16,
+            format!("unsupported image size {}x{}", width, height),
+        ));
+    }
+    let x_hot = reader.u32()?;
+    let y_hot = reader.u32()?;
+    // libXcursor allows the hotspot on the far edge, so we do too.
+    if x_hot > width || y_hot > height {
+        return Err(FormatError::new(
+            start + 24,
+            format!(
+                "hotspot ({}, {}) is outside the {}x{} image",
+                x_hot, y_hot, width, height
+            ),
+        ));
+    }
+    let delay = reader.u32()?;
+
+    reader.seek(start + header_len as usize)?;
+    // The pixels are read before the entry is made, so a file claiming a huge image fails on its
+    // length rather than allocating for it. At most 32767x32767x4 bytes, this fits in a u32.
+    let pixels = reader.bytes(width as usize * height as usize * 4)?;
+    let mut entry = CursorEntry::new(width, height, Hotspot::new(x_hot as u16, y_hot as u16));
+    entry.nominal_size = nominal_size;
+    for (i, argb) in pixels.chunks_exact(4).enumerate() {
+        let argb = u32::from_le_bytes([argb[0], argb[1], argb[2], argb[3]]);
+        let i = i as u32;
+        entry.set_pixel(i % width, i / width, unpremultiplied_rgba(argb));
+    }
+
+    Ok(Image {
+        entry,
+        delay: Duration::from_millis(delay.into()),
+    })
+}
+
+/// Encodes every size of every frame, grouped by nominal size so each size plays the whole
+/// animation. Frames lacking an image of some size contribute their nearest one instead.
+pub fn encode(cursor: &AnimatedCursor) -> Vec<u8> {
+    let mut sizes: Vec<u32> = cursor
+        .frames
+        .iter()
+        .flat_map(|frame| frame.image.entries.iter().map(|entry| entry.nominal_size))
+        .collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let mut chunks = Vec::new();
+    for &size in &sizes {
+        for frame in &cursor.frames {
+            if let Some(entry) = frame.image.nearest(size) {
+                chunks.push((size, encode_image(entry, size, frame.delay)));
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&HEADER_LEN.to_le_bytes());
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+
+    let mut position = HEADER_LEN as usize + TOC_ENTRY_LEN * chunks.len();
+    for (size, chunk) in &chunks {
+        out.extend_from_slice(&IMAGE_TYPE.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&(position as u32).to_le_bytes());
+        position += chunk.len();
+    }
+
+    for (_, chunk) in chunks {
+        out.extend_from_slice(&chunk);
+    }
+
+    out
+}
+
+fn encode_image(entry: &CursorEntry, nominal_size: u32, delay: Duration) -> Vec<u8> {
+    let mut out = Vec::with_capacity(IMAGE_HEADER_LEN as usize + entry.pixels.len());
+    out.extend_from_slice(&IMAGE_HEADER_LEN.to_le_bytes());
+    out.extend_from_slice(&IMAGE_TYPE.to_le_bytes());
+    out.extend_from_slice(&nominal_size.to_le_bytes());
+    out.extend_from_slice(&IMAGE_VERSION.to_le_bytes());
+    out.extend_from_slice(&entry.width.to_le_bytes());
+    out.extend_from_slice(&entry.height.to_le_bytes());
+    out.extend_from_slice(&u32::from(entry.hotspot.x).to_le_bytes());
+    out.extend_from_slice(&u32::from(entry.hotspot.y).to_le_bytes());
+    out.extend_from_slice(&(delay.as_millis() as u32).to_le_bytes());
+
+    for rgba in entry.pixels.chunks(4) {
+        out.extend_from_slice(&premultiplied_argb(rgba).to_le_bytes());
+    }
+
+    out
+}
+
+/// Xcursor wants ARGB with the colour channels premultiplied by alpha.
+pub fn premultiplied_argb(rgba: &[u8]) -> u32 {
+    let alpha = u32::from(rgba[3]);
+    let premultiply = |channel: u8| (u32::from(channel) * alpha + 127) / 255;
+    alpha << 24 | premultiply(rgba[0]) << 16 | premultiply(rgba[1]) << 8 | premultiply(rgba[2])
+}
+
+/// Undoes `premultiplied_argb`, as far as rounding allows.
+pub fn unpremultiplied_rgba(argb: u32) -> [u8; 4] {
+    let alpha = argb >> 24;
+    let unpremultiply = |shift: u32| {
+        let channel = (argb >> shift) & 0xff;
+        (channel * 255 + alpha / 2)
+            .checked_div(alpha)
+            .map_or(0, |channel| channel.min(255) as u8)
+    };
+    [
+        unpremultiply(16),
+        unpremultiply(8),
+        unpremultiply(0),
+        alpha as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file with one image chunk claiming `width`x`height` but holding no pixels
+    fn empty_image(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        for value in &[HEADER_LEN, VERSION, 1, IMAGE_TYPE, 32, HEADER_LEN + 12] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in &[
+            IMAGE_HEADER_LEN,
+            IMAGE_TYPE,
+            32,
+            IMAGE_VERSION,
+            width,
+            height,
+            0,
+            0,
+            0,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn huge_image_without_pixels_is_an_error() {
+        let max_size = Format::Xcursor.max_size();
+        let err = decode(&empty_image(max_size, max_size)).unwrap_err();
+        assert!(
+            err.to_string().contains("unexpected end of file"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut entry = CursorEntry::new(3, 2, Hotspot::new(1, 1));
+        entry.nominal_size = 32;
+        entry.set_pixel(0, 0, [255, 0, 0, 255]);
+        entry.set_pixel(2, 1, [0, 0, 255, 255]);
+        let cursor = AnimatedCursor::new(vec![AnimationFrame::new(
+            CursorImage::new(vec![entry]),
+            Duration::from_millis(50),
+        )]);
+        assert_eq!(decode(&encode(&cursor)).unwrap(), cursor);
+    }
+}
@@ -0,0 +1,491 @@
+//! The zlib container around DEFLATE data, as used by PNG.
+
+use super::FormatError;
+
+/// Bit lengths of the code length alphabet are sent in this order
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const MAX_CODE_LEN: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+
+/// How far back matches may reach
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// How many earlier positions with the same hash are tried before settling for the best so far
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// Decompresses a zlib stream, refusing to produce more than `limit` bytes. Error offsets are
+/// relative to the start of `data`.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, FormatError> {
+    if data.len() < 2 {
+        return Err(FormatError::new(0, "zlib stream is too short"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 {
+        return Err(FormatError::new(0, "zlib stream is not deflate compressed"));
+    }
+    if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(FormatError::new(1, "zlib header check failed"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(FormatError::new(
+            1,
+            "zlib preset dictionaries are not supported",
+        ));
+    }
+
+    let mut bits = Bits::new(data, 2);
+    let out = inflate(&mut bits, limit)?;
+
+    let pos = bits.byte_position();
+    let trailer = data
+        .get(pos..pos + 4)
+        .ok_or_else(|| FormatError::new(pos, "zlib stream is missing its checksum"))?;
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if adler32(&out) != expected {
+        return Err(FormatError::new(
+            pos,
+            "zlib checksum does not match the data",
+        ));
+    }
+
+    Ok(out)
+}
+
+/// Compresses `data` into a zlib stream.
+///
+/// Matches are found greedily through hash chains and written with the fixed Huffman codes, so
+/// the output is not as small as zlib's, but cursor sized images come out close enough.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, and a check value making the header a multiple of 31
+    let mut writer = BitWriter {
+        out: vec![0x78, 0x9c],
+        buffer: 0,
+        count: 0,
+    };
+    writer.bits(1, 1); // the last block
+    writer.bits(1, 2); // compressed with the fixed codes
+
+    let hash = |pos: usize| {
+        let key =
+            u32::from(data[pos]) << 16 | u32::from(data[pos + 1]) << 8 | u32::from(data[pos + 2]);
+        (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |pos: usize, head: &mut [usize], previous: &mut [usize]| {
+        if pos + MIN_MATCH <= data.len() {
+            let hash = hash(pos);
+            previous[pos % WINDOW_SIZE] = head[hash];
+            head[hash] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_len, mut best_distance) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(pos)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+                    break;
+                }
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best_len = len;
+                    best_distance = pos - candidate;
+                    if len == max_len {
+                        break;
+                    }
+                }
+                let next = previous[candidate % WINDOW_SIZE];
+                // Older entries of the ring buffer have been overwritten by newer positions.
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            writer.length(best_len);
+            writer.distance(best_distance);
+            for skipped in pos..pos + best_len {
+                insert(skipped, &mut head, &mut previous);
+            }
+            pos += best_len;
+        } else {
+            writer.literal(u16::from(data[pos]));
+            insert(pos, &mut head, &mut previous);
+            pos += 1;
+        }
+    }
+    writer.literal(END_OF_BLOCK);
+
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn inflate(bits: &mut Bits, limit: usize) -> Result<Vec<u8>, FormatError> {
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        let block_start = bits.byte_position();
+        match bits.bits(2)? {
+            0 => stored(bits, &mut out, limit)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(bits, &mut out, limit, &literals, &distances)?
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(bits)?;
+                codes(bits, &mut out, limit, &literals, &distances)?
+            }
+            _ => return Err(FormatError::new(block_start, "invalid deflate block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>, limit: usize) -> Result<(), FormatError> {
+    bits.align();
+    let start = bits.byte_position();
+    let len = bits.bits(16)?;
+    let inverted = bits.bits(16)?;
+    if len != !inverted & 0xffff {
+        return Err(FormatError::new(start, "stored block length check failed"));
+    }
+    let bytes = bits.bytes(len as usize)?;
+    if out.len() + bytes.len() > limit {
+        return Err(FormatError::new(start, "decompressed data is too large"));
+    }
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn codes(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), FormatError> {
+    loop {
+        let position = bits.byte_position();
+        let symbol = literals.decode(bits)?;
+        match symbol {
+            0..=255 => {
+                if out.len() >= limit {
+                    return Err(FormatError::new(position, "decompressed data is too large"));
+                }
+                out.push(symbol as u8);
+            }
+            END_OF_BLOCK => return Ok(()),
+            _ => {
+                let index = usize::from(symbol - 257);
+                if index >= LENGTH_BASE.len() {
+                    return Err(FormatError::new(position, "invalid length code"));
+                }
+                let len = usize::from(LENGTH_BASE[index])
+                    + bits.bits(LENGTH_EXTRA[index].into())? as usize;
+
+                let index = usize::from(distances.decode(bits)?);
+                if index >= DISTANCE_BASE.len() {
+                    return Err(FormatError::new(position, "invalid distance code"));
+                }
+                let distance = usize::from(DISTANCE_BASE[index])
+                    + bits.bits(DISTANCE_EXTRA[index].into())? as usize;
+                if distance > out.len() {
+                    return Err(FormatError::new(
+                        position,
+                        "distance refers to before the start of the data",
+                    ));
+                }
+                if out.len() + len > limit {
+                    return Err(FormatError::new(position, "decompressed data is too large"));
+                }
+                // The copy may overlap what it produces, so it goes byte by byte.
+                let from = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[from + i]);
+                }
+            }
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].iter_mut().for_each(|len| *len = 8);
+    lengths[144..256].iter_mut().for_each(|len| *len = 9);
+    lengths[256..280].iter_mut().for_each(|len| *len = 7);
+    lengths[280..].iter_mut().for_each(|len| *len = 8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), FormatError> {
+    let start = bits.byte_position();
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(FormatError::new(start, "too many codes in dynamic block"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let position = bits.byte_position();
+        let symbol = code_length_code.decode(bits)?;
+        let (len, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or_else(|| FormatError::new(position, "repeat with no previous length"))?;
+                (previous, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        let repeat = repeat as usize;
+        if index + repeat > lengths.len() {
+            return Err(FormatError::new(
+                position,
+                "code lengths overflow the block",
+            ));
+        }
+        lengths[index..index + repeat]
+            .iter_mut()
+            .for_each(|slot| *slot = len);
+        index += repeat;
+    }
+
+    if lengths[usize::from(END_OF_BLOCK)] == 0 {
+        return Err(FormatError::new(
+            start,
+            "dynamic block has no end-of-block code",
+        ));
+    }
+    let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+    Ok((
+        Huffman::new(literal_lengths),
+        Huffman::new(distance_lengths),
+    ))
+}
+
+/// A canonical Huffman code, decoded a bit at a time by counting codes of each length.
+struct Huffman {
+    counts: [u16; MAX_CODE_LEN + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_CODE_LEN + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; MAX_CODE_LEN + 1];
+        for len in 1..MAX_CODE_LEN {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[usize::from(offsets[usize::from(len)])] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, FormatError> {
+        let position = bits.byte_position();
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_CODE_LEN {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(FormatError::new(position, "invalid Huffman code"))
+    }
+}
+
+/// Reads DEFLATE's least-significant-bit-first bit stream.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self {
+            data,
+            pos,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// The offset of the byte the next bit comes from
+    fn byte_position(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, FormatError> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| FormatError::new(self.pos, "compressed data ends early"))?;
+            self.pos += 1;
+            self.buffer |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drops the rest of the current byte.
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.buffer >>= partial;
+        self.count -= partial;
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        // Whole bytes still in the buffer are given back first.
+        self.pos = self.byte_position();
+        self.buffer = 0;
+        self.count = 0;
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(|| {
+            FormatError::new(self.pos, "stored block runs past the end of the data")
+        })?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+/// Writes DEFLATE's least-significant-bit-first bit stream.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buffer |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which unlike other values goes most significant bit first.
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    /// Writes a literal/length symbol with the fixed code.
+    fn literal(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, len: usize) {
+        let index = LENGTH_BASE
+            .iter()
+            .rposition(|&base| usize::from(base) <= len)
+            .unwrap_or(0);
+        self.literal(257 + index as u16);
+        let extra = len - usize::from(LENGTH_BASE[index]);
+        self.bits(extra as u32, LENGTH_EXTRA[index].into());
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASE
+            .iter()
+            .rposition(|&base| usize::from(base) <= distance)
+            .unwrap_or(0);
+        self.code(index as u32, 5);
+        let extra = distance - usize::from(DISTANCE_BASE[index]);
+        self.bits(extra as u32, DISTANCE_EXTRA[index].into());
+    }
+
+    /// Pads the last byte with zeros and returns everything written.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Sums stay below u32::MAX for chunks of this size before they need reducing.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
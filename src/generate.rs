@@ -0,0 +1,687 @@
+//! Cursors drawn from a short description rather than an image file, for when any big arrow
+//! with a label will do.
+//!
+//! A design is a shape followed by `key=value` settings, separated by spaces:
+//!
+//! ```text
+//! arrow size=64 fill=yellow outline=#000 text="LOL"
+//! ```
+//!
+//! * The shape is one of `arrow`, `circle`, `square`, `diamond`, `cross`, `star`, `heart` and
+//!   `smiley`
+//! * `size=<pixels>` is the width and height of the cursor at 100% display scaling, 32 if not
+//!   given. Larger copies are drawn for higher scalings.
+//! * `fill=<colour>` and `outline=<colour>` default to white and black. Colours are names such
+//!   as `red` or `transparent`, or written as `#rgb`, `#rrggbb` or `#rrggbbaa`.
+//! * `outline-width=<pixels>` defaults to a sixteenth of the size, and may be `0`
+//! * `hotspot=<x>,<y>` defaults to the tip of the arrow or the middle of the other shapes
+//! * `text=<text>` adds a label in a 5x7 pixel font, quoted if it has spaces, drawn in the
+//!   outline colour or `text-colour=<colour>` and edged with the fill colour
+
+mod font;
+
+use std::{error::Error, fmt, str::FromStr, time::Duration};
+
+use crate::{
+    image::{AnimatedCursor, AnimationFrame, CursorEntry, CursorImage, Hotspot},
+    transform::STANDARD_SIZES,
+};
+
+const DEFAULT_SIZE: u32 = 32;
+const MAX_SIZE: u32 = 256;
+/// Samples taken along each axis of a pixel, for smooth edges
+const SUPERSAMPLING: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Arrow,
+    Circle,
+    Square,
+    Diamond,
+    Cross,
+    Star,
+    Heart,
+    Smiley,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 8] = [
+        Self::Arrow,
+        Self::Circle,
+        Self::Square,
+        Self::Diamond,
+        Self::Cross,
+        Self::Star,
+        Self::Heart,
+        Self::Smiley,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Arrow => "arrow",
+            Self::Circle => "circle",
+            Self::Square => "square",
+            Self::Diamond => "diamond",
+            Self::Cross => "cross",
+            Self::Star => "star",
+            Self::Heart => "heart",
+            Self::Smiley => "smiley",
+        }
+    }
+
+    /// The outline of the shape in a unit square with y pointing down, or `None` for shapes
+    /// drawn with circles.
+    fn polygon(self) -> Option<Vec<[f32; 2]>> {
+        match self {
+            Self::Arrow => Some(vec![
+                [0.0, 0.0],
+                [0.0, 0.85],
+                [0.2, 0.66],
+                [0.33, 0.97],
+                [0.47, 0.91],
+                [0.34, 0.61],
+                [0.6, 0.61],
+            ]),
+            Self::Square => Some(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]),
+            Self::Diamond => Some(vec![[0.5, 0.0], [1.0, 0.5], [0.5, 1.0], [0.0, 0.5]]),
+            Self::Cross => {
+                let (near, far) = (0.34, 0.66);
+                Some(vec![
+                    [near, 0.0],
+                    [far, 0.0],
+                    [far, near],
+                    [1.0, near],
+                    [1.0, far],
+                    [far, far],
+                    [far, 1.0],
+                    [near, 1.0],
+                    [near, far],
+                    [0.0, far],
+                    [0.0, near],
+                    [near, near],
+                ])
+            }
+            Self::Star => Some(
+                (0..10)
+                    .map(|point| {
+                        let radius = if point % 2 == 0 { 0.5 } else { 0.2 };
+                        let angle = point as f32 * std::f32::consts::PI / 5.0;
+                        [0.5 + radius * angle.sin(), 0.54 - radius * angle.cos()]
+                    })
+                    .collect(),
+            ),
+            // The classic parametric heart, squeezed into the unit square
+            Self::Heart => Some(
+                (0..64)
+                    .map(|point| {
+                        let t = point as f32 * std::f32::consts::PI / 32.0;
+                        let x = 16.0 * t.sin().powi(3);
+                        let y = 13.0 * t.cos()
+                            - 5.0 * (2.0 * t).cos()
+                            - 2.0 * (3.0 * t).cos()
+                            - (4.0 * t).cos();
+                        [0.5 + x / 34.0, 0.42 - y / 34.0]
+                    })
+                    .collect(),
+            ),
+            Self::Circle | Self::Smiley => None,
+        }
+    }
+
+    /// Distance from `point` to details drawn over the shape in the outline colour, negative
+    /// inside them.
+    fn detail_distance(self, point: [f32; 2]) -> f32 {
+        match self {
+            Self::Smiley => {
+                let eyes = circle_distance(point, [0.35, 0.37], 0.07).min(circle_distance(
+                    point,
+                    [0.65, 0.37],
+                    0.07,
+                ));
+                // The lower half of a ring
+                let mouth = if point[1] > 0.52 {
+                    circle_distance(point, [0.5, 0.5], 0.28).abs() - 0.04
+                } else {
+                    f32::INFINITY
+                };
+                eyes.min(mouth)
+            }
+            _ => f32::INFINITY,
+        }
+    }
+
+    /// Where the hotspot goes unless a design says otherwise, in the unit square
+    fn default_hotspot(self) -> [f32; 2] {
+        match self {
+            Self::Arrow => [0.0, 0.0],
+            _ => [0.5, 0.5],
+        }
+    }
+
+    /// The box in the unit square that labels are centred in, as left, top, right and bottom
+    fn text_box(self) -> [f32; 4] {
+        match self {
+            // The empty corner beside the tail
+            Self::Arrow => [0.5, 0.62, 1.0, 1.0],
+            _ => [0.1, 0.3, 0.9, 0.7],
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = InvalidDesign;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|shape| shape.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                InvalidDesign(format!(
+                    "unknown shape `{}`, expected one of {}",
+                    s,
+                    Self::ALL
+                        .iter()
+                        .map(|shape| shape.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+}
+
+/// A description of a cursor to draw
+#[derive(Clone, Debug, PartialEq)]
+pub struct Design {
+    pub shape: Shape,
+    pub size: u32,
+    pub fill: [u8; 4],
+    pub outline: [u8; 4],
+    pub outline_width: Option<u32>,
+    /// In pixels at `size`
+    pub hotspot: Option<Hotspot>,
+    pub text: Option<String>,
+    pub text_colour: Option<[u8; 4]>,
+}
+
+impl Design {
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            shape,
+            size: DEFAULT_SIZE,
+            fill: [255, 255, 255, 255],
+            outline: [0, 0, 0, 255],
+            outline_width: None,
+            hotspot: None,
+            text: None,
+            text_colour: None,
+        }
+    }
+
+    /// The design drawn at its own size and scaled up for each of `STANDARD_SIZES`, which are
+    /// sizes for the display scalings from 100%.
+    pub fn cursor(&self) -> AnimatedCursor {
+        let mut sizes: Vec<u32> = STANDARD_SIZES
+            .iter()
+            .map(|&standard| (self.size * standard / DEFAULT_SIZE).min(MAX_SIZE))
+            .collect();
+        sizes.dedup();
+        let image = CursorImage::new(sizes.into_iter().map(|size| self.render(size)).collect());
+        AnimatedCursor::new(vec![AnimationFrame::new(image, Duration::default())])
+    }
+
+    /// Draws the design `size` pixels square, scaling the outline, hotspot and text to match.
+    pub fn render(&self, size: u32) -> CursorEntry {
+        let scale = size as f32 / self.size as f32;
+        let outline_width = self
+            .outline_width
+            .unwrap_or_else(|| (self.size / 16).max(1)) as f32
+            * scale
+            / size as f32;
+        let hotspot = match self.hotspot {
+            Some(hotspot) => Hotspot::new(
+                scale_coordinate(hotspot.x, scale, size),
+                scale_coordinate(hotspot.y, scale, size),
+            ),
+            None => {
+                let [x, y] = self.shape.default_hotspot();
+                let coordinate = |unit: f32| ((unit * size as f32) as u32).min(size - 1) as u16;
+                Hotspot::new(coordinate(x), coordinate(y))
+            }
+        };
+
+        let mut entry = CursorEntry::new(size, size, hotspot);
+        let polygon = self.shape.polygon();
+        let samples = (SUPERSAMPLING * SUPERSAMPLING) as f32;
+        for y in 0..size {
+            for x in 0..size {
+                // Colours are averaged premultiplied, so transparent samples add no colour.
+                let mut sum = [0f32; 4];
+                for sample in 0..SUPERSAMPLING * SUPERSAMPLING {
+                    let offset = |index: u32| (index as f32 + 0.5) / SUPERSAMPLING as f32;
+                    let point = [
+                        (x as f32 + offset(sample % SUPERSAMPLING)) / size as f32,
+                        (y as f32 + offset(sample / SUPERSAMPLING)) / size as f32,
+                    ];
+                    let distance = match &polygon {
+                        Some(polygon) => polygon_distance(point, polygon),
+                        None => circle_distance(point, [0.5, 0.5], 0.5),
+                    };
+                    let colour = if distance > 0.0 {
+                        continue;
+                    } else if distance > -outline_width || self.shape.detail_distance(point) <= 0.0
+                    {
+                        self.outline
+                    } else {
+                        self.fill
+                    };
+                    let alpha = f32::from(colour[3]);
+                    for channel in 0..3 {
+                        sum[channel] += f32::from(colour[channel]) * alpha;
+                    }
+                    sum[3] += alpha;
+                }
+                if sum[3] > 0.0 {
+                    let unpremultiply = |value: f32| (value / sum[3]).round() as u8;
+                    entry.set_pixel(
+                        x,
+                        y,
+                        [
+                            unpremultiply(sum[0]),
+                            unpremultiply(sum[1]),
+                            unpremultiply(sum[2]),
+                            (sum[3] / samples).round() as u8,
+                        ],
+                    );
+                }
+            }
+        }
+
+        if let Some(text) = &self.text {
+            self.draw_text(&mut entry, text);
+        }
+        entry
+    }
+
+    /// Draws a label centred in the shape's text box, at the largest whole scale that fits.
+    fn draw_text(&self, entry: &mut CursorEntry, text: &str) {
+        let size = entry.width as f32;
+        let [left, top, right, bottom] = self.shape.text_box();
+        let (box_width, box_height) = ((right - left) * size, (bottom - top) * size);
+        let text_width = font::text_width(text);
+        if text_width == 0 {
+            return;
+        }
+        let scale =
+            ((box_width / text_width as f32).min(box_height / font::HEIGHT as f32) as u32).max(1);
+        let origin_x = ((left + right) / 2.0 * size) as i64 - i64::from(text_width * scale / 2);
+        let origin_y = ((top + bottom) / 2.0 * size) as i64 - i64::from(font::HEIGHT * scale / 2);
+
+        let is_set = |x: i64, y: i64| {
+            let (x, y) = (x - origin_x, y - origin_y);
+            if x < 0 || y < 0 {
+                return false;
+            }
+            let (x, y) = ((x as u32) / scale, (y as u32) / scale);
+            let advance = font::WIDTH + font::SPACING;
+            y < font::HEIGHT
+                && x % advance < font::WIDTH
+                && text
+                    .chars()
+                    .nth((x / advance) as usize)
+                    .is_some_and(|c| font::is_set(c, x % advance, y))
+        };
+        let colour = self.text_colour.unwrap_or(self.outline);
+        let edge = [self.fill[0], self.fill[1], self.fill[2], 255];
+        for y in 0..entry.height {
+            for x in 0..entry.width {
+                let (x_, y_) = (i64::from(x), i64::from(y));
+                if is_set(x_, y_) {
+                    entry.set_pixel(x, y, colour);
+                } else if (-1..=1).any(|dy| (-1..=1).any(|dx| is_set(x_ + dx, y_ + dy))) {
+                    entry.set_pixel(x, y, edge);
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Design {
+    type Err = InvalidDesign;
+
+    /// Parses a shape followed by `key=value` settings, as described in the module docs.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = split_words(s)?;
+        let mut words = words.iter();
+        let mut design = match words.next() {
+            Some(shape) => Self::new(shape.parse()?),
+            None => return Err(InvalidDesign("no shape given".to_owned())),
+        };
+
+        for word in words {
+            let eq = word
+                .find('=')
+                .ok_or_else(|| InvalidDesign(format!("expected `key=value`, found `{}`", word)))?;
+            let (key, value) = (&word[..eq], &word[eq + 1..]);
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| InvalidDesign(format!("{} should be a number of pixels", key)))
+            };
+            match key {
+                "size" => match number()? {
+                    size @ 1..=MAX_SIZE => design.size = size,
+                    _ => {
+                        return Err(InvalidDesign(format!(
+                            "size should be from 1 to {}",
+                            MAX_SIZE
+                        )))
+                    }
+                },
+                "fill" => design.fill = parse_colour(value)?,
+                "outline" => design.outline = parse_colour(value)?,
+                "outline-width" => design.outline_width = Some(number()?),
+                "hotspot" => {
+                    design.hotspot = Some(
+                        value
+                            .parse::<Hotspot>()
+                            .map_err(|err| InvalidDesign(err.to_string()))?,
+                    )
+                }
+                "text" => design.text = Some(value.to_owned()),
+                "text-colour" | "text-color" => design.text_colour = Some(parse_colour(value)?),
+                _ => return Err(InvalidDesign(format!("unknown setting `{}`", key))),
+            }
+        }
+
+        if let Some(hotspot) = design.hotspot {
+            if u32::from(hotspot.x.max(hotspot.y)) >= design.size {
+                return Err(InvalidDesign(format!(
+                    "hotspot {},{} is outside the {}x{} cursor",
+                    hotspot.x, hotspot.y, design.size, design.size
+                )));
+            }
+        }
+        Ok(design)
+    }
+}
+
+/// Splits a design into words at spaces outside double quotes, dropping the quotes.
+fn split_words(s: &str) -> Result<Vec<String>, InvalidDesign> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            _ if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            _ => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(InvalidDesign("unterminated quote".to_owned()));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn parse_colour(value: &str) -> Result<[u8; 4], InvalidDesign> {
+    let named = match value.to_ascii_lowercase().as_str() {
+        "black" => Some([0, 0, 0, 255]),
+        "white" => Some([255, 255, 255, 255]),
+        "red" => Some([255, 0, 0, 255]),
+        "green" => Some([0, 200, 0, 255]),
+        "blue" => Some([0, 0, 255, 255]),
+        "yellow" => Some([255, 230, 0, 255]),
+        "orange" => Some([255, 140, 0, 255]),
+        "purple" => Some([140, 0, 200, 255]),
+        "pink" => Some([255, 120, 200, 255]),
+        "cyan" => Some([0, 230, 230, 255]),
+        "magenta" => Some([255, 0, 255, 255]),
+        "grey" | "gray" => Some([128, 128, 128, 255]),
+        "transparent" | "none" => Some([0, 0, 0, 0]),
+        _ => None,
+    };
+    if let Some(colour) = named {
+        return Ok(colour);
+    }
+
+    let invalid = || {
+        InvalidDesign(format!(
+            "invalid colour `{}`, expected a name or #rgb, #rrggbb or #rrggbbaa",
+            value
+        ))
+    };
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let digit = |index: usize| u8::from_str_radix(&hex[index..=index], 16).unwrap_or(0);
+    let byte = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).unwrap_or(0);
+    match hex.len() {
+        3 => Ok([digit(0) * 17, digit(1) * 17, digit(2) * 17, 255]),
+        6 => Ok([byte(0), byte(2), byte(4), 255]),
+        8 => Ok([byte(0), byte(2), byte(4), byte(6)]),
+        _ => Err(invalid()),
+    }
+}
+
+fn scale_coordinate(coordinate: u16, scale: f32, size: u32) -> u16 {
+    ((f32::from(coordinate) * scale) as u32).min(size - 1) as u16
+}
+
+fn circle_distance(point: [f32; 2], centre: [f32; 2], radius: f32) -> f32 {
+    (point[0] - centre[0]).hypot(point[1] - centre[1]) - radius
+}
+
+/// Distance from `point` to the edge of a polygon, negative inside it.
+fn polygon_distance(point: [f32; 2], polygon: &[[f32; 2]]) -> f32 {
+    let mut nearest = f32::INFINITY;
+    let mut inside = false;
+    for (index, &start) in polygon.iter().enumerate() {
+        let end = polygon[(index + 1) % polygon.len()];
+        let edge = [end[0] - start[0], end[1] - start[1]];
+        let to_point = [point[0] - start[0], point[1] - start[1]];
+        let along = ((to_point[0] * edge[0] + to_point[1] * edge[1])
+            / (edge[0] * edge[0] + edge[1] * edge[1]))
+            .clamp(0.0, 1.0);
+        nearest = nearest.min((to_point[0] - edge[0] * along).hypot(to_point[1] - edge[1] * along));
+        // Counting the edges a ray to the right crosses
+        if (start[1] > point[1]) != (end[1] > point[1])
+            && point[0] < start[0] + (point[1] - start[1]) / edge[1] * edge[0]
+        {
+            inside = !inside;
+        }
+    }
+    if inside {
+        -nearest
+    } else {
+        nearest
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidDesign(pub String);
+
+impl fmt::Display for InvalidDesign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid cursor design: {}", self.0)
+    }
+}
+
+impl Error for InvalidDesign {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    fn error(design: &str) -> String {
+        design.parse::<Design>().unwrap_err().0
+    }
+
+    #[test]
+    fn parses_designs() {
+        let design: Design = concat!(
+            "Arrow size=64 fill=yellow outline=#000 outline-width=0 hotspot=3,4 ",
+            r#"text="LOL cats" text-colour=#11223344"#
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(
+            design,
+            Design {
+                shape: Shape::Arrow,
+                size: 64,
+                fill: [255, 230, 0, 255],
+                outline: BLACK,
+                outline_width: Some(0),
+                hotspot: Some(Hotspot::new(3, 4)),
+                text: Some("LOL cats".to_owned()),
+                text_colour: Some([0x11, 0x22, 0x33, 0x44]),
+            }
+        );
+        assert_eq!("  heart  ".parse(), Ok(Design::new(Shape::Heart)));
+        assert_eq!(
+            split_words(r#"star text="" "a b"c"#).unwrap(),
+            vec!["star", "text=", "a bc"]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_designs() {
+        assert_eq!(error(""), "no shape given");
+        assert_eq!(
+            error("blob"),
+            "unknown shape `blob`, expected one of arrow, circle, square, diamond, cross, \
+             star, heart, smiley"
+        );
+        assert_eq!(error("circle glow=1"), "unknown setting `glow`");
+        assert_eq!(error("circle big"), "expected `key=value`, found `big`");
+        assert_eq!(error("circle size=0"), "size should be from 1 to 256");
+        assert_eq!(error("circle size=257"), "size should be from 1 to 256");
+        assert_eq!(
+            error("circle size=huge"),
+            "size should be a number of pixels"
+        );
+        assert_eq!(
+            error("circle hotspot=32,0"),
+            "hotspot 32,0 is outside the 32x32 cursor"
+        );
+        // The hotspot is checked against the final size, whichever order they come in.
+        assert!("circle hotspot=40,40 size=48".parse::<Design>().is_ok());
+        assert_eq!(error(r#"circle text="LOL"#), "unterminated quote");
+    }
+
+    #[test]
+    fn parses_colours() {
+        assert_eq!(parse_colour("#f0a"), Ok([255, 0, 170, 255]));
+        assert_eq!(parse_colour("#1A2b3C"), Ok([0x1a, 0x2b, 0x3c, 255]));
+        assert_eq!(parse_colour("#1a2b3c80"), Ok([0x1a, 0x2b, 0x3c, 0x80]));
+        assert_eq!(parse_colour("RED"), Ok(RED));
+        assert_eq!(parse_colour("gray"), parse_colour("grey"));
+        assert_eq!(parse_colour("none"), Ok([0, 0, 0, 0]));
+        for invalid in &["#12", "#12345", "#ggg", "123456", "#+12", "chartreuse"] {
+            assert_eq!(
+                parse_colour(invalid).unwrap_err().0,
+                format!(
+                    "invalid colour `{}`, expected a name or #rgb, #rrggbb or #rrggbbaa",
+                    invalid
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn default_hotspots() {
+        let hotspot = |shape| Design::new(shape).render(32).hotspot;
+        assert_eq!(hotspot(Shape::Arrow), Hotspot::new(0, 0));
+        for &shape in &Shape::ALL[1..] {
+            assert_eq!(hotspot(shape), Hotspot::new(16, 16), "{:?}", shape);
+        }
+
+        let mut design = Design::new(Shape::Circle);
+        design.hotspot = Some(Hotspot::new(3, 31));
+        assert_eq!(design.render(64).hotspot, Hotspot::new(6, 62));
+    }
+
+    #[test]
+    fn draws_sizes_for_each_scaling() {
+        let sizes = |size| {
+            let mut design = Design::new(Shape::Square);
+            design.size = size;
+            let cursor = design.cursor();
+            assert_eq!(cursor.frames.len(), 1);
+            let entries = &cursor.frames[0].image.entries;
+            entries
+                .iter()
+                .map(|entry| (entry.width, entry.height))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sizes(32),
+            vec![(32, 32), (48, 48), (64, 64), (96, 96), (128, 128)]
+        );
+        assert_eq!(sizes(200), vec![(200, 200), (256, 256)]);
+        assert_eq!(sizes(256), vec![(256, 256)]);
+    }
+
+    #[test]
+    fn fills_inside_and_outlines_the_edge() {
+        let circle = Design::new(Shape::Circle).render(32);
+        assert_eq!(circle.pixel(16, 16), WHITE);
+        assert_eq!(circle.pixel(16, 1), BLACK);
+        assert_eq!(circle.pixel(0, 0)[3], 0);
+        assert_eq!(circle.pixel(31, 31)[3], 0);
+
+        let arrow = Design::new(Shape::Arrow).render(32);
+        assert_eq!(arrow.pixel(4, 10), WHITE);
+        // The empty corner opposite the tip
+        assert_eq!(arrow.pixel(28, 4)[3], 0);
+
+        let mut bare = Design::new(Shape::Square);
+        bare.outline_width = Some(0);
+        bare.fill = RED;
+        assert!(bare.render(8).pixels.chunks(4).all(|pixel| pixel == RED));
+
+        let smiley = Design::new(Shape::Smiley).render(64);
+        // An eye, drawn in the outline colour over the fill
+        assert_eq!(smiley.pixel(22, 24), BLACK);
+        assert_eq!(smiley.pixel(32, 24), WHITE);
+    }
+
+    #[test]
+    fn draws_text() {
+        let labelled: Design = "square size=32 text=X text-colour=red".parse().unwrap();
+        let has_red = |design: &Design| {
+            let entry = design.render(32);
+            entry.pixels.chunks(4).any(|pixel| pixel == RED)
+        };
+        assert!(has_red(&labelled));
+        assert!(!has_red(&Design {
+            text: None,
+            ..labelled.clone()
+        }));
+        assert!(!has_red(&Design {
+            text: Some(String::new()),
+            ..labelled
+        }));
+    }
+}
@@ -0,0 +1,399 @@
+//! Guessing where the hotspot of a cursor image belongs from the shape drawn in it.
+//!
+//! Each kind of cursor gets the heuristic that suits how it is usually drawn:
+//!
+//! * arrows like `Normal` and `AppStarting` click with the topmost, leftmost pixel
+//! * pointers like `Hand` and `Up` click with their tip, the narrowest end of the shape
+//! * everything else, from crosshairs to hourglasses, clicks in the middle
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    cursor::CursorKind,
+    format::{self, png, Format, FormatError},
+    image::{AnimatedCursor, AnimationFrame, CursorEntry, CursorImage, Hotspot},
+};
+
+/// Pixels at least this opaque make up the shape, which keeps soft shadows and the faint edges
+/// of antialiasing from moving the hotspot
+const OPAQUE: u8 = 128;
+/// The directions pointers point in, straight up first so it wins ties
+const POINTING: [(f32, f32); 3] = [(0.0, -1.0), (-1.0, -1.0), (1.0, -1.0)];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Heuristic {
+    /// The leftmost pixel of the topmost row of the shape
+    TopLeft,
+    /// The average position of the pixels of the shape
+    Centroid,
+    /// The end of the shape that tapers the most
+    Tip,
+}
+
+impl Heuristic {
+    /// The heuristic that suits how cursors of `kind` are usually drawn
+    pub fn for_kind(kind: CursorKind) -> Self {
+        match kind {
+            CursorKind::Normal | CursorKind::AppStarting => Self::TopLeft,
+            CursorKind::Hand | CursorKind::Up => Self::Tip,
+            _ => Self::Centroid,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TopLeft => "top-left",
+            Self::Centroid => "centroid",
+            Self::Tip => "tip",
+        }
+    }
+}
+
+/// Suggests a hotspot for an image meant as a cursor of `kind`, or nothing if the image is
+/// empty.
+pub fn suggest(entry: &CursorEntry, kind: CursorKind) -> Option<Hotspot> {
+    detect(entry, Heuristic::for_kind(kind))
+}
+
+/// Finds the hotspot `heuristic` picks for an image, or nothing if the image is empty.
+pub fn detect(entry: &CursorEntry, heuristic: Heuristic) -> Option<Hotspot> {
+    let shape: Vec<(u32, u32)> = (0..entry.height)
+        .flat_map(|y| (0..entry.width).map(move |x| (x, y)))
+        .filter(|&(x, y)| entry.pixel(x, y)[3] >= OPAQUE)
+        .collect();
+    if shape.is_empty() {
+        return None;
+    }
+
+    let (x, y) = match heuristic {
+        // The shape is in row order already.
+        Heuristic::TopLeft => shape[0],
+        Heuristic::Centroid => {
+            let len = shape.len() as u64;
+            let sum_x: u64 = shape.iter().map(|&(x, _)| u64::from(x)).sum();
+            let sum_y: u64 = shape.iter().map(|&(_, y)| u64::from(y)).sum();
+            (
+                ((sum_x + len / 2) / len) as u32,
+                ((sum_y + len / 2) / len) as u32,
+            )
+        }
+        Heuristic::Tip => tip(&shape),
+    };
+    Some(Hotspot::new(x as u16, y as u16))
+}
+
+/// The end of the shape that tapers the most. Pointers point up, or up and to one side, so of
+/// the points furthest out in those directions, the one with the fewest pixels of the shape
+/// close behind it wins. The middle of its outermost pixels is the tip itself.
+fn tip(shape: &[(u32, u32)]) -> (u32, u32) {
+    let (min_x, max_x) = min_max(shape.iter().map(|&(x, _)| x));
+    let (min_y, max_y) = min_max(shape.iter().map(|&(_, y)| y));
+    // How far behind the tip to look, an eighth of the shape
+    let depth = ((max_x - min_x).max(max_y - min_y) as f32 / 8.0).max(2.0);
+
+    POINTING
+        .iter()
+        .map(|&(dx, dy)| {
+            let length = (dx * dx + dy * dy).sqrt();
+            let reach = |&(x, y): &(u32, u32)| (x as f32 * dx + y as f32 * dy) / length;
+            let furthest = shape.iter().map(reach).fold(f32::MIN, f32::max);
+            let behind = shape
+                .iter()
+                .filter(|point| reach(point) >= furthest - depth)
+                .count();
+            let mut outermost: Vec<(u32, u32)> = shape
+                .iter()
+                .copied()
+                .filter(|point| reach(point) >= furthest - 0.5)
+                .collect();
+            let across = |&(x, y): &(u32, u32)| x as f32 * dy - y as f32 * dx;
+            outermost.sort_by(|a, b| across(a).total_cmp(&across(b)));
+            (behind, outermost[outermost.len() / 2])
+        })
+        .min_by_key(|&(behind, _)| behind)
+        .map(|(_, tip)| tip)
+        .unwrap_or(shape[0])
+}
+
+fn min_max<I: Iterator<Item = u32>>(values: I) -> (u32, u32) {
+    values.fold((u32::MAX, 0), |(min, max), value| {
+        (min.min(value), max.max(value))
+    })
+}
+
+/// Moves the hotspot of every image in a cursor to the one suggested for `kind`. All frames
+/// share the hotspots found in the first, so an animation doesn't jitter, and images without
+/// anything drawn in them keep theirs.
+pub fn apply(cursor: &mut AnimatedCursor, kind: CursorKind) {
+    let suggested: Vec<(u32, u32, Option<Hotspot>)> = match cursor.frames.first() {
+        Some(frame) => frame
+            .image
+            .entries
+            .iter()
+            .map(|entry| (entry.width, entry.height, suggest(entry, kind)))
+            .collect(),
+        None => return,
+    };
+    for frame in &mut cursor.frames {
+        for (index, entry) in frame.image.entries.iter_mut().enumerate() {
+            let hotspot = match suggested.get(index) {
+                Some(&(width, height, hotspot))
+                    if (width, height) == (entry.width, entry.height) =>
+                {
+                    hotspot
+                }
+                _ => suggest(entry, kind),
+            };
+            if let Some(hotspot) = hotspot {
+                entry.hotspot = hotspot;
+            }
+        }
+    }
+}
+
+/// Prints the hotspots suggested for each image of each cursor or PNG in `inputs`, and writes
+/// the cursor with them to `output` if given, which only makes sense for a single input.
+pub fn suggest_files(
+    inputs: &[PathBuf],
+    kind: CursorKind,
+    output: Option<(&Path, Format)>,
+) -> Result<(), HotspotError> {
+    println!(
+        "Using the {} heuristic for {} cursors",
+        Heuristic::for_kind(kind).as_str(),
+        kind.as_str()
+    );
+    for path in inputs {
+        let bytes = fs::read(path).map_err(|err| HotspotError::Read(path.clone(), err))?;
+        let decoded = if bytes.starts_with(b"\x89PNG") {
+            png::decode(&bytes).map(|entry| {
+                AnimatedCursor::new(vec![AnimationFrame::new(
+                    CursorImage::new(vec![entry]),
+                    Duration::default(),
+                )])
+            })
+        } else {
+            format::decode(&bytes)
+        };
+        let mut cursor = decoded.map_err(|err| HotspotError::Decode(path.clone(), err))?;
+        apply(&mut cursor, kind);
+
+        if let Some(frame) = cursor.frames.first() {
+            for entry in &frame.image.entries {
+                println!(
+                    "{}: {}x{} at {},{}",
+                    path.display(),
+                    entry.width,
+                    entry.height,
+                    entry.hotspot.x,
+                    entry.hotspot.y
+                );
+            }
+        }
+        if let Some((output, format)) = output {
+            fs::write(output, format::encode(&cursor, format))
+                .map_err(|err| HotspotError::Write(output.to_owned(), err))?;
+            println!("Wrote {}", output.display());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum HotspotError {
+    Read(PathBuf, io::Error),
+    Decode(PathBuf, FormatError),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for HotspotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Decode(path, err) => write!(
+                f,
+                "{} is not a usable cursor or PNG: {}",
+                path.display(),
+                err
+            ),
+            Self::Write(path, err) => write!(f, "could not write {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for HotspotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(_, err) | Self::Write(_, err) => Some(err),
+            Self::Decode(_, err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An entry drawn from rows of text: `#` is opaque, `.` is a faint shadow and anything
+    /// else is transparent
+    fn draw(rows: &[&str]) -> CursorEntry {
+        let mut entry =
+            CursorEntry::new(rows[0].len() as u32, rows.len() as u32, Hotspot::new(0, 0));
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.bytes().enumerate() {
+                let alpha = match pixel {
+                    b'#' => 255,
+                    b'.' => OPAQUE - 1,
+                    _ => continue,
+                };
+                entry.set_pixel(x as u32, y as u32, [0, 0, 0, alpha]);
+            }
+        }
+        entry
+    }
+
+    const ARROW: &[&str] = &[
+        "..      ", "..#     ", " .##    ", "  ###   ", "  ####  ", "  ##    ", "   #    ",
+    ];
+
+    const CROSSHAIR: &[&str] = &[
+        "    #    ",
+        "    #    ",
+        "    #    ",
+        "    #    ",
+        "#########",
+        "    #    ",
+        "    #    ",
+        "    #    ",
+        "    #   .",
+    ];
+
+    const HAND: &[&str] = &[
+        "    #    ",
+        "    #    ",
+        "    #    ",
+        "    #    ",
+        "  #####  ",
+        " ####### ",
+        " ####### ",
+        " ####### ",
+        "  .....  ",
+    ];
+
+    #[test]
+    fn picks_the_top_left_of_an_arrow() {
+        assert_eq!(
+            detect(&draw(ARROW), Heuristic::TopLeft),
+            Some(Hotspot::new(2, 1))
+        );
+        assert_eq!(
+            suggest(&draw(ARROW), CursorKind::Normal),
+            Some(Hotspot::new(2, 1))
+        );
+    }
+
+    #[test]
+    fn picks_the_middle_of_a_crosshair() {
+        assert_eq!(
+            detect(&draw(CROSSHAIR), Heuristic::Centroid),
+            Some(Hotspot::new(4, 4))
+        );
+        assert_eq!(
+            suggest(&draw(CROSSHAIR), CursorKind::Crosshair),
+            Some(Hotspot::new(4, 4))
+        );
+    }
+
+    #[test]
+    fn picks_the_tip_of_a_hand() {
+        assert_eq!(
+            detect(&draw(HAND), Heuristic::Tip),
+            Some(Hotspot::new(4, 0))
+        );
+        assert_eq!(
+            suggest(&draw(HAND), CursorKind::Hand),
+            Some(Hotspot::new(4, 0))
+        );
+    }
+
+    #[test]
+    fn picks_the_tip_of_a_diagonal_pointer() {
+        let pointer = draw(&[
+            "#      ", "##     ", " ###   ", "  #### ", "  #####", "   ####", "    ###",
+        ]);
+        assert_eq!(detect(&pointer, Heuristic::Tip), Some(Hotspot::new(0, 0)));
+    }
+
+    #[test]
+    fn shadows_are_not_part_of_the_shape() {
+        let shadow = draw(&["...", "...", "..."]);
+        for heuristic in &[Heuristic::TopLeft, Heuristic::Centroid, Heuristic::Tip] {
+            assert_eq!(detect(&shadow, *heuristic), None);
+        }
+    }
+
+    #[test]
+    fn empty_images_keep_their_hotspot() {
+        let empty = CursorEntry::new(4, 4, Hotspot::new(3, 1));
+        assert_eq!(detect(&empty, Heuristic::Centroid), None);
+
+        let mut cursor = AnimatedCursor::new(vec![AnimationFrame::new(
+            CursorImage::new(vec![empty.clone()]),
+            Duration::default(),
+        )]);
+        apply(&mut cursor, CursorKind::Normal);
+        assert_eq!(
+            cursor.frames[0].image.entries[0].hotspot,
+            Hotspot::new(3, 1)
+        );
+    }
+
+    #[test]
+    fn frames_share_the_first_frames_hotspots() {
+        let frame = |entries: Vec<CursorEntry>| {
+            AnimationFrame::new(CursorImage::new(entries), Duration::from_millis(100))
+        };
+        // The crosshair moves over the animation, but the hotspot should stay put.
+        let mut moved = draw(CROSSHAIR);
+        moved.pixels.rotate_right(4);
+        let small = draw(&["  ", " #"]);
+        let mut cursor = AnimatedCursor::new(vec![
+            frame(vec![draw(CROSSHAIR)]),
+            frame(vec![moved]),
+            frame(vec![small]),
+        ]);
+        apply(&mut cursor, CursorKind::Crosshair);
+
+        let hotspots: Vec<Hotspot> = cursor
+            .frames
+            .iter()
+            .map(|frame| frame.image.entries[0].hotspot)
+            .collect();
+        // Images of another size than the first frame's get their own.
+        assert_eq!(
+            hotspots,
+            vec![Hotspot::new(4, 4), Hotspot::new(4, 4), Hotspot::new(1, 1)]
+        );
+    }
+
+    #[test]
+    fn agrees_with_the_default_cursor() {
+        let image = format::cur::decode(crate::cursor::DEFAULT_CURSOR).unwrap();
+        let entry = &image.entries[0];
+        // Its very tip is too faint to count, so the suggestion is the solid pixel beside the
+        // hotspot it comes with.
+        assert_eq!(entry.hotspot, Hotspot::new(0, 0));
+        assert!(entry.pixel(0, 0)[3] < OPAQUE);
+        assert_eq!(suggest(entry, CursorKind::Normal), Some(Hotspot::new(1, 0)));
+
+        // The middle of an arrow lies inside it.
+        let centroid = detect(entry, Heuristic::Centroid).unwrap();
+        let (x, y) = (u32::from(centroid.x), u32::from(centroid.y));
+        assert!(entry.pixel(x, y)[3] >= OPAQUE);
+    }
+}
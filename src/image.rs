@@ -0,0 +1,176 @@
+use std::{error::Error, fmt, str::FromStr, time::Duration};
+
+/// A cursor made up of one or more images, usually the same picture at different sizes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CursorImage {
+    pub entries: Vec<CursorEntry>,
+}
+
+impl CursorImage {
+    pub fn new(entries: Vec<CursorEntry>) -> Self {
+        Self { entries }
+    }
+
+    /// The entry whose nominal size is closest to `size`, preferring the larger of two equally
+    /// close entries
+    pub fn nearest(&self, size: u32) -> Option<&CursorEntry> {
+        self.entries.iter().min_by_key(|entry| {
+            let distance = (i64::from(entry.nominal_size) - i64::from(size)).abs();
+            (distance, std::cmp::Reverse(entry.nominal_size))
+        })
+    }
+
+    /// Moves the hotspot of every entry to `hotspot`, given in pixels of a `width` by `height`
+    /// image and scaled to the size of each entry.
+    pub fn set_hotspot(&mut self, hotspot: Hotspot, width: u32, height: u32) {
+        for entry in &mut self.entries {
+            let scale = |coordinate: u16, from: u32, to: u32| {
+                (u32::from(coordinate) * to / from).min(to.saturating_sub(1)) as u16
+            };
+            entry.hotspot = Hotspot::new(
+                scale(hotspot.x, width, entry.width),
+                scale(hotspot.y, height, entry.height),
+            );
+        }
+    }
+
+    /// The entry with the largest area, if there is one
+    pub fn largest(&self) -> Option<&CursorEntry> {
+        self.entries
+            .iter()
+            .max_by_key(|entry| entry.width * entry.height)
+    }
+}
+
+/// A single image within a cursor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CursorEntry {
+    pub width: u32,
+    pub height: u32,
+    /// The cursor size this image is meant for. Xcursor themes record it separately, and it
+    /// need not match the dimensions of the image.
+    pub nominal_size: u32,
+    /// Bits per pixel of the image as it was stored on disk
+    pub bit_count: u16,
+    pub hotspot: Hotspot,
+    /// Non-premultiplied RGBA pixels, row by row starting at the top left
+    pub pixels: Vec<u8>,
+    /// One value per pixel, `true` where the screen shows through. Opaque pixels with it set
+    /// invert the screen, see `INVERTED`.
+    pub and_mask: Vec<bool>,
+}
+
+/// How pixels that invert the screen behind them are stored in `pixels`, and so how they are
+/// drawn wherever inverting is impossible, such as in X11 cursors or cursors with an alpha
+/// channel. Black shows up on the light backgrounds these pixels were mostly drawn for, like
+/// the text an I-beam sits on. Only `.cur` files with a palette can keep them inverting.
+pub const INVERTED: [u8; 4] = [0, 0, 0, 255];
+
+impl CursorEntry {
+    /// Creates a fully transparent 32-bit entry, nominally sized by its larger dimension.
+    pub fn new(width: u32, height: u32, hotspot: Hotspot) -> Self {
+        let len = (width * height) as usize;
+        Self {
+            width,
+            height,
+            nominal_size: width.max(height),
+            bit_count: 32,
+            hotspot,
+            pixels: vec![0; len * 4],
+            and_mask: vec![true; len],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let idx = self.index(x, y) * 4;
+        let mut rgba = [0; 4];
+        rgba.copy_from_slice(&self.pixels[idx..idx + 4]);
+        rgba
+    }
+
+    /// Sets a pixel and keeps the AND mask in sync with its alpha.
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let idx = self.index(x, y);
+        self.pixels[idx * 4..idx * 4 + 4].copy_from_slice(&rgba);
+        self.and_mask[idx] = rgba[3] == 0;
+    }
+
+    /// Makes a pixel invert the screen behind it.
+    pub fn set_inverted(&mut self, x: u32, y: u32) {
+        let idx = self.index(x, y);
+        self.pixels[idx * 4..idx * 4 + 4].copy_from_slice(&INVERTED);
+        self.and_mask[idx] = true;
+    }
+
+    pub fn is_inverted(&self, x: u32, y: u32) -> bool {
+        let idx = self.index(x, y);
+        self.and_mask[idx] && self.pixels[idx * 4 + 3] != 0
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height);
+        (y * self.width + x) as usize
+    }
+}
+
+/// The point within a cursor image that does the actual clicking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Hotspot {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Hotspot {
+    pub fn new(x: u16, y: u16) -> Self {
+        Self { x, y }
+    }
+}
+
+impl FromStr for Hotspot {
+    type Err = InvalidHotspot;
+
+    /// Parses a hotspot written as `x,y`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidHotspot(s.to_owned());
+        let comma = s.find(',').ok_or_else(invalid)?;
+        let x = s[..comma].trim().parse().map_err(|_| invalid())?;
+        let y = s[comma + 1..].trim().parse().map_err(|_| invalid())?;
+        Ok(Self::new(x, y))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidHotspot(pub String);
+
+impl fmt::Display for InvalidHotspot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid hotspot `{}`, expected `x,y`", self.0)
+    }
+}
+
+impl Error for InvalidHotspot {}
+
+/// A cursor that cycles through a sequence of images.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnimatedCursor {
+    pub frames: Vec<AnimationFrame>,
+}
+
+impl AnimatedCursor {
+    pub fn new(frames: Vec<AnimationFrame>) -> Self {
+        Self { frames }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationFrame {
+    pub image: CursorImage,
+    /// How long the frame stays on screen before the next one is shown
+    pub delay: Duration,
+}
+
+impl AnimationFrame {
+    pub fn new(image: CursorImage, delay: Duration) -> Self {
+        Self { image, delay }
+    }
+}
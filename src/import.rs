@@ -0,0 +1,244 @@
+//! Turning animated GIFs and PNGs into animated cursors.
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    format::{self, Format, FormatError},
+    image::{AnimatedCursor, AnimationFrame, CursorEntry, CursorImage, Hotspot},
+    transform,
+};
+
+/// The size cursors are drawn at without display scaling
+const DEFAULT_SIZE: u32 = 32;
+/// Browsers show frames of `MIN_DELAY` or less for `SHORT_DELAY` instead, and GIFs are made to
+/// look right in browsers
+const MIN_DELAY: Duration = Duration::from_millis(10);
+const SHORT_DELAY: Duration = Duration::from_millis(100);
+
+/// Writes the animation in the GIF or PNG at `input` to `output` as an animated cursor, with
+/// each frame drawn over the ones before it the way browsers play it. The canvas is centred on
+/// a transparent square and resized to each of `sizes`, or to 32 pixels if there are none. The
+/// hotspot is in pixels of the canvas.
+pub fn import(
+    input: &Path,
+    hotspot: Hotspot,
+    format: Format,
+    sizes: &[u32],
+    output: &Path,
+) -> Result<(), ImportError> {
+    let sizes = if sizes.is_empty() {
+        &[DEFAULT_SIZE][..]
+    } else {
+        sizes
+    };
+    if let Some(&size) = sizes.iter().find(|&&size| size > format.max_size()) {
+        return Err(ImportError::SizeTooLarge { size, format });
+    }
+
+    let bytes = fs::read(input).map_err(|err| ImportError::Read(input.to_owned(), err))?;
+    let animation = format::decode_animation(&bytes)
+        .map_err(|err| ImportError::Decode(input.to_owned(), err))?;
+    if u32::from(hotspot.x) >= animation.width || u32::from(hotspot.y) >= animation.height {
+        return Err(ImportError::HotspotOutside {
+            hotspot,
+            width: animation.width,
+            height: animation.height,
+        });
+    }
+    if format == Format::Cur && animation.frames.len() > 1 {
+        return Err(ImportError::CannotAnimate(format));
+    }
+
+    let frames = animation
+        .composite()
+        .into_iter()
+        .map(|(mut canvas, delay)| {
+            canvas.hotspot = hotspot;
+            let image = CursorImage::new(vec![square(&canvas)]);
+            let delay = if delay <= MIN_DELAY {
+                SHORT_DELAY
+            } else {
+                delay
+            };
+            AnimationFrame::new(transform::multi_size(&image, sizes), delay)
+        })
+        .collect();
+    let cursor = AnimatedCursor::new(frames);
+    fs::write(output, format::encode(&cursor, format))
+        .map_err(|err| ImportError::Write(output.to_owned(), err))
+}
+
+/// Centres an entry on a transparent square as large as its larger side, moving the hotspot
+/// along.
+fn square(entry: &CursorEntry) -> CursorEntry {
+    let side = entry.width.max(entry.height);
+    let (left, top) = ((side - entry.width) / 2, (side - entry.height) / 2);
+    let hotspot = Hotspot::new(entry.hotspot.x + left as u16, entry.hotspot.y + top as u16);
+    let mut square = CursorEntry::new(side, side, hotspot);
+    for y in 0..entry.height {
+        for x in 0..entry.width {
+            square.set_pixel(left + x, top + y, entry.pixel(x, y));
+        }
+    }
+    square
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Read(PathBuf, io::Error),
+    Decode(PathBuf, FormatError),
+    /// A size asked for with `--sizes` is more than the format can hold
+    SizeTooLarge {
+        size: u32,
+        format: Format,
+    },
+    HotspotOutside {
+        hotspot: Hotspot,
+        width: u32,
+        height: u32,
+    },
+    /// The format only holds a single frame
+    CannotAnimate(Format),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Decode(path, err) => {
+                write!(f, "{} is not a usable GIF or PNG: {}", path.display(), err)
+            }
+            Self::SizeTooLarge { size, format } => write!(
+                f,
+                "a {} file holds at most {}x{} pixels, too few for size {}",
+                format.as_str(),
+                format.max_size(),
+                format.max_size(),
+                size
+            ),
+            Self::HotspotOutside {
+                hotspot,
+                width,
+                height,
+            } => write!(
+                f,
+                "hotspot {},{} is outside the {}x{} animation",
+                hotspot.x, hotspot.y, width, height
+            ),
+            Self::CannotAnimate(format) => write!(
+                f,
+                "a {} file cannot animate, write an ani or xcursor file instead",
+                format.as_str()
+            ),
+            Self::Write(path, err) => write!(f, "could not write {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(_, err) | Self::Write(_, err) => Some(err),
+            Self::Decode(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/import");
+
+    /// Imports a fixture into the temporary directory, returning the cursor decoded.
+    fn import_fixture(name: &str, format: Format, sizes: &[u32]) -> AnimatedCursor {
+        let output = env::temp_dir().join(format!(
+            "justaprankbro-test-{}-import-{}.{}",
+            process::id(),
+            name,
+            format.as_str()
+        ));
+        import(
+            &Path::new(FIXTURES).join(name),
+            Hotspot::new(1, 1),
+            format,
+            sizes,
+            &output,
+        )
+        .unwrap();
+        let bytes = fs::read(&output).unwrap();
+        let _ = fs::remove_file(&output);
+        format::decode(&bytes).unwrap()
+    }
+
+    #[test]
+    fn imports_gifs_and_apngs_as_they_play() {
+        for name in &["dispose-previous.gif", "dispose-previous.png"] {
+            let cursor = import_fixture(name, Format::Xcursor, &[4]);
+            assert_eq!(cursor.frames.len(), 3, "{}", name);
+            let last = &cursor.frames[2].image.entries[0];
+            assert_eq!((last.width, last.height), (4, 4), "{}", name);
+            assert_eq!(last.hotspot, Hotspot::new(1, 1), "{}", name);
+            assert_eq!(last.pixel(0, 0), [0, 0, 0xff, 0xff], "{}", name);
+            assert_eq!(last.pixel(2, 2), [0xff, 0, 0, 0xff], "{}", name);
+        }
+    }
+
+    #[test]
+    fn short_delays_are_shown_the_way_browsers_show_them() {
+        let cursor = import_fixture("dispose-keep.gif", Format::Xcursor, &[4]);
+        let delays: Vec<_> = cursor.frames.iter().map(|frame| frame.delay).collect();
+        assert_eq!(
+            delays,
+            vec![Duration::from_millis(70), SHORT_DELAY, SHORT_DELAY]
+        );
+    }
+
+    #[test]
+    fn delays_are_rounded_to_jiffies_in_ani_files() {
+        let jiffies = |n: u64| Duration::from_nanos(n * (1_000_000_000 / 60));
+        let cursor = import_fixture("dispose-keep.png", Format::Ani, &[4]);
+        let delays: Vec<_> = cursor.frames.iter().map(|frame| frame.delay).collect();
+        // 70ms is 4.2 jiffies and 100ms is 6.
+        assert_eq!(delays, vec![jiffies(4), jiffies(6), jiffies(6)]);
+    }
+
+    #[test]
+    fn squares_the_canvas_and_moves_the_hotspot() {
+        let mut entry = CursorEntry::new(2, 4, Hotspot::new(1, 3));
+        entry.set_pixel(0, 0, [1, 2, 3, 4]);
+        let square = square(&entry);
+        assert_eq!((square.width, square.height), (4, 4));
+        assert_eq!(square.hotspot, Hotspot::new(2, 3));
+        assert_eq!(square.pixel(1, 0), [1, 2, 3, 4]);
+        assert_eq!(square.pixel(0, 0), [0; 4]);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_write() {
+        let input = Path::new(FIXTURES).join("dispose-keep.gif");
+        let output = Path::new("never-written.cur");
+        let err = import(&input, Hotspot::new(4, 0), Format::Ani, &[], output).unwrap_err();
+        assert!(matches!(
+            err,
+            ImportError::HotspotOutside {
+                width: 4,
+                height: 4,
+                ..
+            }
+        ));
+        let err = import(&input, Hotspot::default(), Format::Cur, &[], output).unwrap_err();
+        assert!(matches!(err, ImportError::CannotAnimate(Format::Cur)));
+        let err = import(&input, Hotspot::default(), Format::Cur, &[512], output).unwrap_err();
+        assert!(matches!(err, ImportError::SizeTooLarge { size: 512, .. }));
+        assert!(!output.exists());
+    }
+}
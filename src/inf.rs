@@ -0,0 +1,393 @@
+//! The `install.inf` files that come with downloadable Windows cursor schemes.
+//!
+//! Installing a scheme writes registry values listed in its `[Scheme.Reg]` section, either one
+//! comma separated list of every cursor under `Control Panel\Cursors\Schemes` or a value per
+//! cursor under `Control Panel\Cursors`. Paths in them are built from `%name%` references to
+//! the `[Strings]` section and point where the files would be copied, so only the file name is
+//! kept and looked up next to the .inf file instead.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::cursor::CursorKind;
+
+/// The cursors of a scheme value in order. Windows has a few roles with no `CursorKind`, which
+/// are `None`.
+const SCHEME_ORDER: [Option<CursorKind>; 15] = [
+    Some(CursorKind::Normal),
+    None, // Help
+    Some(CursorKind::AppStarting),
+    Some(CursorKind::Wait),
+    Some(CursorKind::Crosshair),
+    Some(CursorKind::Ibeam),
+    None, // NWPen
+    Some(CursorKind::No),
+    Some(CursorKind::SizeNs),
+    Some(CursorKind::SizeWe),
+    Some(CursorKind::SizeNwSe),
+    Some(CursorKind::SizeNeSw),
+    Some(CursorKind::SizeAll),
+    Some(CursorKind::Up),
+    Some(CursorKind::Hand),
+];
+
+const DEFAULT_REG_SECTION: &str = "Scheme.Reg";
+
+/// Reads the cursor files an .inf file would install, resolving them against `dir`. Cursors
+/// set on their own take precedence over those from a scheme list.
+pub fn parse(text: &str, dir: &Path) -> Result<Vec<(CursorKind, PathBuf)>, String> {
+    let sections = sections(text);
+    let strings: HashMap<String, String> = section(&sections, "Strings")
+        .iter()
+        .filter_map(|&(_, line)| {
+            let eq = line.find('=')?;
+            let key = unquote(line[..eq].trim()).to_ascii_lowercase();
+            Some((key, unquote(line[eq + 1..].trim())))
+        })
+        .collect();
+
+    // The install section names the sections it adds registry values from.
+    let mut reg_sections: Vec<String> = section(&sections, "DefaultInstall")
+        .iter()
+        .filter_map(|&(_, line)| {
+            let eq = line.find('=')?;
+            if line[..eq].trim().eq_ignore_ascii_case("AddReg") {
+                Some(fields(&line[eq + 1..]))
+            } else {
+                None
+            }
+        })
+        .flatten()
+        .collect();
+    if reg_sections.is_empty() {
+        reg_sections.push(DEFAULT_REG_SECTION.to_owned());
+    }
+
+    let mut from_scheme = Vec::new();
+    let mut single = Vec::new();
+    for name in &reg_sections {
+        for &(number, line) in section(&sections, name) {
+            let fields: Vec<String> = fields(line)
+                .iter()
+                .map(|field| expand(field, &strings))
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("line {}: {}", number, err))?;
+            let (subkey, value_name, value) = match fields.as_slice() {
+                [_, subkey, value_name, _, value, ..] => (subkey, value_name, value),
+                _ => continue,
+            };
+
+            if subkey.eq_ignore_ascii_case(r"Control Panel\Cursors\Schemes") {
+                for (kind, path) in SCHEME_ORDER.iter().zip(value.split(',')) {
+                    if let Some(kind) = kind {
+                        if let Some(path) = file_in(path, dir) {
+                            from_scheme.push((*kind, path));
+                        }
+                    }
+                }
+            } else if subkey.eq_ignore_ascii_case(r"Control Panel\Cursors") {
+                let kind = CursorKind::ALL
+                    .iter()
+                    .find(|kind| kind.registry_name().eq_ignore_ascii_case(value_name));
+                if let (Some(kind), Some(path)) = (kind, file_in(value, dir)) {
+                    single.push((*kind, path));
+                }
+            }
+        }
+    }
+
+    let mut cursors: Vec<(CursorKind, PathBuf)> = Vec::new();
+    for (kind, path) in from_scheme.into_iter().chain(single) {
+        match cursors.iter_mut().find(|(k, _)| *k == kind) {
+            Some(entry) => entry.1 = path,
+            None => cursors.push((kind, path)),
+        }
+    }
+    if cursors.is_empty() {
+        return Err("the file installs no cursors".to_owned());
+    }
+    Ok(cursors)
+}
+
+/// Lines of each section with their line numbers, keyed by the lowercased section name.
+/// Comments and blank lines are dropped.
+fn sections(text: &str) -> HashMap<String, Vec<(usize, &str)>> {
+    let mut sections: HashMap<String, Vec<(usize, &str)>> = HashMap::new();
+    let mut current = None;
+    for (idx, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_ascii_lowercase();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+        } else if let Some(name) = &current {
+            sections
+                .entry(name.clone())
+                .or_default()
+                .push((idx + 1, line));
+        }
+    }
+    sections
+}
+
+fn section<'a, 'b>(
+    sections: &'a HashMap<String, Vec<(usize, &'b str)>>,
+    name: &str,
+) -> &'a [(usize, &'b str)] {
+    sections
+        .get(&name.to_ascii_lowercase())
+        .map_or(&[], Vec::as_slice)
+}
+
+/// Cuts a line at the first semicolon outside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits a line on commas outside quotes, unquoting each field.
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(unquote(line[start..idx].trim()));
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(unquote(line[start..].trim()));
+    fields
+}
+
+/// Removes quotes around a value, where `""` stands for a quote of its own.
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1..value.len() - 1].replace("\"\"", "\"")
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Replaces `%name%` with the string of that name and `%%` with a percent sign. References to
+/// numbered system directories like `%10%` are dropped, since only file names matter.
+fn expand(value: &str, strings: &HashMap<String, String>) -> Result<String, String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find('%')
+            .ok_or_else(|| format!("unmatched `%` in `{}`", value))?;
+        let name = &after[..end];
+        if name.is_empty() {
+            expanded.push('%');
+        } else if !name.bytes().all(|b| b.is_ascii_digit()) {
+            let string = strings
+                .get(&name.to_ascii_lowercase())
+                .ok_or_else(|| format!("no string named `{}`", name))?;
+            expanded.push_str(string);
+        }
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// The file named at the end of a Windows path, looked up in `dir`. Windows ignores case, so
+/// a file differing only in case is taken if there is no exact match.
+fn file_in(path: &str, dir: &Path) -> Option<PathBuf> {
+    let name = path.trim().rsplit(&['\\', '/'][..]).next()?;
+    if name.is_empty() {
+        return None;
+    }
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    // An .inf file named on its own sits in the current directory, which reads as "".
+    let listed = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let found = fs::read_dir(listed).ok().and_then(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .find(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(name)
+            })
+            .map(|entry| dir.join(entry.file_name()))
+    });
+    // A file that isn't there at all is still named, so loading it can say which.
+    Some(found.unwrap_or(exact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// A scheme as the usual installers write it, with the Hand cursor set again on its own
+    const INSTALL_INF: &str = r#"; Clowns cursor scheme
+[Version]
+signature="$CHICAGO$"
+
+[DefaultInstall]
+CopyFiles = Scheme.Cur
+AddReg    = Scheme.Reg, Scheme.Single
+
+[DestinationDirs]
+Scheme.Cur = 10,"%CUR_DIR%"
+
+[Scheme.Reg]
+HKCU,"Control Panel\Cursors\Schemes","%SCHEME_NAME%",,"%10%\%CUR_DIR%\%pointer%,%10%\%CUR_DIR%\%help%,%10%\%CUR_DIR%\%work%,%10%\%CUR_DIR%\%busy%,%10%\%CUR_DIR%\%cross%,%10%\%CUR_DIR%\%text%,%10%\%CUR_DIR%\%pen%,%10%\%CUR_DIR%\%no%,%10%\%CUR_DIR%\%vert%,%10%\%CUR_DIR%\%horz%,%10%\%CUR_DIR%\%dgn1%,%10%\%CUR_DIR%\%dgn2%,%10%\%CUR_DIR%\%move%,%10%\%CUR_DIR%\%up%,%10%\%CUR_DIR%\%link%"
+
+[Scheme.Single]
+HKCU,"Control Panel\Cursors",Hand,0x00020000,"%10%\%CUR_DIR%\glove.ani" ; the better hand
+HKCU,"Control Panel\Cursors",Pin,0x00020000,"%10%\%CUR_DIR%\pin.cur"
+HKCU,"Control Panel\Cursors",,0x00020000
+
+[Strings]
+CUR_DIR     = "Cursors\Clowns"
+SCHEME_NAME = "Clowns"
+pointer     = "pointer.cur"
+help        = "help.cur"
+work        = "work.ani"
+busy        = "busy.ani"
+cross       = "cross.cur"
+Text        = "text.cur"
+pen         = "pen.cur"
+no          = "no.cur"
+vert        = "vert.cur"
+horz        = "horz.cur"
+dgn1        = "dgn1.cur"
+dgn2        = "dgn2.cur"
+move        = "move.cur"
+up          = "up.cur"
+link        = "link.cur"
+"#;
+
+    fn names(cursors: &[(CursorKind, PathBuf)]) -> Vec<(CursorKind, String)> {
+        cursors
+            .iter()
+            .map(|(kind, path)| (*kind, path.to_string_lossy().into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_scheme() {
+        let cursors = parse(INSTALL_INF, Path::new("clowns")).unwrap();
+        assert_eq!(
+            names(&cursors),
+            vec![
+                (CursorKind::Normal, "clowns/pointer.cur".to_owned()),
+                (CursorKind::AppStarting, "clowns/work.ani".to_owned()),
+                (CursorKind::Wait, "clowns/busy.ani".to_owned()),
+                (CursorKind::Crosshair, "clowns/cross.cur".to_owned()),
+                (CursorKind::Ibeam, "clowns/text.cur".to_owned()),
+                (CursorKind::No, "clowns/no.cur".to_owned()),
+                (CursorKind::SizeNs, "clowns/vert.cur".to_owned()),
+                (CursorKind::SizeWe, "clowns/horz.cur".to_owned()),
+                (CursorKind::SizeNwSe, "clowns/dgn1.cur".to_owned()),
+                (CursorKind::SizeNeSw, "clowns/dgn2.cur".to_owned()),
+                (CursorKind::SizeAll, "clowns/move.cur".to_owned()),
+                (CursorKind::Up, "clowns/up.cur".to_owned()),
+                // Set on its own, which beats the scheme's link.cur
+                (CursorKind::Hand, "clowns/glove.ani".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_scheme_reg_without_an_install_section() {
+        let inf = r#"
+[scheme.reg]
+HKCU,"Control Panel\Cursors","Arrow",0x00020000,"%10%\Cursors\a.cur"
+HKCU,"Control Panel\Cursors","uparrow",0x00020000,"C:/Cursors/u.cur"
+HKCU,"Control Panel\Cursors","Wait",0x00020000,""
+"#;
+        assert_eq!(
+            names(&parse(inf, Path::new("")).unwrap()),
+            vec![
+                (CursorKind::Normal, "a.cur".to_owned()),
+                (CursorKind::Up, "u.cur".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bad_strings() {
+        let missing = INSTALL_INF.replace("busy        = \"busy.ani\"\n", "");
+        assert_eq!(
+            parse(&missing, Path::new("")),
+            Err("line 13: no string named `busy`".to_owned())
+        );
+        let unmatched = "[Scheme.Reg]\nHKCU,\"Control Panel\\Cursors\",Arrow,0,\"%oops\"";
+        assert_eq!(
+            parse(unmatched, Path::new("")),
+            Err("line 2: unmatched `%` in `%oops`".to_owned())
+        );
+        assert_eq!(
+            parse("[Version]\nsignature=\"$CHICAGO$\"", Path::new("")),
+            Err("the file installs no cursors".to_owned())
+        );
+    }
+
+    #[test]
+    fn splits_and_expands_fields() {
+        assert_eq!(
+            fields(r#"a, "b, c" ,"say ""hi""",,"#),
+            vec!["a", "b, c", "say \"hi\"", "", ""]
+        );
+        assert_eq!(strip_comment(r#"a,"b;c" ; comment"#), r#"a,"b;c" "#);
+
+        let strings: HashMap<String, String> = vec![("name".to_owned(), "x".to_owned())]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            expand(r"%10%\%NAME%\100%%", &strings),
+            Ok(r"\x\100%".to_owned())
+        );
+    }
+
+    #[test]
+    fn finds_files_whatever_their_case() {
+        let dir = env::temp_dir().join(format!("justaprankbro-test-{}-inf", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Pointer.CUR"), b"").unwrap();
+
+        assert_eq!(
+            file_in(r"%10%\Cursors\pointer.cur", &dir),
+            Some(dir.join("Pointer.CUR"))
+        );
+        assert_eq!(file_in("gone.cur", &dir), Some(dir.join("gone.cur")));
+        // Nor does a directory that can't be read lose the file.
+        let gone = dir.join("gone");
+        assert_eq!(file_in("a.cur", &gone), Some(gone.join("a.cur")));
+        assert_eq!(file_in(r"Cursors\", &dir), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
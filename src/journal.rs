@@ -0,0 +1,330 @@
+//! An on-disk record of which system cursors have been replaced and where their originals came
+//! from, so they can be put back even if the process that replaced them never got to.
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::cursor::{CursorBackend, CursorError, CursorKind, CursorSource};
+
+const HEADER: &str = "# justaprankbro restoration journal";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub kind: CursorKind,
+    pub source: CursorSource,
+}
+
+#[derive(Clone, Debug)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The journal in the per-user state directory.
+    pub fn open_default() -> io::Result<Self> {
+        Ok(Self::new(state_dir()?.join("journal")))
+    }
+
+    /// A journal of its own for a test, in the temporary directory and empty to begin with.
+    #[cfg(test)]
+    pub fn temporary(name: &str) -> Self {
+        let path = env::temp_dir().join(format!(
+            "justaprankbro-test-{}-{}.journal",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        Self::new(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> io::Result<Vec<JournalEntry>> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Records the original source of `kind`, unless the journal already has one, and returns
+    /// whether it did.
+    ///
+    /// An existing record wins over a new one, because after a crash the system might still be
+    /// showing a prank cursor for that kind.
+    pub fn record(&self, kind: CursorKind, source: &CursorSource) -> io::Result<bool> {
+        let mut entries = self.entries()?;
+        if entries.iter().any(|entry| entry.kind == kind) {
+            return Ok(false);
+        }
+        entries.push(JournalEntry {
+            kind,
+            source: source.clone(),
+        });
+        self.write(&entries)?;
+        Ok(true)
+    }
+
+    pub fn remove(&self, kind: CursorKind) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        let len = entries.len();
+        entries.retain(|entry| entry.kind != kind);
+        if entries.len() == len {
+            Ok(())
+        } else {
+            self.write(&entries)
+        }
+    }
+
+    /// Puts back every cursor in the journal, forgetting each one that was restored.
+    pub fn restore<B: CursorBackend>(&self, backend: &B) -> Result<Vec<CursorKind>, CursorError> {
+        let mut restored = Vec::new();
+        for entry in self.entries()? {
+            backend.restore(entry.kind, &entry.source)?;
+            self.remove(entry.kind)?;
+            restored.push(entry.kind);
+        }
+        Ok(restored)
+    }
+
+    /// Writes the whole journal to a temporary file and moves it into place, so a crash
+    /// halfway through never leaves a truncated journal behind.
+    fn write(&self, entries: &[JournalEntry]) -> io::Result<()> {
+        if entries.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(serialize(entries).as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// The per-user directory for the journal and anything else kept between runs.
+pub fn state_dir() -> io::Result<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+    };
+    base.map(|base| base.join("justaprankbro")).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find a directory for the restoration journal",
+        )
+    })
+}
+
+/// One entry per line: the cursor kind, the source type and its details, separated by tabs.
+fn serialize(entries: &[JournalEntry]) -> String {
+    let mut text = String::from(HEADER);
+    text.push('\n');
+    for entry in entries {
+        text.push_str(entry.kind.as_str());
+        text.push('\t');
+        match &entry.source {
+            CursorSource::Default => text.push_str("default"),
+            CursorSource::File(path) => {
+                text.push_str("file\t");
+                text.push_str(&path.to_string_lossy());
+            }
+            CursorSource::Theme { name, size } => {
+                text.push_str(&format!("theme\t{}\t{}", size, name));
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn parse(text: &str) -> io::Result<Vec<JournalEntry>> {
+    // Every line is written whole, so a journal cut off partway through one can't be trusted to
+    // name the right cursor.
+    if !text.is_empty() && !text.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "journal ends partway through a line",
+        ));
+    }
+    let mut entries = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("journal line {}: {}", idx + 1, message),
+            )
+        };
+
+        let mut fields = line.splitn(3, '\t');
+        let kind = fields.next().unwrap_or_default();
+        let kind = kind.parse().map_err(|err| invalid(format!("{}", err)))?;
+        let source = match (fields.next(), fields.next()) {
+            (Some("default"), None) => CursorSource::Default,
+            (Some("file"), Some(path)) => CursorSource::File(PathBuf::from(path)),
+            (Some("theme"), Some(rest)) => {
+                let mut rest = rest.splitn(2, '\t');
+                let size = rest.next().and_then(|size| size.parse().ok());
+                match (size, rest.next()) {
+                    (Some(size), Some(name)) => CursorSource::Theme {
+                        name: name.to_owned(),
+                        size,
+                    },
+                    _ => return Err(invalid(format!("malformed theme source `{}`", line))),
+                }
+            }
+            _ => return Err(invalid(format!("malformed entry `{}`", line))),
+        };
+        entries.push(JournalEntry { kind, source });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{MockBackend, MockCall as Call};
+
+    fn sources() -> Vec<JournalEntry> {
+        vec![
+            JournalEntry {
+                kind: CursorKind::Normal,
+                source: CursorSource::Default,
+            },
+            JournalEntry {
+                kind: CursorKind::Hand,
+                source: CursorSource::File(PathBuf::from("/usr/share/icons/hand with spaces.cur")),
+            },
+            JournalEntry {
+                kind: CursorKind::Ibeam,
+                source: CursorSource::Theme {
+                    name: "Adwaita\tdark".to_owned(),
+                    size: 48,
+                },
+            },
+        ]
+    }
+
+    fn write_raw(journal: &Journal, text: &str) {
+        fs::write(journal.path(), text).unwrap();
+    }
+
+    #[test]
+    fn round_trips_every_source() {
+        let journal = Journal::temporary("round-trip");
+        for entry in sources() {
+            assert!(journal.record(entry.kind, &entry.source).unwrap());
+        }
+        assert_eq!(journal.entries().unwrap(), sources());
+        assert_eq!(parse(&serialize(&sources())).unwrap(), sources());
+    }
+
+    #[test]
+    fn first_record_wins() {
+        let journal = Journal::temporary("first-record-wins");
+        let first = CursorSource::File(PathBuf::from("first.cur"));
+        assert!(journal.record(CursorKind::Normal, &first).unwrap());
+        assert!(!journal
+            .record(CursorKind::Normal, &CursorSource::Default)
+            .unwrap());
+        assert_eq!(journal.entries().unwrap()[0].source, first);
+    }
+
+    #[test]
+    fn removing_the_last_entry_deletes_the_file() {
+        let journal = Journal::temporary("remove");
+        journal
+            .record(CursorKind::Normal, &CursorSource::Default)
+            .unwrap();
+        journal
+            .record(CursorKind::Hand, &CursorSource::Default)
+            .unwrap();
+        journal.remove(CursorKind::Normal).unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 1);
+        journal.remove(CursorKind::Hand).unwrap();
+        assert!(!journal.path().exists());
+        journal.remove(CursorKind::Hand).unwrap();
+    }
+
+    #[test]
+    fn restores_and_forgets_every_entry() {
+        let journal = Journal::temporary("restore");
+        for entry in sources() {
+            journal.record(entry.kind, &entry.source).unwrap();
+        }
+        let backend = MockBackend::new();
+
+        let restored = journal.restore(&backend).unwrap();
+        assert_eq!(
+            restored,
+            sources().iter().map(|entry| entry.kind).collect::<Vec<_>>()
+        );
+        let calls: Vec<Call> = sources()
+            .into_iter()
+            .map(|entry| Call::Restore(entry.kind, entry.source))
+            .collect();
+        assert_eq!(backend.calls(), calls);
+        assert!(journal.entries().unwrap().is_empty());
+        assert!(journal.restore(&backend).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_journal_is_an_error() {
+        let journal = Journal::temporary("truncated");
+        let text = serialize(&sources());
+        // Cut partway into each field of the last line
+        let last_line = text[..text.len() - 1].rfind('\n').unwrap() + 1;
+        for cut in &[last_line + 3, last_line + 6, last_line + 12, last_line + 15] {
+            write_raw(&journal, &text[..*cut]);
+            let err = journal.entries().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "cut at {}", cut);
+        }
+        // A cut at the end of a line loses the entries after it, but nothing more.
+        write_raw(&journal, &text[..last_line]);
+        assert_eq!(journal.entries().unwrap(), sources()[..2].to_vec());
+    }
+
+    #[test]
+    fn corrupt_journal_is_an_error_and_left_alone() {
+        let journal = Journal::temporary("corrupt");
+        for text in &[
+            "Normal\tdefault\n\u{0}\u{1}garbage\n",
+            "Pointer\tdefault\n",
+            "Normal\tfloppy\tA:\\cursor.cur\n",
+            "Normal\ttheme\tbig\tAdwaita\n",
+            "Normal\tdefault\textra\n",
+        ] {
+            write_raw(&journal, text);
+            let err = journal.entries().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", text);
+
+            let backend = MockBackend::new();
+            assert!(journal.restore(&backend).is_err());
+            assert!(backend.calls().is_empty());
+            assert_eq!(fs::read_to_string(journal.path()).unwrap(), *text);
+        }
+    }
+}
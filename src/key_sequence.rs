@@ -0,0 +1,392 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use winit::event::VirtualKeyCode;
+
+/// Watches key presses for a sequence of keys typed in a row.
+///
+/// Matching uses the Knuth-Morris-Pratt failure function, so a wrong key only throws away as
+/// much progress as it has to: "jjustaprankbro" still unlocks "justaprankbro".
+#[derive(Debug)]
+pub struct KeySequence {
+    keys: Vec<VirtualKeyCode>,
+    /// `failure[i]` is the length of the longest proper prefix of `keys[..=i]` that is also a
+    /// suffix of it
+    failure: Vec<usize>,
+    matched: usize,
+}
+
+impl KeySequence {
+    /// Panics if `keys` is empty.
+    pub fn new(keys: Vec<VirtualKeyCode>) -> Self {
+        assert!(!keys.is_empty(), "a key sequence needs at least one key");
+
+        let mut failure = vec![0; keys.len()];
+        let mut len = 0;
+        for i in 1..keys.len() {
+            while len > 0 && keys[i] != keys[len] {
+                len = failure[len - 1];
+            }
+            if keys[i] == keys[len] {
+                len += 1;
+            }
+            failure[i] = len;
+        }
+
+        Self {
+            keys,
+            failure,
+            matched: 0,
+        }
+    }
+
+    /// Returns `true` whenever the keys pressed so far end with the sequence.
+    pub fn process_input(&mut self, keycode: VirtualKeyCode) -> bool {
+        while self.matched > 0 && self.keys[self.matched] != keycode {
+            self.matched = self.failure[self.matched - 1];
+        }
+        if self.keys[self.matched] == keycode {
+            self.matched += 1;
+        }
+
+        if self.matched == self.keys.len() {
+            self.matched = self.failure[self.matched - 1];
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for KeySequence {
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+
+        Self::new(vec![J, U, S, T, A, P, R, A, N, K, B, R, O])
+    }
+}
+
+impl FromStr for KeySequence {
+    type Err = ParseKeySequenceError;
+
+    /// Parses a sequence such as `prank{Space}bro{F5}`, see `parse_keys`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = parse_keys(s)?;
+        if keys.is_empty() {
+            return Err(ParseKeySequenceError::Empty);
+        }
+        Ok(Self::new(keys))
+    }
+}
+
+/// Reads the keys typed by text such as `prank{Space}bro{F5}`. Letters, digits, spaces and
+/// unshifted punctuation stand for their own key, other keys are named in braces.
+pub fn parse_keys(s: &str) -> Result<Vec<VirtualKeyCode>, ParseKeySequenceError> {
+    let mut keys = Vec::new();
+    let mut chars = s.chars().enumerate();
+
+    while let Some((position, c)) = chars.next() {
+        let key = if c == '{' {
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => name.push(c),
+                    None => return Err(ParseKeySequenceError::UnclosedBrace { position }),
+                }
+            }
+            named_key(&name).ok_or(ParseKeySequenceError::UnknownKeyName(name))?
+        } else {
+            char_key(c).ok_or(ParseKeySequenceError::UnmappableChar { position, c })?
+        };
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseKeySequenceError {
+    Empty,
+    /// A character that no single key produces, given by its zero-based position
+    UnmappableChar {
+        position: usize,
+        c: char,
+    },
+    UnknownKeyName(String),
+    UnclosedBrace {
+        position: usize,
+    },
+}
+
+impl fmt::Display for ParseKeySequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the sequence is empty"),
+            Self::UnmappableChar { position, c } => write!(
+                f,
+                "no key types {:?} (character {}); name keys in braces, like {{Space}}",
+                c,
+                position + 1
+            ),
+            Self::UnknownKeyName(name) => write!(f, "unknown key name {{{}}}", name),
+            Self::UnclosedBrace { position } => {
+                write!(f, "the brace at character {} is never closed", position + 1)
+            }
+        }
+    }
+}
+
+impl Error for ParseKeySequenceError {}
+
+fn char_key(c: char) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] =
+        [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+
+    let key = match c {
+        'a'..='z' => LETTERS[(c as u8 - b'a') as usize],
+        'A'..='Z' => LETTERS[(c as u8 - b'A') as usize],
+        '0'..='9' => DIGITS[(c as u8 - b'0') as usize],
+        ' ' => Space,
+        '\'' => Apostrophe,
+        ',' => Comma,
+        '-' => Minus,
+        '.' => Period,
+        '/' => Slash,
+        ';' => Semicolon,
+        '=' => Equals,
+        '[' => LBracket,
+        '\\' => Backslash,
+        ']' => RBracket,
+        '`' => Grave,
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn named_key(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+
+    const FUNCTION_KEYS: [VirtualKeyCode; 24] = [
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19, F20,
+        F21, F22, F23, F24,
+    ];
+    const NUMPAD_KEYS: [VirtualKeyCode; 10] = [
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ];
+    const NAMED_KEYS: &[(&str, VirtualKeyCode)] = &[
+        ("Space", Space),
+        ("Enter", Return),
+        ("Return", Return),
+        ("Tab", Tab),
+        ("Escape", Escape),
+        ("Esc", Escape),
+        ("Backspace", Back),
+        ("Delete", Delete),
+        ("Insert", Insert),
+        ("Home", Home),
+        ("End", End),
+        ("PageUp", PageUp),
+        ("PageDown", PageDown),
+        ("Left", Left),
+        ("Right", Right),
+        ("Up", Up),
+        ("Down", Down),
+        ("LShift", LShift),
+        ("RShift", RShift),
+        ("LControl", LControl),
+        ("RControl", RControl),
+        ("LAlt", LAlt),
+        ("RAlt", RAlt),
+        ("LWin", LWin),
+        ("RWin", RWin),
+        ("CapsLock", Capital),
+        ("NumLock", Numlock),
+        ("ScrollLock", Scroll),
+        ("Pause", Pause),
+        ("PrintScreen", Snapshot),
+    ];
+
+    numbered_key(name, "F", 1, &FUNCTION_KEYS)
+        .or_else(|| numbered_key(name, "Numpad", 0, &NUMPAD_KEYS))
+        .or_else(|| {
+            NAMED_KEYS
+                .iter()
+                .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+                .map(|&(_, key)| key)
+        })
+}
+
+/// Looks up names like `F5`, where the number counts up from `first`.
+fn numbered_key(
+    name: &str,
+    prefix: &str,
+    first: usize,
+    keys: &[VirtualKeyCode],
+) -> Option<VirtualKeyCode> {
+    let number = name.get(prefix.len()..)?;
+    if !name[..prefix.len()].eq_ignore_ascii_case(prefix)
+        || number.is_empty()
+        || !number.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let number: usize = number.parse().ok()?;
+    keys.get(number.checked_sub(first)?).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    fn keys(s: &str) -> Vec<VirtualKeyCode> {
+        s.chars().map(|c| char_key(c).unwrap()).collect()
+    }
+
+    /// Feeds `typed` to a matcher for `sequence`, checking after every key that it unlocks exactly
+    /// when the keys typed so far end with the sequence.
+    fn check(sequence: &[VirtualKeyCode], typed: &[VirtualKeyCode]) {
+        let mut matcher = KeySequence::new(sequence.to_vec());
+        for i in 0..typed.len() {
+            assert_eq!(
+                matcher.process_input(typed[i]),
+                typed[..=i].ends_with(sequence),
+                "sequence {:?} after typing {:?}",
+                sequence,
+                &typed[..=i]
+            );
+        }
+    }
+
+    #[test]
+    fn unlocks_after_a_false_start() {
+        check(&KeySequence::default().keys, &keys("jjustaprankbro"));
+        check(
+            &KeySequence::default().keys,
+            &keys("justaprankbjustaprankbro"),
+        );
+    }
+
+    #[test]
+    fn parses_characters() {
+        use VirtualKeyCode::*;
+        assert_eq!(
+            parse_keys("Ab9 ,-./;=[\\]`'").unwrap(),
+            vec![
+                A, B, Key9, Space, Comma, Minus, Period, Slash, Semicolon, Equals, LBracket,
+                Backslash, RBracket, Grave, Apostrophe
+            ]
+        );
+        assert_eq!(parse_keys("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn parses_named_keys() {
+        use VirtualKeyCode::*;
+        assert_eq!(
+            parse_keys("{Space}{F5}{Numpad0}{F24}{Numpad9}{Enter}{PageUp}").unwrap(),
+            vec![Space, F5, Numpad0, F24, Numpad9, Return, PageUp]
+        );
+        // Names ignore case.
+        assert_eq!(
+            parse_keys("{space}{f5}{NUMPAD0}{pAGEuP}").unwrap(),
+            vec![Space, F5, Numpad0, PageUp]
+        );
+        assert_eq!(parse_keys("a{Esc}b").unwrap(), vec![A, Escape, B]);
+    }
+
+    #[test]
+    fn rejects_what_no_key_types() {
+        let error = |s: &str| s.parse::<KeySequence>().unwrap_err();
+        assert_eq!(error(""), ParseKeySequenceError::Empty);
+        assert_eq!(
+            error("prank!"),
+            ParseKeySequenceError::UnmappableChar {
+                position: 5,
+                c: '!'
+            }
+        );
+        assert_eq!(
+            error("é"),
+            ParseKeySequenceError::UnmappableChar {
+                position: 0,
+                c: 'é'
+            }
+        );
+        for name in &["Bogus", "F0", "F25", "Numpad10", "F", "F+1", ""] {
+            assert_eq!(
+                error(&format!("a{{{}}}", name)),
+                ParseKeySequenceError::UnknownKeyName(name.to_string())
+            );
+        }
+        assert_eq!(
+            error("ab{Space"),
+            ParseKeySequenceError::UnclosedBrace { position: 2 }
+        );
+
+        assert_eq!(
+            error("prank!").to_string(),
+            "no key types '!' (character 6); name keys in braces, like {Space}"
+        );
+        assert_eq!(error("{Bogus}").to_string(), "unknown key name {Bogus}");
+        assert_eq!(
+            error("ab{Space").to_string(),
+            "the brace at character 3 is never closed"
+        );
+        assert_eq!(error("").to_string(), "the sequence is empty");
+    }
+
+    #[test]
+    fn parsed_sequence_unlocks() {
+        use VirtualKeyCode::*;
+        let mut sequence: KeySequence = "ok{F5}".parse().unwrap();
+        assert!(!sequence.process_input(O));
+        assert!(!sequence.process_input(K));
+        assert!(sequence.process_input(F5));
+    }
+
+    #[test]
+    fn matches_brute_force_on_random_input() {
+        // A small alphabet makes partial and overlapping matches common.
+        const ALPHABET: [VirtualKeyCode; 3] =
+            [VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C];
+        let mut rng = Rng::new(7);
+        let mut random_keys = |len: usize, alphabet: usize| -> Vec<VirtualKeyCode> {
+            (0..len)
+                .map(|_| ALPHABET[rng.below(alphabet as u64) as usize])
+                .collect()
+        };
+
+        // Self-repeating sequences whose failure functions matter most
+        let mut sequences: Vec<Vec<VirtualKeyCode>> =
+            ["a", "aa", "aaaa", "aab", "abab", "abaab", "abcabca"]
+                .iter()
+                .map(|s| keys(s))
+                .collect();
+        for len in 1..=7 {
+            for _ in 0..20 {
+                sequences.push(random_keys(len, 2));
+                sequences.push(random_keys(len, 3));
+            }
+        }
+
+        for sequence in &sequences {
+            for alphabet in 1..=3 {
+                let typed = random_keys(300, alphabet);
+                check(sequence, &typed);
+            }
+            // The sequence typed over and over, overlapping with itself
+            let repeated: Vec<VirtualKeyCode> = sequence
+                .iter()
+                .cycle()
+                .take(sequence.len() * 5)
+                .copied()
+                .collect();
+            check(sequence, &repeated);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! When a prank starts, and when it has gone on long enough.
+
+use std::time::{Duration, Instant};
+
+use crate::rotation::Clock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Waiting out the delay, with the cursors untouched
+    Waiting,
+    Running,
+    /// The prank has lasted as long as it may, and the cursors should go back
+    Over,
+}
+
+/// The stretch of time a prank runs for, by the time on its clock.
+#[derive(Debug)]
+pub struct Lifetime<C: Clock> {
+    clock: C,
+    start: Instant,
+    /// `None` for a prank that runs until it is unlocked
+    end: Option<Instant>,
+}
+
+impl<C: Clock> Lifetime<C> {
+    /// A prank that starts `delay` from now and, if there is a `max_duration`, ends that long
+    /// after it starts, or `None` if either is further ahead than the clock can tell.
+    pub fn new(clock: C, delay: Duration, max_duration: Option<Duration>) -> Option<Self> {
+        let start = clock.now().checked_add(delay)?;
+        let end = match max_duration {
+            Some(duration) => Some(start.checked_add(duration)?),
+            None => None,
+        };
+        Some(Self { clock, start, end })
+    }
+
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    pub fn stage(&self) -> Stage {
+        let now = self.clock.now();
+        if now < self.start {
+            Stage::Waiting
+        } else if self.end.is_some_and(|end| now >= end) {
+            Stage::Over
+        } else {
+            Stage::Running
+        }
+    }
+
+    /// When the stage changes next, or `None` if it never will.
+    pub fn next_change(&self) -> Option<Instant> {
+        match self.stage() {
+            Stage::Waiting => Some(self.start),
+            Stage::Running => self.end,
+            Stage::Over => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::ManualClock;
+
+    #[test]
+    fn waits_runs_and_ends() {
+        let clock = ManualClock::new();
+        let (delay, duration) = (Duration::from_secs(5), Duration::from_secs(10));
+        let lifetime = Lifetime::new(clock.clone(), delay, Some(duration)).unwrap();
+        let start = clock.now() + delay;
+
+        assert_eq!(lifetime.stage(), Stage::Waiting);
+        assert_eq!(lifetime.next_change(), Some(start));
+        clock.advance(delay);
+        assert_eq!(lifetime.stage(), Stage::Running);
+        assert_eq!(lifetime.next_change(), Some(start + duration));
+        clock.advance(duration);
+        assert_eq!(lifetime.stage(), Stage::Over);
+        assert_eq!(lifetime.next_change(), None);
+    }
+
+    #[test]
+    fn runs_until_unlocked_without_a_max_duration() {
+        let clock = ManualClock::new();
+        let lifetime = Lifetime::new(clock.clone(), Duration::default(), None).unwrap();
+        assert_eq!(lifetime.stage(), Stage::Running);
+        clock.advance(Duration::from_secs(1_000_000));
+        assert_eq!(lifetime.stage(), Stage::Running);
+        assert_eq!(lifetime.next_change(), None);
+    }
+
+    #[test]
+    fn too_far_ahead_is_refused() {
+        let clock = ManualClock::new();
+        assert!(Lifetime::new(clock.clone(), Duration::MAX, None).is_none());
+        let half = Duration::MAX / 2;
+        assert!(Lifetime::new(clock.clone(), Duration::default(), Some(Duration::MAX)).is_none());
+        assert!(Lifetime::new(clock, half, Some(half)).is_none());
+    }
+}
@@ -0,0 +1,114 @@
+//! A record of what runs said, kept next to the restoration journal.
+//!
+//! Release builds on Windows have no console, so anything only printed is lost. Messages worth
+//! keeping, like the random seed of a run, are appended here as well, each line stamped with
+//! the time in UTC.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::journal;
+
+/// A log larger than this is moved aside to `<name>.old` before the next line is written, so
+/// it never grows past twice this size
+const MAX_LEN: u64 = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub struct Log {
+    path: PathBuf,
+}
+
+impl Log {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The log in the per-user state directory, beside the journal.
+    pub fn open_default() -> io::Result<Self> {
+        Ok(Self::new(journal::state_dir()?.join("log")))
+    }
+
+    pub fn write(&self, message: &str) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() > MAX_LEN) {
+            fs::rename(&self.path, self.path.with_extension("old"))?;
+        }
+
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // One write per line keeps lines from two runs at once from interleaving.
+        file.write_all(format!("{} {}\n", utc(seconds), message).as_bytes())
+    }
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time, like `2021-03-04 05:06:07`.
+fn utc(seconds: u64) -> String {
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Howard Hinnant's days-to-civil algorithm, counting in 400 year eras from 0000-03-01
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn formats_utc() {
+        assert_eq!(utc(0), "1970-01-01 00:00:00");
+        assert_eq!(utc(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(utc(1_614_834_367), "2021-03-04 05:06:07");
+        assert_eq!(utc(4_107_542_399), "2100-02-28 23:59:59");
+    }
+
+    #[test]
+    fn appends_stamped_lines() {
+        let path = env::temp_dir().join(format!("justaprankbro-test-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let log = Log::new(&path);
+
+        log.write("first").unwrap();
+        log.write("second").unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let messages: Vec<&str> = text
+            .lines()
+            .map(|line| line.splitn(3, ' ').nth(2).unwrap())
+            .collect();
+        assert_eq!(messages, vec!["first", "second"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,21 +1,448 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cli;
+mod config;
+mod convert;
 mod cursor;
+mod format;
+mod generate;
+mod hotspot;
+mod image;
+mod import;
+mod inf;
+mod journal;
+mod key_sequence;
+mod lifetime;
+mod log;
+mod pool;
+mod prank;
+mod preview;
+mod random;
+mod rotation;
+mod scheme;
+mod theme;
+mod transform;
+mod validate;
 
-use cursor::{Cursor, CursorKind};
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead},
+    path::Path,
+    process,
+    rc::Rc,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        OnceLock,
+    },
+    thread,
+    time::Instant,
+};
+
+use cli::{Args, Command};
+use config::Config;
+use cursor::{CursorBackend, CursorKind, MockBackend, SystemBackend};
+use format::Format;
+use generate::Design;
+use image::CursorImage;
+use journal::Journal;
+use key_sequence::KeySequence;
+use lifetime::{Lifetime, Stage};
+use log::Log;
+use pool::Pool;
+use prank::{Prank, PrankError};
+use random::Rng;
+use rotation::{Rotation, SystemClock, DEFAULT_INTERVAL};
+use scheme::CursorScheme;
+use theme::Theme;
+
+/// Where runs keep what they report, see `log`
+static LOG: OnceLock<Log> = OnceLock::new();
 
 fn main() {
-    let cursor = Cursor::from_file(r"normal.cur")
-        .unwrap()
-        .replace_system(CursorKind::Normal);
+    let args = match cli::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => usage_error(&err.to_string()),
+    };
+    if let Command::Run | Command::Restore = args.command {
+        let log = if args.dry_run {
+            Ok(Log::new(env::temp_dir().join("justaprankbro-dry-run.log")))
+        } else {
+            Log::open_default()
+        };
+        // Without a place for the log, messages are only printed.
+        if let Ok(log) = log {
+            let _ = LOG.set(log);
+        }
+    }
+
+    match args.command {
+        Command::Help => print!("{}", cli::USAGE),
+        Command::Convert => convert(&args),
+        Command::Import => import(&args),
+        Command::Generate => generate(&args),
+        Command::Hotspot => hotspot(&args),
+        Command::Preview => preview(&args),
+        Command::Validate => validate(&args),
+        Command::Restore => {
+            if args.dry_run {
+                restore_from_journal(&MockBackend::echoing(), &dry_run_journal())
+            } else {
+                let journal = open_journal();
+                restore_from_journal(&system_backend(), &journal)
+            }
+        }
+        Command::Run => {
+            let config = load_config(&args);
+            let mut scheme = scheme(&args, &config);
+            let unlock_sequence = unlock_sequence(&args, &config);
+            let lifetime = lifetime(&args, &config);
+            let rotation = rotation(&args, &config, &mut scheme, lifetime.start());
+            if args.dry_run {
+                let backend = Rc::new(MockBackend::echoing());
+                let prank = Prank::new(backend, dry_run_journal(), &scheme, rotation, lifetime);
+                dry_run(prank, unlock_sequence)
+            } else {
+                let backend = Rc::new(system_backend());
+                let prank = Prank::new(backend, open_journal(), &scheme, rotation, lifetime);
+                run(prank, unlock_sequence)
+            }
+        }
+    }
+}
+
+/// Prints `message` and keeps it in the log.
+fn report(message: &str) {
+    println!("{}", message);
+    keep(message);
+}
+
+/// Adds `message` to the log, if there is one. A log that can't be written to is no reason to
+/// stop a run.
+fn keep(message: &str) {
+    if let Some(log) = LOG.get() {
+        let _ = log.write(message);
+    }
+}
+
+/// Prints an error and keeps it in the log.
+fn report_error(message: &str) {
+    eprintln!("{}", message);
+    keep(message);
+}
+
+/// Reports an error that leaves nothing to clean up and exits.
+fn fail(context: &str, err: impl fmt::Display) -> ! {
+    report_error(&format!("{}: {}", context, err));
+    process::exit(1);
+}
+
+fn system_backend() -> SystemBackend {
+    SystemBackend::new().unwrap_or_else(|err| fail("Could not access the system cursors", err))
+}
+
+fn open_journal() -> Journal {
+    Journal::open_default()
+        .unwrap_or_else(|err| fail("Could not find a place for the restoration journal", err))
+}
+
+fn usage_error(message: &str) -> ! {
+    keep(message);
+    eprintln!("{}\n\n{}", message, cli::USAGE);
+    process::exit(2);
+}
+
+/// The file to write a cursor to and its format, for the commands that make one.
+fn output_file<'a>(args: &'a Args, command: &str) -> (&'a Path, Format) {
+    let output = match &args.output {
+        Some(output) => output,
+        None => usage_error(&format!("{} needs an --output file", command)),
+    };
+    match args.format.or_else(|| Format::from_path(output)) {
+        Some(format) => (output, format),
+        None => usage_error(&format!(
+            "cannot tell the format of {} from its name, pick one with --format",
+            output.display()
+        )),
+    }
+}
+
+fn convert(args: &Args) {
+    if args.inputs.is_empty() {
+        usage_error("convert needs at least one PNG");
+    }
+    let (output, format) = output_file(args, "convert");
+
+    let hotspot = args.hotspot.unwrap_or_default();
+    match convert::convert(
+        &args.inputs,
+        hotspot,
+        format,
+        &args.sizes,
+        args.bit_depth,
+        output,
+    ) {
+        Ok(()) => println!("Wrote {}", output.display()),
+        Err(err) => fail("Could not convert", err),
+    }
+}
+
+fn import(args: &Args) {
+    let input = match args.inputs.as_slice() {
+        [input] => input,
+        _ => usage_error("import needs a single GIF or PNG"),
+    };
+    let (output, format) = output_file(args, "import");
+
+    let hotspot = args.hotspot.unwrap_or_default();
+    match import::import(input, hotspot, format, &args.sizes, output) {
+        Ok(()) => println!("Wrote {}", output.display()),
+        Err(err) => fail("Could not import", err),
+    }
+}
+
+fn generate(args: &Args) {
+    if args.design.is_empty() {
+        usage_error("generate needs a design, like \"arrow text=LOL\"");
+    }
+    let design = args
+        .design
+        .join(" ")
+        .parse::<Design>()
+        .unwrap_or_else(|err| usage_error(&err.to_string()));
+    let (output, format) = output_file(args, "generate");
+
+    let mut cursor = design.cursor();
+    if !args.sizes.is_empty() {
+        if let Some(size) = args.sizes.iter().find(|&&size| size > format.max_size()) {
+            usage_error(&format!(
+                "a {} file holds at most {}x{} pixels, too few for size {}",
+                format.as_str(),
+                format.max_size(),
+                format.max_size(),
+                size
+            ));
+        }
+        let entries = args.sizes.iter().map(|&size| design.render(size)).collect();
+        cursor.frames[0].image = CursorImage::new(entries);
+    }
+    match fs::write(output, format::encode(&cursor, format)) {
+        Ok(()) => println!("Wrote {}", output.display()),
+        Err(err) => fail(&format!("Could not write {}", output.display()), err),
+    }
+}
+
+fn hotspot(args: &Args) {
+    if args.inputs.is_empty() {
+        usage_error("hotspot needs at least one cursor file or PNG");
+    }
+    let output = match args.output {
+        Some(_) if args.inputs.len() > 1 => {
+            usage_error("hotspot can only write one cursor, give it a single file")
+        }
+        Some(_) => Some(output_file(args, "hotspot")),
+        None => None,
+    };
+
+    let kind = args.kind.unwrap_or(CursorKind::Normal);
+    if let Err(err) = hotspot::suggest_files(&args.inputs, kind, output) {
+        fail("Could not suggest a hotspot", err);
+    }
+}
+
+fn preview(args: &Args) {
+    if args.inputs.is_empty() {
+        usage_error("preview needs at least one cursor file");
+    }
+    if let Err(err) = preview::preview(&args.inputs, args.output.as_deref()) {
+        fail("Could not preview", err);
+    }
+}
+
+fn validate(args: &Args) {
+    if args.inputs.is_empty() {
+        usage_error("validate needs at least one cursor file");
+    }
+    let reports: Vec<validate::Report> = args
+        .inputs
+        .iter()
+        .map(|path| validate::Report::new(path))
+        .collect();
+    if args.json {
+        println!("{}", validate::to_json(&reports));
+    } else {
+        reports.iter().for_each(|report| print!("{}", report));
+    }
+    if !reports.iter().all(validate::Report::is_valid) {
+        process::exit(1);
+    }
+}
+
+/// Dry runs keep their journal out of the way of the real one.
+fn dry_run_journal() -> Journal {
+    Journal::new(env::temp_dir().join("justaprankbro-dry-run.journal"))
+}
+
+fn load_config(args: &Args) -> Config {
+    let config = match &args.config {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    config.unwrap_or_else(|err| {
+        report_error(&format!("Could not read the config file: {}", err));
+        process::exit(2);
+    })
+}
+
+/// The theme's cursors overridden by the config file's, overridden in turn by the ones on the
+/// command line. If none of them names any, the scheme is empty and the built-in cursor is used.
+fn scheme(args: &Args, config: &Config) -> CursorScheme {
+    let theme = args.theme.as_ref().or(config.theme.as_ref()).map(|dir| {
+        Theme::load(dir).unwrap_or_else(|err| fail("Could not load the cursor theme", err))
+    });
+    let mut scheme = match &theme {
+        Some(theme) => theme.scheme().clone(),
+        None => CursorScheme::new(),
+    };
+    scheme.merge(&config.scheme);
+    scheme.merge(&args.scheme);
+
+    if let Some(theme) = theme {
+        let missing: Vec<&str> = theme
+            .missing()
+            .iter()
+            .filter(|&&kind| scheme.iter().all(|(k, _)| k != kind))
+            .map(|kind| kind.as_str())
+            .collect();
+        if !missing.is_empty() {
+            println!(
+                "The theme has no cursor for {}, which will stay as they are",
+                missing.join(", ")
+            );
+        }
+    }
+    scheme
+}
+
+/// The sequence from the command line, then the one from the config file, then the default.
+fn unlock_sequence(args: &Args, config: &Config) -> KeySequence {
+    match args.sequence.as_ref().or(config.sequence.as_ref()) {
+        Some(sequence) => sequence.parse().unwrap_or_else(|err| {
+            report_error(&format!("Invalid unlock sequence {:?}: {}", sequence, err));
+            process::exit(2);
+        }),
+        None => KeySequence::default(),
+    }
+}
+
+/// The config file's playlist and pool with the command line's overriding them kind by kind,
+/// changing every interval from the command line, the config file or `DEFAULT_INTERVAL`.
+/// Nothing rotates without a playlist, or a pool and an interval. A pool on its own is picked
+/// from once, into `scheme`. The first change is one interval after `start`.
+fn rotation(
+    args: &Args,
+    config: &Config,
+    scheme: &mut CursorScheme,
+    start: Instant,
+) -> Option<Rotation<SystemClock>> {
+    let mut playlist = config.playlist.clone();
+    playlist.merge(&args.playlist);
+    let pool = pool(args, config);
+    let interval = args.interval.as_ref().or(config.interval.as_ref()).cloned();
+
+    if playlist.is_empty() && (pool.is_empty() || interval.is_none()) {
+        if !pool.is_empty() {
+            scheme.merge(&pool.pick(&mut rng(args, config)));
+        } else if args.interval.is_some() {
+            usage_error("--interval needs a --playlist or --pool to rotate through");
+        }
+        return None;
+    }
+    Some(Rotation::new(
+        playlist,
+        pool,
+        scheme.transforms().clone(),
+        interval.unwrap_or(DEFAULT_INTERVAL),
+        start,
+        SystemClock,
+        rng(args, config),
+    ))
+}
+
+/// The delay and maximum duration from the command line, or else the config file. The prank
+/// starts right away and runs until it is unlocked if neither gives them.
+fn lifetime(args: &Args, config: &Config) -> Lifetime<SystemClock> {
+    let delay = args.delay.or(config.delay).unwrap_or_default();
+    let max_duration = args.max_duration.or(config.max_duration);
+    Lifetime::new(SystemClock, delay, max_duration).unwrap_or_else(|| {
+        usage_error("The delay and maximum duration reach further ahead than the clock can tell")
+    })
+}
+
+/// The config file's pool with the command line's overriding it kind by kind, with directories
+/// replaced by the cursors in them.
+fn pool(args: &Args, config: &Config) -> Pool {
+    let mut pool = config.pool.clone();
+    pool.merge(&args.pool);
+    pool.expand_dirs()
+        .unwrap_or_else(|err| fail("Could not load the cursor pool", err))
+}
+
+/// A generator seeded from the command line, the config file or the time. The seed is reported
+/// so a run that went well can be repeated.
+fn rng(args: &Args, config: &Config) -> Rng {
+    let seed = args
+        .seed
+        .or(config.seed)
+        .unwrap_or_else(random::seed_from_time);
+    report(&format!(
+        "Random seed {}, pass --seed {} to repeat this run",
+        seed, seed
+    ));
+    Rng::new(seed)
+}
+
+/// Puts back cursors left replaced by a run that never got to clean up after itself.
+fn restore_from_journal<B: CursorBackend>(backend: &B, journal: &Journal) {
+    match journal.restore(backend) {
+        Ok(restored) if restored.is_empty() => println!("Nothing to restore"),
+        Ok(restored) => {
+            for kind in restored {
+                println!("Restored the {} cursor", kind.as_str());
+            }
+        }
+        Err(err) => {
+            report_error(&format!(
+                "Could not restore cursors from {}: {}",
+                journal.path().display(),
+                err
+            ));
+            process::exit(1);
+        }
+    }
+}
+
+/// Reports a failed update of the prank, exiting if it left nothing replaced.
+fn update<B: CursorBackend>(prank: &mut Prank<B, SystemClock>) -> Option<Stage> {
+    match prank.update() {
+        Ok(stage) => Some(stage),
+        Err(err @ PrankError::Replace(_)) => fail("Could not start the prank", err),
+        Err(err @ PrankError::Swap(_)) => {
+            report_error(&err.to_string());
+            None
+        }
+    }
+}
 
+/// Runs the prank until it is over or the unlock sequence is typed on the keyboard.
+fn run<B: CursorBackend + 'static>(
+    mut prank: Prank<B, SystemClock>,
+    mut unlock_sequence: KeySequence,
+) -> ! {
     let event_loop = winit::event_loop::EventLoop::new();
     let _window = winit::window::WindowBuilder::new()
         .with_visible(false)
         .build(&event_loop)
-        .unwrap();
-
-    let mut unlock_sequence = KeySequence::default();
+        .unwrap_or_else(|err| fail("Could not create the event window", err));
 
     event_loop.run(move |event, _, control_flow| {
         use winit::{
@@ -23,55 +450,122 @@ fn main() {
             event_loop::ControlFlow,
         };
 
-        match event {
-            Event::DeviceEvent { event, .. } => {
-                if let DeviceEvent::Key(keyboard_input) = event {
-                    if let Some(keycode) = keyboard_input.virtual_keycode {
-                        if keyboard_input.state == ElementState::Pressed
-                            && unlock_sequence.process_input(keycode)
-                        {
-                            *control_flow = ControlFlow::Exit;
-                        }
-                    }
+        if *control_flow == ControlFlow::Exit {
+            return;
+        }
+        if update(&mut prank) == Some(Stage::Over) {
+            println!("Time is up, the cursors are back");
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+        *control_flow = match prank.next_change() {
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
+        };
+
+        if let Event::DeviceEvent {
+            event: DeviceEvent::Key(keyboard_input),
+            ..
+        } = event
+        {
+            if let Some(keycode) = keyboard_input.virtual_keycode {
+                if keyboard_input.state == ElementState::Pressed
+                    && unlock_sequence.process_input(keycode)
+                {
+                    *control_flow = ControlFlow::Exit;
                 }
             }
-            Event::LoopDestroyed => {
-                let _ = &cursor;
-            }
-            _ => {}
         }
     });
 }
 
-#[derive(Debug)]
-struct KeySequence {
-    keys: Vec<winit::event::VirtualKeyCode>,
-    idx: usize,
-}
+/// Runs the prank like `run`, but without a window, reading the keys from lines typed on
+/// standard input. With standard input closed, it ends once there is nothing left to wait for.
+fn dry_run<B: CursorBackend>(mut prank: Prank<B, SystemClock>, mut unlock_sequence: KeySequence) {
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let sent = line.ok().map(|line| sender.send(line));
+            if !matches!(sent, Some(Ok(()))) {
+                break;
+            }
+        }
+    });
+    let mut lines = Some(lines);
 
-impl KeySequence {
-    fn process_input(&mut self, keycode: winit::event::VirtualKeyCode) -> bool {
-        if self.keys[self.idx] == keycode {
-            if self.idx == self.keys.len() - 1 {
-                true
-            } else {
-                self.idx += 1;
-                false
+    loop {
+        if update(&mut prank) == Some(Stage::Over) {
+            println!("Time is up, the cursors are back");
+            return;
+        }
+        let line = match (&lines, prank.next_change()) {
+            (Some(lines), Some(deadline)) => {
+                match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    line => line.ok(),
+                }
             }
-        } else {
-            self.idx = 0;
-            false
+            (Some(lines), None) => lines.recv().ok(),
+            (None, Some(deadline)) => {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                continue;
+            }
+            (None, None) => {
+                // Dropping the prank puts the originals back.
+                drop(prank);
+                println!("Nothing left to wait for, the cursors are back");
+                return;
+            }
+        };
+
+        match line.as_deref().map(key_sequence::parse_keys) {
+            Some(Ok(keys)) => {
+                if keys
+                    .into_iter()
+                    .any(|key| unlock_sequence.process_input(key))
+                {
+                    // Dropping the prank puts the originals back.
+                    drop(prank);
+                    println!("Unlocked, the cursors are back");
+                    return;
+                }
+            }
+            Some(Err(err)) => eprintln!("Ignoring that line: {}", err),
+            None => lines = None,
         }
     }
 }
 
-impl Default for KeySequence {
-    fn default() -> Self {
-        use winit::event::VirtualKeyCode::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Self {
-            keys: vec![J, U, S, T, A, P, R, A, N, K, B, R, O],
-            idx: 0,
-        }
+    fn unlocks(sequence: &mut KeySequence, keys: &str) -> bool {
+        key_sequence::parse_keys(keys)
+            .unwrap()
+            .into_iter()
+            .any(|key| sequence.process_input(key))
+    }
+
+    #[test]
+    fn command_line_sequence_wins_over_the_config_file() {
+        let args = Args {
+            sequence: Some("cli".to_owned()),
+            ..Args::default()
+        };
+        let config = Config {
+            sequence: Some("conf".to_owned()),
+            ..Config::default()
+        };
+
+        assert!(unlocks(&mut unlock_sequence(&args, &config), "cli"));
+        assert!(!unlocks(&mut unlock_sequence(&args, &config), "conf"));
+        assert!(unlocks(
+            &mut unlock_sequence(&Args::default(), &config),
+            "conf"
+        ));
+        let mut default = unlock_sequence(&Args::default(), &Config::default());
+        assert!(!unlocks(&mut default, "conf"));
+        assert!(unlocks(&mut default, "justaprankbro"));
     }
 }
@@ -0,0 +1,369 @@
+//! Picking cursors at random, so no two pranks have to look alike.
+//!
+//! A pool holds weighted cursors for each kind it covers, and each kind gets one picked for it,
+//! with a cursor of weight 2 turning up twice as often as one of weight 1. Entries are written
+//! like cursor assignments, with an optional weight after a `*`:
+//!
+//! ```text
+//! clown.cur*3
+//! Hand=gloves/
+//! all=generate:heart fill=red
+//! ```
+//!
+//! A directory stands for every cursor file in it, each with the directory's weight, and
+//! `all=` adds the entry to the pool of every kind, which then each pick on their own.
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cursor::{CursorKind, UnknownCursorKind},
+    generate::InvalidDesign,
+    random::Rng,
+    scheme::{CursorScheme, SchemeCursor},
+    theme,
+};
+
+/// Stands for every kind of cursor, wherever a kind is expected
+pub const ALL_KINDS: &str = "all";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pool {
+    cursors: Vec<(CursorKind, Vec<(SchemeCursor, u32)>)>,
+}
+
+impl Pool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<C: Into<SchemeCursor>>(&mut self, kind: CursorKind, cursor: C, weight: u32) {
+        let entry = (cursor.into(), weight);
+        match self.cursors.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, list)) => list.push(entry),
+            None => self.cursors.push((kind, vec![entry])),
+        }
+    }
+
+    /// Adds an entry written as `path*weight` to the pools of `kinds`, resolving relative
+    /// paths against `dir`. The weight is 1 if not given.
+    pub fn push_value(
+        &mut self,
+        kinds: &[CursorKind],
+        value: &str,
+        dir: &Path,
+    ) -> Result<(), PoolError> {
+        let (value, weight) = match value.rfind('*') {
+            Some(star) => match value[star + 1..].trim().parse::<u32>() {
+                Ok(0) => return Err(PoolError::ZeroWeight(value.to_owned())),
+                Ok(weight) => (&value[..star], weight),
+                Err(_) => (value, 1),
+            },
+            None => (value, 1),
+        };
+        let cursor = SchemeCursor::from_str_in(value, dir)?;
+        for &kind in kinds {
+            self.push(kind, cursor.clone(), weight);
+        }
+        Ok(())
+    }
+
+    /// Adds an entry written as `Kind=path*weight`, `all=path*weight` or just `path*weight` for
+    /// the normal pointer.
+    pub fn push_from_str(&mut self, assignment: &str) -> Result<(), PoolError> {
+        if let Some(eq) = assignment.find('=') {
+            if let Ok(kinds) = parse_kinds(assignment[..eq].trim()) {
+                return self.push_value(&kinds, &assignment[eq + 1..], Path::new(""));
+            }
+        }
+        self.push_value(&[CursorKind::Normal], assignment, Path::new(""))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+
+    /// Takes the pool of every kind in `other`, replacing the one already here for the same
+    /// kind.
+    pub fn merge(&mut self, other: &Pool) {
+        for (kind, list) in &other.cursors {
+            match self.cursors.iter_mut().find(|(k, _)| k == kind) {
+                Some(entry) => entry.1 = list.clone(),
+                None => self.cursors.push((*kind, list.clone())),
+            }
+        }
+    }
+
+    /// Replaces directories with the cursor files in them, in order of their names so a seed
+    /// picks the same cursors every time.
+    pub fn expand_dirs(&self) -> Result<Self, PoolError> {
+        let mut expanded = Self::new();
+        for (kind, list) in &self.cursors {
+            for (cursor, weight) in list {
+                match cursor {
+                    SchemeCursor::File(dir) if dir.is_dir() => {
+                        for path in cursor_files(dir)? {
+                            expanded.push(*kind, path, *weight);
+                        }
+                    }
+                    _ => expanded.push(*kind, cursor.clone(), *weight),
+                }
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Picks a cursor for each kind in the pool.
+    pub fn pick(&self, rng: &mut Rng) -> CursorScheme {
+        let mut scheme = CursorScheme::new();
+        for (kind, list) in &self.cursors {
+            let total: u64 = list.iter().map(|&(_, weight)| u64::from(weight)).sum();
+            let mut ticket = rng.below(total);
+            for (cursor, weight) in list {
+                if ticket < u64::from(*weight) {
+                    scheme.set(*kind, cursor.clone());
+                    break;
+                }
+                ticket -= u64::from(*weight);
+            }
+        }
+        scheme
+    }
+}
+
+/// Reads `all` as every kind and anything else as the name of one.
+pub fn parse_kinds(name: &str) -> Result<Vec<CursorKind>, UnknownCursorKind> {
+    if name.eq_ignore_ascii_case(ALL_KINDS) {
+        Ok(CursorKind::ALL.to_vec())
+    } else {
+        Ok(vec![name.parse()?])
+    }
+}
+
+fn cursor_files(dir: &Path) -> Result<Vec<PathBuf>, PoolError> {
+    let read_dir = |err| PoolError::Read(dir.to_owned(), err);
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_dir)? {
+        let path = entry.map_err(read_dir)?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if !hidden && path.is_file() && theme::has_cursor_extension(&path) {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(PoolError::NoCursors(dir.to_owned()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[derive(Debug)]
+pub enum PoolError {
+    Design(InvalidDesign),
+    ZeroWeight(String),
+    Read(PathBuf, io::Error),
+    /// A directory in the pool has no cursor files in it
+    NoCursors(PathBuf),
+}
+
+impl From<InvalidDesign> for PoolError {
+    fn from(err: InvalidDesign) -> Self {
+        Self::Design(err)
+    }
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Design(err) => err.fmt(f),
+            Self::ZeroWeight(value) => {
+                write!(f, "`{}` has a weight of 0 and is never picked", value)
+            }
+            Self::Read(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            Self::NoCursors(dir) => write!(f, "{} has no cursor files in it", dir.display()),
+        }
+    }
+}
+
+impl Error for PoolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Design(err) => Some(err),
+            Self::Read(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn file(path: &str) -> SchemeCursor {
+        SchemeCursor::File(PathBuf::from(path))
+    }
+
+    /// A directory of its own for a test holding empty files with the given names
+    fn temporary_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("justaprankbro-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in files {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        dir
+    }
+
+    fn picks(pool: &Pool, seed: u64, count: usize) -> Vec<CursorScheme> {
+        let mut rng = Rng::new(seed);
+        (0..count).map(|_| pool.pick(&mut rng)).collect()
+    }
+
+    #[test]
+    fn parses_weights() {
+        let mut pool = Pool::new();
+        pool.push_value(&[CursorKind::Normal], "clown.cur*3", Path::new("dir"))
+            .unwrap();
+        pool.push_value(&[CursorKind::Normal], "plain.cur", Path::new("dir"))
+            .unwrap();
+        // A star not followed by a number is part of the file name.
+        pool.push_value(&[CursorKind::Normal], "star*ry.cur", Path::new("dir"))
+            .unwrap();
+        assert_eq!(
+            pool.cursors,
+            vec![(
+                CursorKind::Normal,
+                vec![
+                    (file("dir/clown.cur"), 3),
+                    (file("dir/plain.cur"), 1),
+                    (file("dir/star*ry.cur"), 1),
+                ]
+            )]
+        );
+
+        let err = pool
+            .push_value(&[CursorKind::Normal], "never.cur*0", Path::new(""))
+            .unwrap_err();
+        assert!(matches!(&err, PoolError::ZeroWeight(value) if value == "never.cur*0"));
+        assert_eq!(
+            err.to_string(),
+            "`never.cur*0` has a weight of 0 and is never picked"
+        );
+    }
+
+    #[test]
+    fn parses_kinds() {
+        let mut pool = Pool::new();
+        pool.push_from_str("Hand=glove.ani*2").unwrap();
+        pool.push_from_str("clown.cur").unwrap();
+        // Something that isn't a kind before the `=` is part of the file name.
+        pool.push_from_str("odd=name.cur").unwrap();
+        assert_eq!(
+            pool.cursors,
+            vec![
+                (CursorKind::Hand, vec![(file("glove.ani"), 2)]),
+                (
+                    CursorKind::Normal,
+                    vec![(file("clown.cur"), 1), (file("odd=name.cur"), 1)]
+                ),
+            ]
+        );
+
+        let mut all = Pool::new();
+        all.push_from_str("ALL=generate:heart").unwrap();
+        assert_eq!(all.cursors.len(), CursorKind::ALL.len());
+        assert_eq!(
+            all.pick(&mut Rng::new(0)).iter().count(),
+            CursorKind::ALL.len()
+        );
+    }
+
+    #[test]
+    fn same_seed_same_picks() {
+        let mut pool = Pool::new();
+        for name in &["a.cur", "b.cur", "c.cur", "d.cur"] {
+            pool.push_from_str(name).unwrap();
+            pool.push_from_str(&format!("Hand={}", name)).unwrap();
+        }
+        assert_eq!(picks(&pool, 42, 20), picks(&pool, 42, 20));
+        assert_ne!(picks(&pool, 42, 20), picks(&pool, 43, 20));
+    }
+
+    #[test]
+    fn picks_by_weight() {
+        let mut pool = Pool::new();
+        pool.push_from_str("rare.cur").unwrap();
+        pool.push_from_str("common.cur*3").unwrap();
+        let common = picks(&pool, 7, 4000)
+            .iter()
+            .filter(|scheme| scheme.iter().next().unwrap().1 == &file("common.cur"))
+            .count();
+        assert!((2850..3150).contains(&common), "{}", common);
+    }
+
+    #[test]
+    fn expands_directories_in_order() {
+        let dir = temporary_dir(
+            "pool-expand",
+            &["b.ani", "a.cur", "C", ".hidden.cur", "notes.txt"],
+        );
+        fs::create_dir(dir.join("nested.cur")).unwrap();
+
+        let mut pool = Pool::new();
+        pool.push(CursorKind::Normal, dir.as_path(), 2);
+        pool.push(CursorKind::Hand, Path::new("glove.ani"), 1);
+        let expanded = pool.expand_dirs().unwrap();
+        assert_eq!(
+            expanded.cursors,
+            vec![
+                (
+                    CursorKind::Normal,
+                    vec![
+                        (dir.join("C").into(), 2),
+                        (dir.join("a.cur").into(), 2),
+                        (dir.join("b.ani").into(), 2),
+                    ]
+                ),
+                (CursorKind::Hand, vec![(file("glove.ani"), 1)]),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn all_kinds_pick_from_a_directory_on_their_own() {
+        let names: Vec<String> = (0..8).map(|i| format!("{}.cur", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let dir = temporary_dir("pool-all", &names);
+
+        let mut pool = Pool::new();
+        let value = format!("{}*2", dir.display());
+        pool.push_value(&parse_kinds("all").unwrap(), &value, Path::new(""))
+            .unwrap();
+        let scheme = pool.expand_dirs().unwrap().pick(&mut Rng::new(1));
+        let picked: Vec<&SchemeCursor> = scheme.iter().map(|(_, cursor)| cursor).collect();
+        assert_eq!(picked.len(), CursorKind::ALL.len());
+        assert!(picked.windows(2).any(|pair| pair[0] != pair[1]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_directory_is_an_error() {
+        let dir = temporary_dir("pool-empty", &["readme.txt"]);
+        let mut pool = Pool::new();
+        pool.push(CursorKind::Normal, dir.as_path(), 1);
+        assert!(matches!(
+            pool.expand_dirs(),
+            Err(PoolError::NoCursors(empty)) if empty == dir
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
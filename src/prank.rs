@@ -0,0 +1,202 @@
+//! A prank in progress: the cursors go in once its delay is up, change as its rotation says and
+//! go back when it is over. Whatever runs it decides how to wait for the next change.
+
+use std::{error::Error, fmt, rc::Rc, time::Instant};
+
+use crate::{
+    cursor::{CursorBackend, CursorError, ReplacedScheme},
+    journal::Journal,
+    lifetime::{Lifetime, Stage},
+    rotation::{Clock, Rotation},
+    scheme::CursorScheme,
+};
+
+#[derive(Debug)]
+pub struct Prank<B: CursorBackend, C: Clock> {
+    backend: Rc<B>,
+    journal: Journal,
+    scheme: CursorScheme,
+    rotation: Option<Rotation<C>>,
+    lifetime: Lifetime<C>,
+    /// The replaced cursors, while the prank is running
+    cursors: Option<ReplacedScheme<B>>,
+}
+
+impl<B: CursorBackend, C: Clock> Prank<B, C> {
+    pub fn new(
+        backend: Rc<B>,
+        journal: Journal,
+        scheme: &CursorScheme,
+        rotation: Option<Rotation<C>>,
+        lifetime: Lifetime<C>,
+    ) -> Self {
+        // The playlist's first cursors are replaced along with the scheme, and later ones are
+        // only swapped in, so the originals saved the first time are the ones put back.
+        let mut scheme = scheme.clone();
+        if let Some(rotation) = &rotation {
+            scheme.merge(rotation.current());
+        }
+        Self {
+            backend,
+            journal,
+            scheme,
+            rotation,
+            lifetime,
+            cursors: None,
+        }
+    }
+
+    /// Brings the cursors up to date with the clock and returns the stage the prank is in.
+    /// Once it is over, the originals are back.
+    pub fn update(&mut self) -> Result<Stage, PrankError> {
+        let stage = self.lifetime.stage();
+        match stage {
+            Stage::Waiting => {}
+            Stage::Running if self.cursors.is_none() => {
+                // Replacing the scheme is all or nothing, so a failure leaves no cursor
+                // replaced.
+                let replaced = ReplacedScheme::replace(&self.backend, &self.scheme, &self.journal)
+                    .map_err(PrankError::Replace)?;
+                self.cursors = Some(replaced);
+            }
+            Stage::Running => {
+                if let (Some(rotation), Some(cursors)) = (&mut self.rotation, &self.cursors) {
+                    if let Some(next) = rotation.poll() {
+                        cursors.swap(next).map_err(PrankError::Swap)?;
+                    }
+                }
+            }
+            // Dropping the replaced cursors puts the originals back.
+            Stage::Over => self.cursors = None,
+        }
+        Ok(stage)
+    }
+
+    /// When `update` next has something to do, or `None` if only unlocking will end the prank.
+    pub fn next_change(&self) -> Option<Instant> {
+        if self.lifetime.stage() == Stage::Over {
+            return None;
+        }
+        let deadlines = [
+            self.lifetime.next_change(),
+            self.rotation.as_ref().and_then(Rotation::next_change),
+        ];
+        deadlines.iter().flatten().min().copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum PrankError {
+    /// The cursors could not go in, and none of them did
+    Replace(CursorError),
+    /// The rotation's next cursors could not go in, and the prank carries on with the ones
+    /// showing
+    Swap(CursorError),
+}
+
+impl fmt::Display for PrankError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Replace(err) => write!(f, "could not replace the cursors: {}", err),
+            Self::Swap(err) => write!(f, "could not change the cursors: {}", err),
+        }
+    }
+}
+
+impl Error for PrankError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Replace(err) | Self::Swap(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cursor::{CursorKind, MockBackend},
+        pool::Pool,
+        random::Rng,
+        rotation::{Interval, ManualClock, Playlist},
+        transform::Pipeline,
+    };
+    use std::{path::Path, time::Duration};
+
+    fn shown(backend: &MockBackend) -> Option<String> {
+        let cursor = backend.current(CursorKind::Normal)?;
+        Some(format!("{:?}", cursor.source))
+    }
+
+    #[test]
+    fn waits_rotates_and_puts_the_cursors_back() {
+        let clock = ManualClock::new();
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("prank");
+        let second = Duration::from_secs(1);
+
+        let lifetime = Lifetime::new(clock.clone(), 5 * second, Some(25 * second)).unwrap();
+        let mut playlist = Playlist::new();
+        playlist.push(CursorKind::Normal, Path::new("one.cur"));
+        playlist.push(CursorKind::Normal, Path::new("two.cur"));
+        let rotation = Rotation::new(
+            playlist,
+            Pool::new(),
+            Pipeline::new(),
+            Interval::Fixed(10 * second),
+            lifetime.start(),
+            clock.clone(),
+            Rng::new(0),
+        );
+        let mut prank = Prank::new(
+            Rc::clone(&backend),
+            journal.clone(),
+            &CursorScheme::new(),
+            Some(rotation),
+            lifetime,
+        );
+        let start = clock.now();
+
+        assert_eq!(prank.update().unwrap(), Stage::Waiting);
+        assert_eq!(shown(&backend), None);
+        assert_eq!(prank.next_change(), Some(start + 5 * second));
+
+        clock.advance(5 * second);
+        assert_eq!(prank.update().unwrap(), Stage::Running);
+        assert!(shown(&backend).unwrap().contains("one.cur"));
+        assert_eq!(journal.entries().unwrap().len(), 1);
+        assert_eq!(prank.next_change(), Some(start + 15 * second));
+
+        clock.advance(10 * second);
+        prank.update().unwrap();
+        assert!(shown(&backend).unwrap().contains("two.cur"));
+        assert_eq!(prank.next_change(), Some(start + 25 * second));
+
+        clock.advance(15 * second);
+        assert_eq!(prank.update().unwrap(), Stage::Over);
+        assert_eq!(shown(&backend), None);
+        assert!(journal.entries().unwrap().is_empty());
+        assert_eq!(prank.next_change(), None);
+    }
+
+    #[test]
+    fn failed_replace_leaves_nothing_replaced() {
+        let clock = ManualClock::new();
+        let backend = Rc::new(MockBackend::new());
+        let journal = Journal::temporary("prank-failed-replace");
+        backend.fail_replace(CursorKind::Normal);
+
+        let lifetime = Lifetime::new(clock, Duration::default(), None).unwrap();
+        let mut prank = Prank::new(
+            Rc::clone(&backend),
+            journal.clone(),
+            &CursorScheme::new(),
+            None,
+            lifetime,
+        );
+        assert!(matches!(prank.update(), Err(PrankError::Replace(_))));
+        assert_eq!(shown(&backend), None);
+        assert!(journal.entries().unwrap().is_empty());
+        assert_eq!(prank.next_change(), None);
+    }
+}
@@ -0,0 +1,266 @@
+//! Showing what a cursor file looks like without installing it.
+//!
+//! Cursors are drawn over a checkerboard so transparency shows, with the hotspot picked out in
+//! magenta. In the terminal every character cell holds two pixels, one above the other, drawn
+//! with an upper half block in 24-bit colour. Contact sheets lay out one row per animation
+//! frame and one column per size, with ticks in the margins lining up with the hotspot.
+
+use std::{
+    error::Error,
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    format::{self, png, FormatError},
+    image::{AnimatedCursor, CursorEntry, Hotspot},
+};
+
+const HOTSPOT_COLOUR: [u8; 3] = [255, 0, 255];
+const CHECKER_LIGHT: [u8; 3] = [204, 204, 204];
+const CHECKER_DARK: [u8; 3] = [153, 153, 153];
+const SHEET_BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+/// Space around each image on a contact sheet, which holds the hotspot ticks
+const MARGIN: u32 = 6;
+const TICK_LEN: u32 = 4;
+
+/// Prints each cursor in `inputs` to the terminal, and writes contact sheets to `output` if
+/// given: the PNG itself for a single cursor, or a directory to put `<name>.png` sheets in for
+/// several.
+pub fn preview(inputs: &[PathBuf], output: Option<&Path>) -> Result<(), PreviewError> {
+    if inputs.is_empty() {
+        return Err(PreviewError::NoInputs);
+    }
+
+    for path in inputs {
+        let bytes = fs::read(path).map_err(|err| PreviewError::Read(path.clone(), err))?;
+        let cursor =
+            format::decode(&bytes).map_err(|err| PreviewError::Decode(path.clone(), err))?;
+
+        println!("{}", path.display());
+        print!("{}", terminal(&cursor));
+
+        if let Some(output) = output {
+            let sheet_path = if inputs.len() == 1 {
+                output.to_owned()
+            } else {
+                let name = path.file_name().unwrap_or_default();
+                output.join(name).with_extension("png")
+            };
+            fs::write(&sheet_path, png::encode(&contact_sheet(&cursor)))
+                .map_err(|err| PreviewError::Write(sheet_path.clone(), err))?;
+            println!("Wrote {}", sheet_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Renders every size of every frame with 24-bit colour escape codes, each under a line
+/// describing it.
+pub fn terminal(cursor: &AnimatedCursor) -> String {
+    let mut out = String::new();
+    for (index, frame) in cursor.frames.iter().enumerate() {
+        for entry in &frame.image.entries {
+            let _ = write!(
+                out,
+                "{}x{}, hotspot {},{}",
+                entry.width, entry.height, entry.hotspot.x, entry.hotspot.y
+            );
+            if cursor.frames.len() > 1 {
+                let _ = write!(
+                    out,
+                    ", frame {} of {} for {} ms",
+                    index + 1,
+                    cursor.frames.len(),
+                    frame.delay.as_millis()
+                );
+            }
+            out.push('\n');
+
+            for y in (0..entry.height).step_by(2) {
+                for x in 0..entry.width {
+                    let [r, g, b] = shown_colour(entry, x, y);
+                    let _ = write!(out, "\x1b[38;2;{};{};{}m", r, g, b);
+                    // An odd height leaves the bottom half of the last row empty.
+                    if y + 1 < entry.height {
+                        let [r, g, b] = shown_colour(entry, x, y + 1);
+                        let _ = write!(out, "\x1b[48;2;{};{};{}m", r, g, b);
+                    } else {
+                        out.push_str("\x1b[49m");
+                    }
+                    out.push('\u{2580}');
+                }
+                out.push_str("\x1b[0m\n");
+            }
+        }
+    }
+    out
+}
+
+/// Lays out every size of every frame on one image.
+pub fn contact_sheet(cursor: &AnimatedCursor) -> CursorEntry {
+    let columns = cursor
+        .frames
+        .iter()
+        .map(|frame| frame.image.entries.len())
+        .max()
+        .unwrap_or(0);
+    let column_widths: Vec<u32> = (0..columns)
+        .map(|column| {
+            cursor
+                .frames
+                .iter()
+                .filter_map(|frame| frame.image.entries.get(column))
+                .map(|entry| entry.width + 2 * MARGIN)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let row_heights: Vec<u32> = cursor
+        .frames
+        .iter()
+        .map(|frame| {
+            frame
+                .image
+                .entries
+                .iter()
+                .map(|entry| entry.height + 2 * MARGIN)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let width = column_widths.iter().sum::<u32>().max(1);
+    let height = row_heights.iter().sum::<u32>().max(1);
+    let mut sheet = CursorEntry::new(width, height, Hotspot::default());
+    for y in 0..height {
+        for x in 0..width {
+            sheet.set_pixel(x, y, SHEET_BACKGROUND);
+        }
+    }
+
+    let mut top = 0;
+    for (frame, row_height) in cursor.frames.iter().zip(&row_heights) {
+        let mut left = 0;
+        for (entry, column_width) in frame.image.entries.iter().zip(&column_widths) {
+            draw_entry(&mut sheet, entry, left + MARGIN, top + MARGIN);
+            left += column_width;
+        }
+        top += row_height;
+    }
+    sheet
+}
+
+/// Draws `entry` on `sheet` with its top left corner at (`left`, `top`), and ticks in the
+/// margin around it pointing at the hotspot.
+fn draw_entry(sheet: &mut CursorEntry, entry: &CursorEntry, left: u32, top: u32) {
+    for y in 0..entry.height {
+        for x in 0..entry.width {
+            let [r, g, b] = shown_colour(entry, x, y);
+            sheet.set_pixel(left + x, top + y, [r, g, b, 255]);
+        }
+    }
+
+    let [r, g, b] = HOTSPOT_COLOUR;
+    let tick = [r, g, b, 255];
+    // A hotspot off the image, which some formats allow, gets ticks at the nearest edge.
+    let (hot_x, hot_y) = (
+        left + u32::from(entry.hotspot.x).min(entry.width - 1),
+        top + u32::from(entry.hotspot.y).min(entry.height - 1),
+    );
+    for offset in 1..=TICK_LEN {
+        sheet.set_pixel(hot_x, top - offset, tick);
+        sheet.set_pixel(hot_x, top + entry.height - 1 + offset, tick);
+        sheet.set_pixel(left - offset, hot_y, tick);
+        sheet.set_pixel(left + entry.width - 1 + offset, hot_y, tick);
+    }
+}
+
+/// The colour of a pixel blended over the checkerboard, or the hotspot colour at the hotspot.
+fn shown_colour(entry: &CursorEntry, x: u32, y: u32) -> [u8; 3] {
+    if x == u32::from(entry.hotspot.x) && y == u32::from(entry.hotspot.y) {
+        return HOTSPOT_COLOUR;
+    }
+    let background = if (x / 4 + y / 4).is_multiple_of(2) {
+        CHECKER_LIGHT
+    } else {
+        CHECKER_DARK
+    };
+    let [r, g, b, a] = entry.pixel(x, y);
+    let blend = |colour: u8, background: u8| {
+        ((u32::from(colour) * u32::from(a) + u32::from(background) * (255 - u32::from(a)) + 127)
+            / 255) as u8
+    };
+    [
+        blend(r, background[0]),
+        blend(g, background[1]),
+        blend(b, background[2]),
+    ]
+}
+
+#[derive(Debug)]
+pub enum PreviewError {
+    NoInputs,
+    Read(PathBuf, io::Error),
+    Decode(PathBuf, FormatError),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoInputs => f.write_str("no cursors to preview"),
+            Self::Read(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Decode(path, err) => {
+                write!(f, "{} is not a usable cursor: {}", path.display(), err)
+            }
+            Self::Write(path, err) => write!(f, "could not write {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for PreviewError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(_, err) | Self::Write(_, err) => Some(err),
+            Self::Decode(_, err) => Some(err),
+            Self::NoInputs => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{AnimationFrame, CursorImage};
+    use std::time::Duration;
+
+    fn sheet_for(entry: CursorEntry) -> CursorEntry {
+        contact_sheet(&AnimatedCursor::new(vec![AnimationFrame::new(
+            CursorImage::new(vec![entry]),
+            Duration::default(),
+        )]))
+    }
+
+    #[test]
+    fn ticks_point_at_the_hotspot() {
+        let sheet = sheet_for(CursorEntry::new(8, 8, Hotspot::new(2, 5)));
+        let [r, g, b] = HOTSPOT_COLOUR;
+        let tick = [r, g, b, 255];
+        assert_eq!(sheet.pixel(MARGIN + 2, MARGIN - 1), tick);
+        assert_eq!(sheet.pixel(MARGIN + 2, MARGIN + 8), tick);
+        assert_eq!(sheet.pixel(MARGIN - 1, MARGIN + 5), tick);
+        assert_eq!(sheet.pixel(MARGIN + 8, MARGIN + 5), tick);
+    }
+
+    #[test]
+    fn hotspot_off_the_image_ticks_at_the_edge() {
+        for hotspot in &[Hotspot::new(8, 8), Hotspot::new(u16::MAX, u16::MAX)] {
+            let sheet = sheet_for(CursorEntry::new(8, 8, *hotspot));
+            let [r, g, b] = HOTSPOT_COLOUR;
+            assert_eq!(sheet.pixel(MARGIN + 7, MARGIN - 1), [r, g, b, 255]);
+            assert_eq!(sheet.pixel(MARGIN - 1, MARGIN + 7), [r, g, b, 255]);
+        }
+    }
+}
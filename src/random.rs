@@ -0,0 +1,85 @@
+//! A small random number generator, good enough for picking cursors and when to show them but
+//! not for anything that needs to be unpredictable. The same seed always gives the same
+//! numbers, so a run can be played again.
+
+use std::{
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// SplitMix64, which turns any seed into a good stream, zero included
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from `0` up to but not including `bound`, which must not be 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Rejecting the top of the range that doesn't divide evenly keeps every result equally
+        // likely.
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+/// A seed from the time and process id, so each run goes differently unless it is given the
+/// seed of another.
+pub fn seed_from_time() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    nanos ^ u64::from(process::id()).rotate_left(32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_splitmix64() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let numbers = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..10).map(|_| rng.below(1000)).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(5), numbers(5));
+        assert_ne!(numbers(5), numbers(6));
+    }
+
+    #[test]
+    fn below_stays_in_range() {
+        let mut rng = Rng::new(3);
+        assert_eq!(rng.below(1), 0);
+        let mut seen = [false; 6];
+        for _ in 0..600 {
+            seen[rng.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        assert!(rng.below(u64::MAX) < u64::MAX);
+    }
+}
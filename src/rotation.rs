@@ -0,0 +1,501 @@
+//! Switching cursors on a timer, so the prank keeps changing after it starts.
+//!
+//! A playlist holds a list of cursors for each kind it covers. Every time the interval runs out,
+//! each of those kinds moves on to its next cursor, starting over after the last. The interval
+//! is the same every time, picked at random from a range each time, or taken in turn from a
+//! schedule of waits.
+//!
+//! Kinds with a pool rather than a playlist get a cursor picked at random from it at every
+//! change instead, and a kind with both follows its playlist.
+
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cursor::CursorKind,
+    generate::InvalidDesign,
+    pool::Pool,
+    random::Rng,
+    scheme::{self, CursorScheme, SchemeCursor},
+    transform::Pipeline,
+};
+
+/// How long each cursor of a playlist is shown when no interval is given
+pub const DEFAULT_INTERVAL: Interval = Interval::Fixed(Duration::from_secs(60));
+
+/// Tells the time. Rotations ask one for it instead of the system, so they can be driven by a
+/// clock that doesn't have to wait for real time to pass.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The real, monotonic time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, shared between its clones
+#[cfg(test)]
+#[derive(Clone, Debug)]
+pub struct ManualClock(std::rc::Rc<std::cell::Cell<Instant>>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> Self {
+        Self(std::rc::Rc::new(std::cell::Cell::new(Instant::now())))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.0.get()
+    }
+}
+
+/// The cursors to cycle through for each kind.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Playlist {
+    cursors: Vec<(CursorKind, Vec<SchemeCursor>)>,
+}
+
+impl Playlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a cursor to the end of the list for `kind`.
+    pub fn push<C: Into<SchemeCursor>>(&mut self, kind: CursorKind, cursor: C) {
+        let cursor = cursor.into();
+        match self.cursors.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, list)) => list.push(cursor),
+            None => self.cursors.push((kind, vec![cursor])),
+        }
+    }
+
+    /// Adds a cursor written as `Kind=path`, or just `path` for the normal pointer, the same
+    /// way as `CursorScheme::set_from_str`.
+    pub fn push_from_str(&mut self, assignment: &str) -> Result<(), InvalidDesign> {
+        let (kind, path) = scheme::split_assignment(assignment);
+        self.push(kind, SchemeCursor::from_str_in(path, Path::new(""))?);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+
+    /// Takes the list of every kind in `other`, replacing the one already here for the same
+    /// kind.
+    pub fn merge(&mut self, other: &Playlist) {
+        for (kind, list) in &other.cursors {
+            match self.cursors.iter_mut().find(|(k, _)| k == kind) {
+                Some(entry) => entry.1 = list.clone(),
+                None => self.cursors.push((*kind, list.clone())),
+            }
+        }
+    }
+
+    /// The cursor each kind shows after `step` changes, with `transforms` applied to them.
+    pub fn scheme(&self, step: usize, transforms: &Pipeline) -> CursorScheme {
+        let mut scheme = CursorScheme::new();
+        for (kind, list) in &self.cursors {
+            scheme.set(*kind, list[step % list.len()].clone());
+        }
+        scheme.set_transforms(transforms.clone());
+        scheme
+    }
+}
+
+/// How long to wait between changes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Interval {
+    Fixed(Duration),
+    /// Any wait from `min` to `max`, picked anew for each change
+    Random {
+        min: Duration,
+        max: Duration,
+    },
+    /// Each wait in turn, starting over after the last
+    Schedule(Vec<Duration>),
+}
+
+impl Interval {
+    /// The wait before change number `step + 1`.
+    fn wait(&self, step: usize, rng: &mut Rng) -> Duration {
+        match self {
+            Self::Fixed(wait) => *wait,
+            Self::Random { min, max } => {
+                let spread = u64::try_from((*max - *min).as_millis()).unwrap_or(u64::MAX);
+                *min + Duration::from_millis(rng.below(spread.saturating_add(1)))
+            }
+            Self::Schedule(waits) => waits[step % waits.len()],
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = ParseIntervalError;
+
+    /// Parses a single duration like `30s`, a range like `10s-2m` or a comma separated
+    /// schedule like `5s,1m,30s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let positive = |value: &str| match parse_duration(value)? {
+            wait if wait == Duration::default() => Err(ParseIntervalError::Zero),
+            wait => Ok(wait),
+        };
+
+        if s.contains(',') {
+            Ok(Self::Schedule(
+                s.split(',').map(positive).collect::<Result<_, _>>()?,
+            ))
+        } else if let Some(dash) = s.find('-') {
+            let (min, max) = (positive(&s[..dash])?, positive(&s[dash + 1..])?);
+            if min > max {
+                return Err(ParseIntervalError::BackwardsRange(s.to_owned()));
+            }
+            Ok(Self::Random { min, max })
+        } else {
+            Ok(Self::Fixed(positive(s)?))
+        }
+    }
+}
+
+/// Parses a number followed by `ms`, `s`, `m` or `h`, or a plain number of seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, ParseIntervalError> {
+    let value = value.trim();
+    let invalid = || ParseIntervalError::InvalidDuration(value.to_owned());
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let seconds_per_unit = match &value[split..] {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(invalid()),
+    };
+    match value[..split].parse::<f64>() {
+        Ok(number) if number >= 0.0 && number.is_finite() => {
+            Duration::try_from_secs_f64(number * seconds_per_unit)
+                .map_err(|_| ParseIntervalError::TooLong(value.to_owned()))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Works out when a playlist moves on and a pool is picked from again, by the time on its
+/// clock.
+#[derive(Debug)]
+pub struct Rotation<C: Clock> {
+    playlist: Playlist,
+    pool: Pool,
+    transforms: Pipeline,
+    interval: Interval,
+    clock: C,
+    rng: Rng,
+    /// Changes made so far
+    step: usize,
+    /// `None` once a wait reaches further ahead than the clock can tell, so the change never
+    /// comes
+    next_change: Option<Instant>,
+    current: CursorScheme,
+}
+
+impl<C: Clock> Rotation<C> {
+    /// Starts the playlist on its first cursors and picks the first from the pool, with the
+    /// first change one wait after `start`, when the cursors go in.
+    pub fn new(
+        playlist: Playlist,
+        pool: Pool,
+        transforms: Pipeline,
+        interval: Interval,
+        start: Instant,
+        clock: C,
+        mut rng: Rng,
+    ) -> Self {
+        let next_change = start.checked_add(interval.wait(0, &mut rng));
+        let mut rotation = Self {
+            playlist,
+            pool,
+            transforms,
+            interval,
+            clock,
+            rng,
+            step: 0,
+            next_change,
+            current: CursorScheme::new(),
+        };
+        rotation.current = rotation.pick();
+        rotation
+    }
+
+    /// The cursors that should be showing right now.
+    pub fn current(&self) -> &CursorScheme {
+        &self.current
+    }
+
+    fn pick(&mut self) -> CursorScheme {
+        let mut scheme = self.pool.pick(&mut self.rng);
+        scheme.merge(&self.playlist.scheme(self.step, &self.transforms));
+        scheme
+    }
+
+    pub fn next_change(&self) -> Option<Instant> {
+        self.next_change
+    }
+
+    /// Moves on to the next cursors if it is time to, and returns them.
+    ///
+    /// Changes keep to their schedule even if a poll comes late, but a rotation that falls
+    /// more than a wait behind, like when the computer sleeps, makes a single change and
+    /// carries on from now rather than rushing through the ones it missed.
+    pub fn poll(&mut self) -> Option<&CursorScheme> {
+        let now = self.clock.now();
+        match self.next_change {
+            Some(next_change) if now >= next_change => {}
+            _ => return None,
+        }
+
+        self.step += 1;
+        let wait = self.interval.wait(self.step, &mut self.rng);
+        self.next_change = match self.next_change.and_then(|next| next.checked_add(wait)) {
+            Some(next_change) if next_change <= now => now.checked_add(wait),
+            next_change => next_change,
+        };
+        self.current = self.pick();
+        Some(&self.current)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseIntervalError {
+    InvalidDuration(String),
+    /// A duration too long to count
+    TooLong(String),
+    /// A wait of nothing, which would change cursors as fast as the computer can
+    Zero,
+    /// A range with its larger end first
+    BackwardsRange(String),
+}
+
+impl fmt::Display for ParseIntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidDuration(value) => write!(
+                f,
+                "invalid duration `{}`, expected a number followed by ms, s, m or h",
+                value
+            ),
+            Self::TooLong(value) => write!(f, "the duration `{}` is too long", value),
+            Self::Zero => f.write_str("an interval must be longer than zero"),
+            Self::BackwardsRange(range) => {
+                write!(f, "the range `{}` has its larger end first", range)
+            }
+        }
+    }
+}
+
+impl Error for ParseIntervalError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist_rotation(interval: Interval, clock: &ManualClock) -> Rotation<ManualClock> {
+        let mut playlist = Playlist::new();
+        for path in &["one.cur", "two.cur", "three.cur"] {
+            playlist.push(CursorKind::Normal, Path::new(path));
+        }
+        Rotation::new(
+            playlist,
+            Pool::new(),
+            Pipeline::new(),
+            interval,
+            clock.now(),
+            clock.clone(),
+            Rng::new(0),
+        )
+    }
+
+    /// The normal pointer's file in a scheme, which is all the rotations here change
+    fn normal(scheme: &CursorScheme) -> &Path {
+        match scheme.iter().find(|&(kind, _)| kind == CursorKind::Normal) {
+            Some((_, SchemeCursor::File(path))) => path,
+            other => panic!("expected a file for the normal pointer, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn playlist_cycles_on_a_fixed_interval() {
+        let clock = ManualClock::new();
+        let mut rotation = playlist_rotation(Interval::Fixed(Duration::from_secs(10)), &clock);
+        assert_eq!(normal(rotation.current()), Path::new("one.cur"));
+
+        clock.advance(Duration::from_secs(9));
+        assert!(rotation.poll().is_none());
+        for path in &["two.cur", "three.cur", "one.cur"] {
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(normal(rotation.poll().unwrap()), Path::new(path));
+            assert!(rotation.poll().is_none());
+            clock.advance(Duration::from_secs(9));
+            assert!(rotation.poll().is_none());
+        }
+    }
+
+    #[test]
+    fn late_poll_keeps_to_the_schedule() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut rotation = playlist_rotation(Interval::Fixed(Duration::from_secs(10)), &clock);
+
+        clock.advance(Duration::from_secs(15));
+        assert!(rotation.poll().is_some());
+        assert_eq!(
+            rotation.next_change(),
+            Some(start + Duration::from_secs(20))
+        );
+    }
+
+    #[test]
+    fn sleeping_through_changes_makes_only_one() {
+        let clock = ManualClock::new();
+        let mut rotation = playlist_rotation(Interval::Fixed(Duration::from_secs(10)), &clock);
+
+        clock.advance(Duration::from_secs(55));
+        assert_eq!(normal(rotation.poll().unwrap()), Path::new("two.cur"));
+        assert!(rotation.poll().is_none());
+        assert_eq!(
+            rotation.next_change(),
+            Some(clock.now() + Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn schedule_waits_in_turn() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let waits = [1, 5, 2].iter().map(|&s| Duration::from_secs(s)).collect();
+        let mut rotation = playlist_rotation(Interval::Schedule(waits), &clock);
+
+        let mut changes = Vec::new();
+        for second in 1..=16 {
+            clock.advance(Duration::from_secs(1));
+            if rotation.poll().is_some() {
+                changes.push(second);
+            }
+        }
+        // Waits of 1, then 5, 2, 1, 5, 2, starting over after the last
+        assert_eq!(changes, vec![1, 6, 8, 9, 14, 16]);
+        assert_eq!(
+            rotation.next_change(),
+            Some(start + Duration::from_secs(17))
+        );
+    }
+
+    #[test]
+    fn random_waits_stay_in_range() {
+        let clock = ManualClock::new();
+        let (min, max) = (Duration::from_secs(2), Duration::from_secs(4));
+        let mut rotation = playlist_rotation(Interval::Random { min, max }, &clock);
+
+        for _ in 0..50 {
+            let wait = rotation.next_change().unwrap() - clock.now();
+            assert!(min <= wait && wait <= max, "waited {:?}", wait);
+            clock.advance(wait);
+            assert!(rotation.poll().is_some());
+        }
+    }
+
+    #[test]
+    fn pool_is_picked_from_at_every_change() {
+        let picks = |seed| {
+            let clock = ManualClock::new();
+            let mut pool = Pool::new();
+            for path in &["a.cur", "b.cur", "c.cur"] {
+                pool.push(CursorKind::Normal, Path::new(path), 1);
+            }
+            let mut rotation = Rotation::new(
+                Playlist::new(),
+                pool,
+                Pipeline::new(),
+                Interval::Fixed(Duration::from_secs(1)),
+                clock.now(),
+                clock.clone(),
+                Rng::new(seed),
+            );
+            let mut picks = vec![normal(rotation.current()).to_owned()];
+            for _ in 0..30 {
+                clock.advance(Duration::from_secs(1));
+                picks.push(normal(rotation.poll().unwrap()).to_owned());
+            }
+            picks
+        };
+
+        let first = picks(42);
+        assert_eq!(picks(42), first);
+        for path in &["a.cur", "b.cur", "c.cur"] {
+            assert!(first.iter().any(|pick| pick == Path::new(path)));
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(matches!(
+            parse_duration("5d"),
+            Err(ParseIntervalError::InvalidDuration(_))
+        ));
+        assert!(matches!(
+            parse_duration("-1s"),
+            Err(ParseIntervalError::InvalidDuration(_))
+        ));
+    }
+
+    #[test]
+    fn huge_durations_are_too_long() {
+        assert!(matches!(
+            parse_duration("100000000000000000000000h"),
+            Err(ParseIntervalError::TooLong(_))
+        ));
+        assert!(matches!(
+            "1s-100000000000000000000000h".parse::<Interval>(),
+            Err(ParseIntervalError::TooLong(_))
+        ));
+    }
+
+    #[test]
+    fn wait_past_the_end_of_time_never_comes() {
+        let clock = ManualClock::new();
+        let mut rotation = playlist_rotation(Interval::Fixed(Duration::MAX), &clock);
+        assert_eq!(rotation.next_change(), None);
+        clock.advance(Duration::from_secs(3600));
+        assert!(rotation.poll().is_none());
+
+        let schedule = Interval::Schedule(vec![Duration::from_secs(1), Duration::MAX]);
+        let mut rotation = playlist_rotation(schedule, &clock);
+        clock.advance(Duration::from_secs(1));
+        assert!(rotation.poll().is_some());
+        assert_eq!(rotation.next_change(), None);
+        clock.advance(Duration::from_secs(3600));
+        assert!(rotation.poll().is_none());
+    }
+}
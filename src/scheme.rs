@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cursor::CursorKind,
+    generate::{Design, InvalidDesign},
+    image::Hotspot,
+    transform::Pipeline,
+};
+
+/// Stands for the cursor the system is already using, wherever a cursor file is expected
+pub const SYSTEM: &str = "system";
+/// Starts a design to draw the cursor from, wherever a cursor file is expected
+pub const GENERATE_PREFIX: &str = "generate:";
+
+/// Which cursor to use for each kind of system cursor, and the transforms to apply to all of
+/// them. Kinds without a cursor are left alone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CursorScheme {
+    cursors: Vec<(CursorKind, SchemeCursor)>,
+    /// Hotspots that replace the ones stored in the cursor files, in pixels of the largest image
+    hotspots: Vec<(CursorKind, Hotspot)>,
+    transforms: Pipeline,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemeCursor {
+    File(PathBuf),
+    /// The cursor the system is already using for the kind, which is only worth replacing with
+    /// itself if the scheme transforms it
+    System,
+    Generated(Design),
+}
+
+impl SchemeCursor {
+    /// Reads `system` as `System`, `generate:<design>` as a cursor drawn from the design and
+    /// anything else as a path relative to `dir`.
+    pub fn from_str_in(value: &str, dir: &Path) -> Result<Self, InvalidDesign> {
+        if value == SYSTEM {
+            Ok(Self::System)
+        } else if let Some(design) = value.strip_prefix(GENERATE_PREFIX) {
+            Ok(Self::Generated(design.parse()?))
+        } else {
+            Ok(Self::File(dir.join(value)))
+        }
+    }
+}
+
+impl From<PathBuf> for SchemeCursor {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
+impl From<&Path> for SchemeCursor {
+    fn from(path: &Path) -> Self {
+        Self::File(path.to_owned())
+    }
+}
+
+impl CursorScheme {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the cursor for `kind`, replacing any cursor it had before along with its hotspot.
+    pub fn set<C: Into<SchemeCursor>>(&mut self, kind: CursorKind, cursor: C) {
+        let cursor = cursor.into();
+        match self.cursors.iter_mut().find(|(k, _)| *k == kind) {
+            Some(entry) => entry.1 = cursor,
+            None => self.cursors.push((kind, cursor)),
+        }
+        self.hotspots.retain(|(k, _)| *k != kind);
+    }
+
+    /// Overrides the hotspot of the cursor set for `kind`.
+    pub fn set_hotspot(&mut self, kind: CursorKind, hotspot: Hotspot) {
+        match self.hotspots.iter_mut().find(|(k, _)| *k == kind) {
+            Some(entry) => entry.1 = hotspot,
+            None => self.hotspots.push((kind, hotspot)),
+        }
+    }
+
+    pub fn hotspot(&self, kind: CursorKind) -> Option<Hotspot> {
+        self.hotspots
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, hotspot)| *hotspot)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (CursorKind, &SchemeCursor)> {
+        self.cursors.iter().map(|(kind, cursor)| (*kind, cursor))
+    }
+
+    /// Whether the scheme names no cursors. It may still have transforms.
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+
+    pub fn transforms(&self) -> &Pipeline {
+        &self.transforms
+    }
+
+    pub fn set_transforms(&mut self, transforms: Pipeline) {
+        self.transforms = transforms;
+    }
+
+    /// Takes every cursor from `other`, overriding the ones already set for the same kind, and
+    /// its transforms if it has any.
+    pub fn merge(&mut self, other: &CursorScheme) {
+        for (kind, cursor) in other.iter() {
+            self.set(kind, cursor.clone());
+        }
+        for &(kind, hotspot) in &other.hotspots {
+            self.set_hotspot(kind, hotspot);
+        }
+        if !other.transforms.is_empty() {
+            self.transforms = other.transforms.clone();
+        }
+    }
+
+    /// Adds an assignment written as `Kind=path`, or just `path` for the normal pointer. The
+    /// path may be `system` for the cursor already in use, or a design to draw.
+    pub fn set_from_str(&mut self, assignment: &str) -> Result<(), InvalidDesign> {
+        let (kind, path) = split_assignment(assignment);
+        self.set(kind, SchemeCursor::from_str_in(path, Path::new(""))?);
+        Ok(())
+    }
+}
+
+/// Splits `Kind=value` into the kind and the value, reading anything else as a value for the
+/// normal pointer.
+pub fn split_assignment(assignment: &str) -> (CursorKind, &str) {
+    if let Some(eq) = assignment.find('=') {
+        if let Ok(kind) = assignment[..eq].trim().parse() {
+            return (kind, &assignment[eq + 1..]);
+        }
+    }
+    (CursorKind::Normal, assignment)
+}
@@ -0,0 +1,384 @@
+//! Directories holding a cursor for each kind, so a whole look can be handed around at once.
+//!
+//! Cursors are found by name: `Normal.cur`, `IBeam.ani`, `Hand` (an Xcursor file) and so on,
+//! after `CursorKind::as_str`, ignoring case. An optional `theme.conf` in the directory can
+//! point a kind at some other file and move hotspots:
+//!
+//! ```text
+//! cursor.Wait = hourglass.ani
+//! cursor.Up = system
+//! hotspot.Normal = 3,2
+//! ```
+//!
+//! Hotspots are in pixels of the largest image in the cursor.
+//!
+//! A Windows cursor scheme can be used as a theme too, by loading its `.inf` file.
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config,
+    cursor::CursorKind,
+    image::Hotspot,
+    inf,
+    scheme::{CursorScheme, SchemeCursor},
+};
+
+pub const MANIFEST_NAME: &str = "theme.conf";
+
+/// Extensions of the files taken as cursors, with the empty one standing for Xcursor files
+const EXTENSIONS: [&str; 3] = ["cur", "ani", ""];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    scheme: CursorScheme,
+    missing: Vec<CursorKind>,
+}
+
+impl Theme {
+    /// Loads a theme directory, or the Windows cursor scheme an `.inf` file installs.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ThemeError> {
+        let path = path.as_ref();
+        let is_inf = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("inf"));
+        if is_inf && path.is_file() {
+            Self::load_inf(path)
+        } else {
+            Self::load_dir(path)
+        }
+    }
+
+    fn load_inf(path: &Path) -> Result<Self, ThemeError> {
+        let text = fs::read(path).map_err(|err| ThemeError::Read(path.to_owned(), err))?;
+        // Scheme files are often saved in a Windows code page rather than UTF-8.
+        let text = String::from_utf8_lossy(&text);
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let cursors = inf::parse(&text, dir)
+            .map_err(|message| ThemeError::Manifest(path.to_owned(), message))?;
+
+        let mut scheme = CursorScheme::new();
+        for (kind, file) in cursors {
+            scheme.set(kind, file);
+        }
+        Ok(Self::new(scheme))
+    }
+
+    fn load_dir(dir: &Path) -> Result<Self, ThemeError> {
+        let read_dir = |err| ThemeError::Read(dir.to_owned(), err);
+
+        let mut files: Vec<(CursorKind, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(dir).map_err(read_dir)? {
+            let path = entry.map_err(read_dir)?.path();
+            let kind = match cursor_kind(&path) {
+                Some(kind) if path.is_file() => kind,
+                _ => continue,
+            };
+            if let Some((_, other)) = files.iter().find(|(k, _)| *k == kind) {
+                return Err(ThemeError::Ambiguous {
+                    kind,
+                    first: other.clone(),
+                    second: path,
+                });
+            }
+            files.push((kind, path));
+        }
+
+        let mut scheme = CursorScheme::new();
+        // Sticking to the order of `CursorKind::ALL` keeps the directory order from leaking out.
+        for &kind in CursorKind::ALL.iter() {
+            if let Some((_, path)) = files.iter().find(|(k, _)| *k == kind) {
+                scheme.set(kind, path.as_path());
+            }
+        }
+
+        let manifest = dir.join(MANIFEST_NAME);
+        match fs::read_to_string(&manifest) {
+            Ok(text) => apply_manifest(&mut scheme, &text, dir)
+                .map_err(|message| ThemeError::Manifest(manifest, message))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(ThemeError::Read(manifest, err)),
+        }
+
+        Ok(Self::new(scheme))
+    }
+
+    fn new(scheme: CursorScheme) -> Self {
+        let missing = CursorKind::ALL
+            .iter()
+            .copied()
+            .filter(|&kind| scheme.iter().all(|(k, _)| k != kind))
+            .collect();
+        Self { scheme, missing }
+    }
+
+    pub fn scheme(&self) -> &CursorScheme {
+        &self.scheme
+    }
+
+    /// Kinds the theme has no cursor for, which are left as they are
+    pub fn missing(&self) -> &[CursorKind] {
+        &self.missing
+    }
+}
+
+/// The kind a file in a theme directory is the cursor for, if it is one.
+fn cursor_kind(path: &Path) -> Option<CursorKind> {
+    if !has_cursor_extension(path) {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+/// Whether a file is named like a cursor, `.cur`, `.ani` or no extension at all for Xcursor.
+pub fn has_cursor_extension(path: &Path) -> bool {
+    let extension = match path.extension() {
+        Some(extension) => extension.to_str(),
+        None => Some(""),
+    };
+    extension.is_some_and(|extension| {
+        EXTENSIONS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(extension))
+    })
+}
+
+fn apply_manifest(scheme: &mut CursorScheme, text: &str, dir: &Path) -> Result<(), String> {
+    // Hotspots wait until every cursor is set, since setting a cursor drops its hotspot.
+    let mut hotspots = Vec::new();
+    for setting in config::settings(text) {
+        let (line, key, value) = setting?;
+        let kind = |prefix: &str| {
+            key[prefix.len()..]
+                .parse::<CursorKind>()
+                .map_err(|err| format!("line {}: {}", line, err))
+        };
+        if key.starts_with("cursor.") {
+            let cursor = SchemeCursor::from_str_in(value, dir)
+                .map_err(|err| format!("line {}: {}", line, err))?;
+            scheme.set(kind("cursor.")?, cursor);
+        } else if key.starts_with("hotspot.") {
+            let hotspot = value
+                .parse::<Hotspot>()
+                .map_err(|err| format!("line {}: {}", line, err))?;
+            hotspots.push((kind("hotspot.")?, hotspot));
+        } else {
+            return Err(format!("line {}: unknown setting `{}`", line, key));
+        }
+    }
+    for (kind, hotspot) in hotspots {
+        scheme.set_hotspot(kind, hotspot);
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Read(PathBuf, io::Error),
+    Manifest(PathBuf, String),
+    /// Two files in the directory are named after the same kind
+    Ambiguous {
+        kind: CursorKind,
+        first: PathBuf,
+        second: PathBuf,
+    },
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Manifest(path, message) => write!(f, "{}: {}", path.display(), message),
+            Self::Ambiguous {
+                kind,
+                first,
+                second,
+            } => write!(
+                f,
+                "both {} and {} are cursors for {}",
+                first.display(),
+                second.display(),
+                kind.as_str()
+            ),
+        }
+    }
+}
+
+impl Error for ThemeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// A theme directory of its own for a test, holding empty files with the given names and
+    /// a `theme.conf` if given one
+    fn temporary_theme(name: &str, files: &[&str], manifest: Option<&str>) -> PathBuf {
+        let dir = env::temp_dir().join(format!("justaprankbro-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in files {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        if let Some(manifest) = manifest {
+            fs::write(dir.join(MANIFEST_NAME), manifest).unwrap();
+        }
+        dir
+    }
+
+    fn cursors(theme: &Theme) -> Vec<(CursorKind, SchemeCursor)> {
+        theme
+            .scheme()
+            .iter()
+            .map(|(kind, cursor)| (kind, cursor.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_cursors_by_name() {
+        let dir = temporary_theme(
+            "theme-names",
+            &[
+                "normal.CUR",
+                "IBeam.ani",
+                "Hand",
+                "Wait.txt",
+                "README",
+                "notes.cur",
+            ],
+            None,
+        );
+        fs::create_dir(dir.join("Up.cur")).unwrap();
+
+        let theme = Theme::load(&dir).unwrap();
+        assert_eq!(
+            cursors(&theme),
+            vec![
+                (CursorKind::Normal, dir.join("normal.CUR").into()),
+                (CursorKind::Hand, dir.join("Hand").into()),
+                (CursorKind::Ibeam, dir.join("IBeam.ani").into()),
+            ]
+        );
+        let missing: Vec<CursorKind> = CursorKind::ALL
+            .iter()
+            .copied()
+            .filter(|kind| {
+                ![CursorKind::Normal, CursorKind::Hand, CursorKind::Ibeam].contains(kind)
+            })
+            .collect();
+        assert_eq!(theme.missing(), missing.as_slice());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn two_cursors_for_a_kind_are_ambiguous() {
+        let dir = temporary_theme("theme-ambiguous", &["Hand.cur", "Hand.ani"], None);
+        match Theme::load(&dir) {
+            Err(ThemeError::Ambiguous {
+                kind,
+                first,
+                second,
+            }) => {
+                assert_eq!(kind, CursorKind::Hand);
+                let mut both = vec![first, second];
+                both.sort();
+                assert_eq!(both, vec![dir.join("Hand.ani"), dir.join("Hand.cur")]);
+            }
+            other => panic!("expected an ambiguous theme, got {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_overrides_cursors_and_hotspots() {
+        let manifest = "\
+            # Hotspots may come before the cursors they move\n\
+            hotspot.Wait = 1,1\n\
+            hotspot.Normal = 3,2\n\
+            cursor.Wait = hourglass.ani\n\
+            cursor.Up = system\n\
+            cursor.Hand = generate:smiley\n";
+        let dir = temporary_theme(
+            "theme-manifest",
+            &["Normal.cur", "Wait.ani"],
+            Some(manifest),
+        );
+
+        let theme = Theme::load(&dir).unwrap();
+        assert_eq!(
+            cursors(&theme),
+            vec![
+                (CursorKind::Normal, dir.join("Normal.cur").into()),
+                (CursorKind::Wait, dir.join("hourglass.ani").into()),
+                (CursorKind::Up, SchemeCursor::System),
+                (
+                    CursorKind::Hand,
+                    SchemeCursor::Generated("smiley".parse().unwrap())
+                ),
+            ]
+        );
+        let scheme = theme.scheme();
+        assert_eq!(scheme.hotspot(CursorKind::Normal), Some(Hotspot::new(3, 2)));
+        assert_eq!(scheme.hotspot(CursorKind::Wait), Some(Hotspot::new(1, 1)));
+        assert_eq!(scheme.hotspot(CursorKind::Up), None);
+        assert!(!theme.missing().contains(&CursorKind::Up));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_manifest_errors_by_line() {
+        let manifest_error = |name: &str, manifest: &str| {
+            let dir = temporary_theme(name, &["Normal.cur"], Some(manifest));
+            let message = match Theme::load(&dir) {
+                Err(ThemeError::Manifest(path, message)) => {
+                    assert_eq!(path, dir.join(MANIFEST_NAME));
+                    message
+                }
+                other => panic!("expected a manifest error, got {:?}", other),
+            };
+            fs::remove_dir_all(&dir).unwrap();
+            message
+        };
+        assert_eq!(
+            manifest_error("theme-unknown", "cursor.Wait = a.ani\ncolour = red"),
+            "line 2: unknown setting `colour`"
+        );
+        assert_eq!(
+            manifest_error("theme-kind", "cursor.Pen = pen.cur"),
+            "line 1: unknown cursor kind `Pen`"
+        );
+        assert!(manifest_error("theme-hotspot", "hotspot.Normal = 3").starts_with("line 1: "));
+    }
+
+    #[test]
+    fn missing_directory_is_a_read_error() {
+        let dir = env::temp_dir().join(format!("justaprankbro-test-{}-gone", process::id()));
+        assert!(matches!(Theme::load(&dir), Err(ThemeError::Read(path, _)) if path == dir));
+    }
+
+    #[test]
+    fn loads_windows_schemes_from_their_inf() {
+        let inf = "[Scheme.Reg]\n\
+                   HKCU,\"Control Panel\\Cursors\",Arrow,0x00020000,\"%10%\\Cursors\\a.cur\"\n";
+        let dir = temporary_theme("theme-inf", &["A.cur"], None);
+        fs::write(dir.join("Install.INF"), inf).unwrap();
+
+        let theme = Theme::load(dir.join("Install.INF")).unwrap();
+        assert_eq!(
+            cursors(&theme),
+            vec![(CursorKind::Normal, dir.join("A.cur").into())]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
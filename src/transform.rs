@@ -0,0 +1,597 @@
+//! Effects that alter a cursor rather than replace it, applied one after another in a pipeline.
+//!
+//! Pipelines are written as a comma separated list, like `flip-h,hue=90,opacity=0.8`:
+//!
+//! * `flip-h` and `flip-v` mirror the cursor, moving the hotspot along with it
+//! * `rotate=90`, `rotate=180` and `rotate=270` turn it clockwise
+//! * `invert` inverts its colours
+//! * `hue=<degrees>` shifts its hue around the colour wheel
+//! * `scale=<factor>` resizes it
+//! * `opacity=<0 to 1>` makes it more transparent
+
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::image::{AnimatedCursor, CursorEntry, CursorImage, Hotspot};
+
+/// Scaling further than this is more likely a typo than a prank
+const MAX_SCALE: f32 = 16.0;
+
+/// The sizes Windows draws cursors at with display scaling from 100% to 400%
+pub const STANDARD_SIZES: [u32; 5] = [32, 48, 64, 96, 128];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    FlipHorizontal,
+    FlipVertical,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Invert,
+    /// Shifts the hue by this many degrees
+    HueShift(f32),
+    /// Resizes the image but not the size it is shown for, so it stays larger or smaller than
+    /// the cursors around it once fitted to the display
+    Scale(f32),
+    /// Multiplies the alpha of every pixel
+    Opacity(f32),
+}
+
+impl Transform {
+    pub fn apply(self, entry: &CursorEntry) -> CursorEntry {
+        let (width, height) = (entry.width, entry.height);
+        match self {
+            Self::FlipHorizontal => remap(entry, width, height, |x, y| (width - 1 - x, y)),
+            Self::FlipVertical => remap(entry, width, height, |x, y| (x, height - 1 - y)),
+            Self::Rotate90 => remap(entry, height, width, |x, y| (height - 1 - y, x)),
+            Self::Rotate180 => remap(entry, width, height, |x, y| (width - 1 - x, height - 1 - y)),
+            Self::Rotate270 => remap(entry, height, width, |x, y| (y, width - 1 - x)),
+            Self::Invert => map_pixels(entry, |[r, g, b, a]| [255 - r, 255 - g, 255 - b, a]),
+            Self::HueShift(degrees) => map_pixels(entry, |rgba| shift_hue(rgba, degrees)),
+            Self::Scale(factor) => {
+                let scaled = |size: u32| ((size as f32 * factor).round() as u32).max(1);
+                let mut scaled_entry = resize(entry, scaled(width), scaled(height));
+                scaled_entry.nominal_size = entry.nominal_size;
+                scaled_entry
+            }
+            Self::Opacity(opacity) => map_pixels(entry, |[r, g, b, a]| {
+                [r, g, b, (f32::from(a) * opacity).round() as u8]
+            }),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = ParseTransformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, value) = match s.find('=') {
+            Some(eq) => (s[..eq].trim(), Some(s[eq + 1..].trim())),
+            None => (s, None),
+        };
+        let number = |min: f32, max: f32| -> Result<f32, ParseTransformError> {
+            let value = value.ok_or_else(|| ParseTransformError::MissingValue(name.to_owned()))?;
+            match value.parse::<f32>() {
+                Ok(number) if number >= min && number <= max => Ok(number),
+                _ => Err(ParseTransformError::InvalidValue {
+                    name: name.to_owned(),
+                    value: value.to_owned(),
+                }),
+            }
+        };
+
+        let transform = match name {
+            "flip-h" => Self::FlipHorizontal,
+            "flip-v" => Self::FlipVertical,
+            "invert" => Self::Invert,
+            "rotate" => match value {
+                Some("90") => Self::Rotate90,
+                Some("180") => Self::Rotate180,
+                Some("270") => Self::Rotate270,
+                Some(value) => {
+                    return Err(ParseTransformError::InvalidValue {
+                        name: name.to_owned(),
+                        value: value.to_owned(),
+                    })
+                }
+                None => return Err(ParseTransformError::MissingValue(name.to_owned())),
+            },
+            "hue" => Self::HueShift(number(-360.0, 360.0)?),
+            "scale" => Self::Scale(number(1.0 / MAX_SCALE, MAX_SCALE)?),
+            "opacity" => Self::Opacity(number(0.0, 1.0)?),
+            _ => return Err(ParseTransformError::UnknownTransform(name.to_owned())),
+        };
+
+        let takes_value = matches!(
+            transform,
+            Self::Rotate90
+                | Self::Rotate180
+                | Self::Rotate270
+                | Self::HueShift(_)
+                | Self::Scale(_)
+                | Self::Opacity(_)
+        );
+        if value.is_some() && !takes_value {
+            return Err(ParseTransformError::UnexpectedValue(name.to_owned()));
+        }
+        Ok(transform)
+    }
+}
+
+/// Transforms applied in order to every image of a cursor.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pipeline {
+    transforms: Vec<Transform>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a transform to the end of the pipeline.
+    pub fn then(mut self, transform: Transform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn apply(&self, cursor: &AnimatedCursor) -> AnimatedCursor {
+        let mut cursor = cursor.clone();
+        for frame in &mut cursor.frames {
+            for entry in &mut frame.image.entries {
+                *entry = self.apply_entry(entry);
+            }
+        }
+        cursor
+    }
+
+    pub fn apply_entry(&self, entry: &CursorEntry) -> CursorEntry {
+        self.transforms
+            .iter()
+            .fold(entry.clone(), |entry, transform| transform.apply(&entry))
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = ParseTransformError;
+
+    /// Parses a comma separated list of transforms. An empty string is an empty pipeline.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|transform| !transform.trim().is_empty())
+            .try_fold(Self::new(), |pipeline, transform| {
+                Ok(pipeline.then(transform.parse()?))
+            })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseTransformError {
+    UnknownTransform(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue { name: String, value: String },
+}
+
+impl fmt::Display for ParseTransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownTransform(name) => write!(f, "unknown transform `{}`", name),
+            Self::MissingValue(name) => write!(f, "transform `{}` needs a value", name),
+            Self::UnexpectedValue(name) => write!(f, "transform `{}` takes no value", name),
+            Self::InvalidValue { name, value } => {
+                write!(f, "invalid value `{}` for transform `{}`", value, name)
+            }
+        }
+    }
+}
+
+impl Error for ParseTransformError {}
+
+/// Resizes an entry to exactly `width` by `height`, scaling the hotspot along with it.
+///
+/// Each axis is resampled with a triangle filter that widens when shrinking, so every source
+/// pixel contributes and thin lines don't vanish. Colours are weighted by alpha, which keeps
+/// the invisible colour of transparent pixels from bleeding into the edges.
+pub fn resize(entry: &CursorEntry, width: u32, height: u32) -> CursorEntry {
+    let premultiplied: Vec<f32> = entry
+        .pixels
+        .chunks(4)
+        .flat_map(|rgba| {
+            let alpha = f32::from(rgba[3]) / 255.0;
+            [
+                f32::from(rgba[0]) * alpha,
+                f32::from(rgba[1]) * alpha,
+                f32::from(rgba[2]) * alpha,
+                f32::from(rgba[3]),
+            ]
+        })
+        .collect();
+
+    let columns = filter_weights(entry.width, width);
+    let rows = filter_weights(entry.height, height);
+
+    // Horizontal pass into an intermediate `width` by `entry.height` image
+    let mut horizontal = vec![0f32; width as usize * entry.height as usize * 4];
+    for y in 0..entry.height as usize {
+        for (x, weights) in columns.iter().enumerate() {
+            let out = (y * width as usize + x) * 4;
+            for &(source, weight) in weights {
+                let src = (y * entry.width as usize + source) * 4;
+                for channel in 0..4 {
+                    horizontal[out + channel] += premultiplied[src + channel] * weight;
+                }
+            }
+        }
+    }
+
+    let hotspot = Hotspot::new(
+        scale_coordinate(entry.hotspot.x, entry.width, width),
+        scale_coordinate(entry.hotspot.y, entry.height, height),
+    );
+    let mut resized = CursorEntry::new(width, height, hotspot);
+    resized.bit_count = entry.bit_count;
+    for (y, weights) in rows.iter().enumerate() {
+        for x in 0..width as usize {
+            let mut sum = [0f32; 4];
+            for &(source, weight) in weights {
+                let src = (source * width as usize + x) * 4;
+                for channel in 0..4 {
+                    sum[channel] += horizontal[src + channel] * weight;
+                }
+            }
+            let alpha = sum[3].clamp(0.0, 255.0);
+            let unpremultiply = |value: f32| {
+                if alpha > 0.0 {
+                    (value * 255.0 / alpha).round().clamp(0.0, 255.0) as u8
+                } else {
+                    0
+                }
+            };
+            let rgba = [
+                unpremultiply(sum[0]),
+                unpremultiply(sum[1]),
+                unpremultiply(sum[2]),
+                alpha.round() as u8,
+            ];
+            resized.set_pixel(x as u32, y as u32, rgba);
+        }
+    }
+
+    resized
+}
+
+/// The entry of `image` to show at `size` pixels: the one made for that size if there is one,
+/// otherwise the next larger one scaled down, or the largest scaled up if they are all smaller.
+/// Shrinking loses less than enlarging, so larger entries are preferred over closer ones.
+pub fn fit(image: &CursorImage, size: u32) -> Option<CursorEntry> {
+    let source = image
+        .entries
+        .iter()
+        .filter(|entry| entry.nominal_size >= size)
+        .min_by_key(|entry| entry.nominal_size)
+        .or_else(|| image.entries.iter().max_by_key(|entry| entry.nominal_size))?;
+    if source.nominal_size == size {
+        return Some(source.clone());
+    }
+
+    let nominal_size = u64::from(source.nominal_size.max(1));
+    let scaled = |length: u32| {
+        ((u64::from(length) * u64::from(size) + nominal_size / 2) / nominal_size).max(1) as u32
+    };
+    let mut entry = resize(source, scaled(source.width), scaled(source.height));
+    entry.nominal_size = size;
+    Some(entry)
+}
+
+/// A cursor image with an entry for each of `sizes`, each fitted from the entries of `image`.
+pub fn multi_size(image: &CursorImage, sizes: &[u32]) -> CursorImage {
+    CursorImage::new(sizes.iter().filter_map(|&size| fit(image, size)).collect())
+}
+
+/// For each destination pixel along an axis, the source pixels that contribute to it and how
+/// much, summing to one.
+fn filter_weights(source_len: u32, dest_len: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = dest_len as f32 / source_len as f32;
+    let radius = (1.0 / scale).max(1.0);
+
+    (0..dest_len)
+        .map(|dest| {
+            let center = (dest as f32 + 0.5) / scale;
+            let first = (center - radius).floor().max(0.0) as usize;
+            let last = ((center + radius).ceil() as usize).min(source_len as usize);
+            let mut weights: Vec<(usize, f32)> = (first..last)
+                .map(|source| {
+                    let distance = (source as f32 + 0.5 - center).abs() / radius;
+                    (source, (1.0 - distance).max(0.0))
+                })
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect();
+            let total: f32 = weights.iter().map(|&(_, weight)| weight).sum();
+            if total > 0.0 {
+                weights.iter_mut().for_each(|(_, weight)| *weight /= total);
+            } else {
+                let nearest = (center as usize).min(source_len as usize - 1);
+                weights = vec![(nearest, 1.0)];
+            }
+            weights
+        })
+        .collect()
+}
+
+/// Moves a coordinate to the pixel covering the same spot after resizing.
+fn scale_coordinate(coordinate: u16, source_len: u32, dest_len: u32) -> u16 {
+    let scaled = (f32::from(coordinate) + 0.5) * dest_len as f32 / source_len as f32;
+    (scaled as u32).min(dest_len - 1) as u16
+}
+
+/// Builds a `width` by `height` entry whose pixel at (x, y) in the original ends up at
+/// `position(x, y)`, taking the hotspot along.
+fn remap<F>(entry: &CursorEntry, width: u32, height: u32, position: F) -> CursorEntry
+where
+    F: Fn(u32, u32) -> (u32, u32),
+{
+    // A hotspot off the image, which some formats allow, moves to its nearest pixel first.
+    let (hot_x, hot_y) = position(
+        u32::from(entry.hotspot.x).min(entry.width - 1),
+        u32::from(entry.hotspot.y).min(entry.height - 1),
+    );
+    let mut remapped = CursorEntry::new(width, height, Hotspot::new(hot_x as u16, hot_y as u16));
+    remapped.nominal_size = entry.nominal_size;
+    remapped.bit_count = entry.bit_count;
+    for y in 0..entry.height {
+        for x in 0..entry.width {
+            let (to_x, to_y) = position(x, y);
+            let index = (to_y * width + to_x) as usize;
+            remapped.pixels[index * 4..index * 4 + 4].copy_from_slice(&entry.pixel(x, y));
+            remapped.and_mask[index] = entry.and_mask[(y * entry.width + x) as usize];
+        }
+    }
+    remapped
+}
+
+fn map_pixels<F>(entry: &CursorEntry, f: F) -> CursorEntry
+where
+    F: Fn([u8; 4]) -> [u8; 4],
+{
+    let mut mapped = entry.clone();
+    for y in 0..entry.height {
+        for x in 0..entry.width {
+            // Pixels that invert the screen have no colour of their own to change.
+            if !entry.is_inverted(x, y) {
+                mapped.set_pixel(x, y, f(entry.pixel(x, y)));
+            }
+        }
+    }
+    mapped
+}
+
+/// Rotates the hue of a colour through HSV space, leaving saturation, value and alpha alone.
+fn shift_hue(rgba: [u8; 4], degrees: f32) -> [u8; 4] {
+    let [r, g, b, a] = rgba;
+    let (r, g, b) = (
+        f32::from(r) / 255.0,
+        f32::from(g) / 255.0,
+        f32::from(b) / 255.0,
+    );
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    // Greys have no hue to shift.
+    if chroma == 0.0 {
+        return rgba;
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    let hue = (hue + degrees).rem_euclid(360.0);
+
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |value: f32| ((value + min) * 255.0).round().clamp(0.0, 255.0) as u8;
+    [channel(r), channel(g), channel(b), a]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEOMETRIC: [Transform; 5] = [
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+    ];
+
+    #[test]
+    fn hotspot_moves_with_the_image() {
+        let entry = CursorEntry::new(4, 3, Hotspot::new(1, 0));
+        let moved: Vec<Hotspot> = GEOMETRIC
+            .iter()
+            .map(|transform| transform.apply(&entry).hotspot)
+            .collect();
+        assert_eq!(
+            moved,
+            vec![
+                Hotspot::new(2, 0),
+                Hotspot::new(1, 2),
+                Hotspot::new(2, 1),
+                Hotspot::new(2, 2),
+                Hotspot::new(0, 2),
+            ]
+        );
+    }
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> CursorEntry {
+        let mut entry = CursorEntry::new(width, height, Hotspot::new(1, 1));
+        for y in 0..height {
+            for x in 0..width {
+                entry.set_pixel(x, y, rgba);
+            }
+        }
+        entry
+    }
+
+    #[test]
+    fn invert_leaves_alpha_and_inverting_pixels_alone() {
+        let mut entry = solid(2, 1, [10, 20, 30, 200]);
+        entry.set_inverted(1, 0);
+        let inverted = Transform::Invert.apply(&entry);
+        assert_eq!(inverted.pixel(0, 0), [245, 235, 225, 200]);
+        assert_eq!(inverted.pixel(1, 0), entry.pixel(1, 0));
+        assert!(inverted.is_inverted(1, 0));
+    }
+
+    #[test]
+    fn hue_turns_colours_but_not_greys() {
+        let red = solid(1, 1, [255, 0, 0, 128]);
+        assert_eq!(
+            Transform::HueShift(120.0).apply(&red).pixel(0, 0),
+            [0, 255, 0, 128]
+        );
+        assert_eq!(
+            Transform::HueShift(-120.0).apply(&red).pixel(0, 0),
+            [0, 0, 255, 128]
+        );
+        assert_eq!(
+            Transform::HueShift(360.0).apply(&red).pixel(0, 0),
+            [255, 0, 0, 128]
+        );
+        let grey = solid(1, 1, [90, 90, 90, 255]);
+        assert_eq!(Transform::HueShift(45.0).apply(&grey), grey);
+    }
+
+    #[test]
+    fn opacity_scales_alpha() {
+        let entry = solid(1, 1, [10, 20, 30, 200]);
+        assert_eq!(
+            Transform::Opacity(0.5).apply(&entry).pixel(0, 0),
+            [10, 20, 30, 100]
+        );
+        assert_eq!(Transform::Opacity(0.0).apply(&entry).pixel(0, 0)[3], 0);
+        assert_eq!(Transform::Opacity(1.0).apply(&entry), entry);
+    }
+
+    #[test]
+    fn scale_resizes_pixels_and_hotspot() {
+        let entry = solid(2, 2, [200, 100, 50, 255]);
+        let larger = Transform::Scale(2.0).apply(&entry);
+        assert_eq!((larger.width, larger.height), (4, 4));
+        assert_eq!(larger.nominal_size, entry.nominal_size);
+        assert_eq!(larger.hotspot, Hotspot::new(3, 3));
+        assert!(larger
+            .pixels
+            .chunks(4)
+            .all(|rgba| rgba == [200, 100, 50, 255]));
+
+        let smaller = Transform::Scale(0.5).apply(&solid(4, 4, [200, 100, 50, 255]));
+        assert_eq!((smaller.width, smaller.height), (2, 2));
+        assert!(smaller
+            .pixels
+            .chunks(4)
+            .all(|rgba| rgba == [200, 100, 50, 255]));
+        // Nothing shrinks below a pixel.
+        let tiny = Transform::Scale(1.0 / 16.0).apply(&entry);
+        assert_eq!((tiny.width, tiny.height), (1, 1));
+    }
+
+    #[test]
+    fn parses_pipelines() {
+        assert_eq!("".parse(), Ok(Pipeline::new()));
+        assert_eq!(
+            " flip-h, ,rotate = 90,hue=-30,scale=1.5,opacity=0.25,invert".parse(),
+            Ok(Pipeline::new()
+                .then(Transform::FlipHorizontal)
+                .then(Transform::Rotate90)
+                .then(Transform::HueShift(-30.0))
+                .then(Transform::Scale(1.5))
+                .then(Transform::Opacity(0.25))
+                .then(Transform::Invert))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_transforms() {
+        let invalid = |name: &str, value: &str| ParseTransformError::InvalidValue {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        };
+        let cases = [
+            (
+                "flip-h,spin",
+                ParseTransformError::UnknownTransform("spin".to_owned()),
+            ),
+            ("hue", ParseTransformError::MissingValue("hue".to_owned())),
+            (
+                "rotate",
+                ParseTransformError::MissingValue("rotate".to_owned()),
+            ),
+            (
+                "invert=1",
+                ParseTransformError::UnexpectedValue("invert".to_owned()),
+            ),
+            ("rotate=45", invalid("rotate", "45")),
+            ("hue=400", invalid("hue", "400")),
+            ("scale=17", invalid("scale", "17")),
+            ("scale=0", invalid("scale", "0")),
+            ("opacity=1.5", invalid("opacity", "1.5")),
+            ("opacity=half", invalid("opacity", "half")),
+        ];
+        for (pipeline, error) in &cases {
+            assert_eq!(
+                pipeline.parse::<Pipeline>().as_ref(),
+                Err(error),
+                "{}",
+                pipeline
+            );
+        }
+        assert_eq!(
+            cases[4].1.to_string(),
+            "invalid value `45` for transform `rotate`"
+        );
+    }
+
+    #[test]
+    fn scaled_cursor_stays_scaled_once_fitted() {
+        let image = crate::format::cur::decode(crate::cursor::DEFAULT_CURSOR).unwrap();
+        for &(factor, expected) in &[(2.0, 64), (0.5, 16)] {
+            let scaled = CursorImage::new(vec![Transform::Scale(factor).apply(&image.entries[0])]);
+            let fitted = fit(&scaled, 32).unwrap();
+            assert_eq!((fitted.width, fitted.height), (expected, expected));
+            // At a larger display size it grows in proportion, staying scaled.
+            let fitted = fit(&scaled, 48).unwrap();
+            assert_eq!(fitted.width, expected * 3 / 2);
+        }
+    }
+
+    #[test]
+    fn hotspot_off_the_image_is_clamped() {
+        for hotspot in &[Hotspot::new(4, 3), Hotspot::new(u16::MAX, u16::MAX)] {
+            let entry = CursorEntry::new(4, 3, *hotspot);
+            let clamped = CursorEntry::new(4, 3, Hotspot::new(3, 2));
+            for transform in &GEOMETRIC {
+                assert_eq!(
+                    transform.apply(&entry).hotspot,
+                    transform.apply(&clamped).hotspot
+                );
+            }
+        }
+    }
+}
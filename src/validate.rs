@@ -0,0 +1,221 @@
+//! Checking cursor files before they are deployed, for people and for scripts.
+
+use std::{
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::format::{self, Diagnostic, Severity};
+
+/// What was found in one file
+#[derive(Debug)]
+pub struct Report {
+    pub path: PathBuf,
+    /// The problems found, or why the file could not be read at all
+    pub result: Result<Vec<Diagnostic>, io::Error>,
+}
+
+impl Report {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            result: fs::read(path).map(|bytes| format::lint(&bytes)),
+        }
+    }
+
+    /// Whether the file can be used, warnings and all
+    pub fn is_valid(&self) -> bool {
+        match &self.result {
+            Ok(diagnostics) => diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.severity != Severity::Error),
+            Err(_) => false,
+        }
+    }
+
+    /// The report as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"file\":{},\"valid\":{}",
+            json_string(&self.path.to_string_lossy()),
+            self.is_valid()
+        );
+        match &self.result {
+            Ok(diagnostics) => {
+                json.push_str(",\"diagnostics\":[");
+                for (index, diagnostic) in diagnostics.iter().enumerate() {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    let _ = write!(
+                        json,
+                        "{{\"severity\":\"{}\",\"offset\":{},\"message\":{}}}",
+                        diagnostic.severity.as_str(),
+                        diagnostic.offset,
+                        json_string(&diagnostic.message)
+                    );
+                }
+                json.push(']');
+            }
+            Err(err) => {
+                let _ = write!(json, ",\"error\":{}", json_string(&err.to_string()));
+            }
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// One line per problem, or a single line saying the file is fine.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.display();
+        match &self.result {
+            Ok(diagnostics) if diagnostics.is_empty() => writeln!(f, "{}: ok", path),
+            Ok(diagnostics) => diagnostics.iter().try_for_each(|diagnostic| {
+                writeln!(
+                    f,
+                    "{}: {} at byte {} (0x{:x}): {}",
+                    path,
+                    diagnostic.severity.as_str(),
+                    diagnostic.offset,
+                    diagnostic.offset,
+                    diagnostic.message
+                )
+            }),
+            Err(err) => writeln!(f, "{}: error: could not read the file: {}", path, err),
+        }
+    }
+}
+
+/// Reports for several files as a JSON array.
+pub fn to_json(reports: &[Report]) -> String {
+    let objects: Vec<String> = reports.iter().map(Report::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(path: &str, diagnostics: Vec<Diagnostic>) -> Report {
+        Report {
+            path: PathBuf::from(path),
+            result: Ok(diagnostics),
+        }
+    }
+
+    fn diagnostic(severity: Severity, offset: usize, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity,
+            offset,
+            message: message.to_owned(),
+        }
+    }
+
+    fn unreadable(path: &str) -> Report {
+        Report {
+            path: PathBuf::from(path),
+            result: Err(io::Error::new(io::ErrorKind::NotFound, "no such file")),
+        }
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(
+            json_string("a \"b\" c:\\d\ne\rf\tg\u{1}h\u{7f}é"),
+            "\"a \\\"b\\\" c:\\\\d\\ne\\rf\\tg\\u0001h\u{7f}é\""
+        );
+    }
+
+    #[test]
+    fn json_snapshot() {
+        let reports = [
+            report("ok.cur", vec![]),
+            report(
+                "bad \"one\".ani",
+                vec![
+                    diagnostic(
+                        Severity::Warning,
+                        6,
+                        "reserved field of image 0 is not zero",
+                    ),
+                    diagnostic(Severity::Error, 36, "unsupported bit depth 24 in image 0"),
+                ],
+            ),
+            unreadable("gone.cur"),
+        ];
+        assert_eq!(
+            to_json(&reports),
+            concat!(
+                r#"[{"file":"ok.cur","valid":true,"diagnostics":[]},"#,
+                r#"{"file":"bad \"one\".ani","valid":false,"diagnostics":["#,
+                r#"{"severity":"warning","offset":6,"message":"reserved field of image 0 is not zero"},"#,
+                r#"{"severity":"error","offset":36,"message":"unsupported bit depth 24 in image 0"}]},"#,
+                r#"{"file":"gone.cur","valid":false,"error":"no such file"}]"#,
+            )
+        );
+        assert_eq!(to_json(&[]), "[]");
+    }
+
+    #[test]
+    fn warnings_alone_leave_a_file_valid() {
+        let warned = report("a.cur", vec![diagnostic(Severity::Warning, 0, "odd")]);
+        assert!(warned.is_valid());
+        let failed = report("a.cur", vec![diagnostic(Severity::Error, 0, "broken")]);
+        assert!(!failed.is_valid());
+        assert!(!unreadable("a.cur").is_valid());
+    }
+
+    #[test]
+    fn prints_a_line_per_problem() {
+        assert_eq!(report("ok.cur", vec![]).to_string(), "ok.cur: ok\n");
+        let reported = report(
+            "bad.cur",
+            vec![
+                diagnostic(Severity::Error, 4, "cursor contains no images"),
+                diagnostic(Severity::Warning, 255, "odd"),
+            ],
+        );
+        assert_eq!(
+            reported.to_string(),
+            "bad.cur: error at byte 4 (0x4): cursor contains no images\n\
+             bad.cur: warning at byte 255 (0xff): odd\n"
+        );
+        assert_eq!(
+            unreadable("gone.cur").to_string(),
+            "gone.cur: error: could not read the file: no such file\n"
+        );
+    }
+
+    #[test]
+    fn reads_and_lints_files() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("normal.cur");
+        let report = Report::new(&path);
+        assert!(report.is_valid());
+        assert!(report.result.unwrap().is_empty());
+        assert!(Report::new(&path.with_extension("missing")).result.is_err());
+    }
+}